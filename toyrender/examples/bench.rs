@@ -0,0 +1,34 @@
+//! Frame time of the african_head scene of `toyrender::demo` without a window.
+//!
+//! Run from the `toyrender` directory with
+//! `cargo run --release --example bench [THREADS] [FRAMES]`, one thread by default.
+
+extern crate toyrender;
+
+use std::env;
+use std::time::Instant;
+
+use toyrender::demo;
+
+const SIZE: usize = 800;
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse::<usize>().expect("not a number"));
+    let threads = args.next().unwrap_or(1);
+    let frames = args.next().unwrap_or(30);
+
+    let model = demo::load().unwrap();
+    let mut canvas = demo::canvas(SIZE, SIZE);
+    canvas.set_threads(threads);
+
+    // the first frame warms up the caches and is not counted
+    demo::render(&mut canvas, &model);
+    let start = Instant::now();
+    for _ in 0..frames {
+        canvas.clear();
+        demo::render(&mut canvas, &model);
+    }
+    let elapsed = start.elapsed();
+    let ms = elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1e6;
+    println!("{}x{}, {} thread(s): {:.2} ms per frame", SIZE, SIZE, threads, ms / frames as f64);
+}
@@ -0,0 +1,549 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::result;
+
+use color::Rgba8;
+use image::ImageDecoder;
+use pixmap::Pixmap;
+use tgaimage::{ImageLoader, ImageWriter, Result};
+
+pub type BmpResult<T> = result::Result<T, BmpError>;
+
+/// Describes why a BMP file could not be decoded.
+#[derive(Debug)]
+pub enum BmpError {
+    Io(io::Error),
+    /// The data ended before the whole image was read.
+    Truncated,
+    /// The file does not start with `BM`.
+    InvalidSignature,
+    /// Size of the info header is not one of the known versions.
+    UnsupportedHeader(u32),
+    UnsupportedBitCount(u16),
+    /// Compression other than none or bit fields, run length encoding is not supported.
+    UnsupportedCompression(u32),
+    /// A pixel refers to a palette entry which does not exist.
+    ColorIndexOutOfRange(usize),
+    /// The pixel data starts inside of the headers.
+    InvalidDataOffset(u32),
+    /// Width or height is zero or too large.
+    InvalidSize {
+        width: i32,
+        height: i32,
+    },
+}
+
+impl fmt::Display for BmpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BmpError::Io(ref err) => write!(f, "bmp: {}", err),
+            BmpError::Truncated => write!(f, "bmp: unexpected end of image data"),
+            BmpError::InvalidSignature => write!(f, "bmp: missing BM signature"),
+            BmpError::UnsupportedHeader(size) => {
+                write!(f, "bmp: unsupported info header of {} bytes", size)
+            }
+            BmpError::UnsupportedBitCount(bits) => {
+                write!(f, "bmp: {} bits per pixel are not supported", bits)
+            }
+            BmpError::UnsupportedCompression(compression) => {
+                write!(f, "bmp: unsupported compression {}", compression)
+            }
+            BmpError::ColorIndexOutOfRange(index) => {
+                write!(f, "bmp: color index {} is out of the palette", index)
+            }
+            BmpError::InvalidDataOffset(offset) => {
+                write!(f, "bmp: pixel data offset {} points into the header", offset)
+            }
+            BmpError::InvalidSize { width, height } => {
+                write!(f, "bmp: invalid image size {}x{}", width, height)
+            }
+        }
+    }
+}
+
+impl error::Error for BmpError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BmpError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BmpError {
+    fn from(err: io::Error) -> BmpError {
+        match err.kind() {
+            ErrorKind::UnexpectedEof => BmpError::Truncated,
+            _ => BmpError::Io(err),
+        }
+    }
+}
+
+impl From<BmpError> for io::Error {
+    fn from(err: BmpError) -> io::Error {
+        match err {
+            BmpError::Io(err) => err,
+            BmpError::Truncated => Error::new(ErrorKind::UnexpectedEof, err),
+            err => Error::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+const FILE_HEADER_SIZE: u32 = 14;
+const CORE_HEADER_SIZE: u32 = 12;
+const INFO_HEADER_SIZE: u32 = 40;
+const V4_HEADER_SIZE: u32 = 108;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+    bytes[i] as u16 | (bytes[i + 1] as u16) << 8
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u16_at(bytes, i) as u32 | (u16_at(bytes, i + 2) as u32) << 16
+}
+
+/// Channel of a 16 or 32 bit pixel, `mask` selects its bits.
+#[derive(Copy, Clone, Debug)]
+struct BitField {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl BitField {
+    fn new(mask: u32) -> BitField {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        BitField {
+            mask: mask,
+            shift: shift,
+            max: mask >> shift,
+        }
+    }
+
+    /// Channel scaled to 8 bits, `missing` if the mask is empty.
+    fn extract(&self, pixel: u32, missing: u8) -> u8 {
+        if self.max == 0 {
+            return missing;
+        }
+        let v = ((pixel & self.mask) >> self.shift) as u64;
+        ((v * 255 + self.max as u64 / 2) / self.max as u64) as u8
+    }
+}
+
+struct BmpHeader {
+    data_offset: u32,
+    header_size: u32,
+    width: i32,
+    height: i32,
+    bitcount: u16,
+    compression: u32,
+    colors_used: u32,
+    /// Red, green, blue and alpha masks if the header holds them.
+    masks: Option<[u32; 4]>,
+}
+
+/// BMP decoder for palette, 16, 24 and 32 bit images without compression or with
+/// bit fields. The resulting pixmap is oriented with row zero at the top.
+pub struct BmpImage;
+
+impl BmpImage {
+    /// Same as `ImageLoader::load`, but reports exactly what was wrong with the file.
+    pub fn open(path: &str) -> BmpResult<Pixmap> {
+        let file = try!(File::open(&Path::new(path)));
+        Self::read(&mut BufReader::new(file))
+    }
+
+    pub fn read<R: Read>(inp: &mut R) -> BmpResult<Pixmap> {
+        let header = try!(Self::read_header(inp));
+        let mut consumed = FILE_HEADER_SIZE + header.header_size;
+
+        let (width, height) = (header.width, header.height);
+        // rows are stored from the bottom up unless the height is negative
+        let top_down = height < 0;
+        let h = height.checked_abs().unwrap_or(0);
+        if width <= 0 || h == 0 || width > 1 << 16 || h > 1 << 16 {
+            return Err(BmpError::InvalidSize {
+                width: width,
+                height: height,
+            });
+        }
+        let (w, h) = (width as usize, h as usize);
+
+        let bitcount = header.bitcount;
+        match (bitcount, header.compression) {
+            (1, BI_RGB) | (4, BI_RGB) | (8, BI_RGB) | (24, BI_RGB) => {}
+            (16, BI_RGB) | (32, BI_RGB) => {}
+            (16, BI_BITFIELDS) | (32, BI_BITFIELDS) => {}
+            (16, BI_ALPHABITFIELDS) | (32, BI_ALPHABITFIELDS) => {}
+            (1, _) | (4, _) | (8, _) | (16, _) | (24, _) | (32, _) => {
+                return Err(BmpError::UnsupportedCompression(header.compression))
+            }
+            _ => return Err(BmpError::UnsupportedBitCount(bitcount)),
+        }
+
+        // masks of an info header follow it
+        let masks = match header.masks {
+            Some(masks) => masks,
+            None if header.compression == BI_RGB => {
+                match bitcount {
+                    16 => [0x7c00, 0x03e0, 0x001f, 0],
+                    _ => [0xff0000, 0x00ff00, 0x0000ff, 0],
+                }
+            }
+            None => {
+                let count = if header.compression == BI_ALPHABITFIELDS { 4 } else { 3 };
+                let mut bytes = [0u8; 16];
+                try!(inp.read_exact(&mut bytes[..count * 4]));
+                consumed += count as u32 * 4;
+                [u32_at(&bytes, 0), u32_at(&bytes, 4), u32_at(&bytes, 8), u32_at(&bytes, 12)]
+            }
+        };
+        let fields = [BitField::new(masks[0]),
+                      BitField::new(masks[1]),
+                      BitField::new(masks[2]),
+                      BitField::new(masks[3])];
+
+        let mut palette = Vec::new();
+        if bitcount <= 8 {
+            let entry_size = if header.header_size == CORE_HEADER_SIZE { 3 } else { 4 };
+            let count = match header.colors_used {
+                0 => 1 << bitcount,
+                n => n.min(256) as usize,
+            };
+            let mut bytes = Vec::new();
+            try!(inp.by_ref().take((count * entry_size) as u64).read_to_end(&mut bytes));
+            if bytes.len() < count * entry_size {
+                return Err(BmpError::Truncated);
+            }
+            palette = bytes.chunks(entry_size).map(|c| Rgba8::rgb(c[2], c[1], c[0])).collect();
+            consumed += (count * entry_size) as u32;
+        }
+
+        if header.data_offset < consumed {
+            return Err(BmpError::InvalidDataOffset(header.data_offset));
+        }
+        let gap = (header.data_offset - consumed) as u64;
+        if try!(io::copy(&mut inp.by_ref().take(gap), &mut io::sink())) < gap {
+            return Err(BmpError::Truncated);
+        }
+
+        // rows are padded to multiples of four bytes. The buffer grows while reading,
+        // so a bogus header can not force a huge allocation.
+        let stride = (w * bitcount as usize + 31) / 32 * 4;
+        let size = stride as u64 * h as u64;
+        let mut data = Vec::new();
+        try!(inp.by_ref().take(size).read_to_end(&mut data));
+        if (data.len() as u64) < size {
+            return Err(BmpError::Truncated);
+        }
+
+        let mut pixmap = Pixmap::new(w, h, Rgba8::default());
+        for (i, row) in data.chunks(stride).enumerate() {
+            let y = if top_down { i } else { h - 1 - i };
+            let out = pixmap.row_mut(y);
+
+            match bitcount {
+                1 | 4 | 8 => {
+                    let bits = bitcount as usize;
+                    let per_byte = 8 / bits;
+                    for (x, pixel) in out.iter_mut().enumerate() {
+                        let byte = row[x / per_byte];
+                        let shift = 8 - bits * (x % per_byte + 1);
+                        let index = (byte >> shift) as usize & ((1 << bits) - 1);
+                        *pixel = match palette.get(index) {
+                            Some(&color) => color,
+                            None => return Err(BmpError::ColorIndexOutOfRange(index)),
+                        };
+                    }
+                }
+                24 => {
+                    for (pixel, c) in out.iter_mut().zip(row.chunks(3)) {
+                        *pixel = Rgba8::rgb(c[2], c[1], c[0]);
+                    }
+                }
+                _ => {
+                    let bytes = bitcount as usize / 8;
+                    for (pixel, c) in out.iter_mut().zip(row.chunks(bytes)) {
+                        let v = if bytes == 2 { u16_at(c, 0) as u32 } else { u32_at(c, 0) };
+                        *pixel = Rgba8::new(fields[0].extract(v, 0),
+                                            fields[1].extract(v, 0),
+                                            fields[2].extract(v, 0),
+                                            fields[3].extract(v, 255));
+                    }
+                }
+            }
+        }
+
+        Ok(pixmap)
+    }
+
+    fn read_header<R: Read>(inp: &mut R) -> BmpResult<BmpHeader> {
+        let mut file_header = [0u8; FILE_HEADER_SIZE as usize + 4];
+        try!(inp.read_exact(&mut file_header));
+        if &file_header[..2] != b"BM" {
+            return Err(BmpError::InvalidSignature);
+        }
+
+        let header_size = u32_at(&file_header, 14);
+        match header_size {
+            CORE_HEADER_SIZE | INFO_HEADER_SIZE | 52 | 56 | V4_HEADER_SIZE | 124 => {}
+            size => return Err(BmpError::UnsupportedHeader(size)),
+        }
+        let mut info = vec![0u8; header_size as usize];
+        try!(inp.read_exact(&mut info[4..]));
+
+        let mut header = BmpHeader {
+            data_offset: u32_at(&file_header, 10),
+            header_size: header_size,
+            width: 0,
+            height: 0,
+            bitcount: 0,
+            compression: BI_RGB,
+            colors_used: 0,
+            masks: None,
+        };
+        if header_size == CORE_HEADER_SIZE {
+            header.width = u16_at(&info, 4) as i32;
+            header.height = u16_at(&info, 6) as i32;
+            header.bitcount = u16_at(&info, 10);
+            return Ok(header);
+        }
+
+        header.width = u32_at(&info, 4) as i32;
+        header.height = u32_at(&info, 8) as i32;
+        header.bitcount = u16_at(&info, 14);
+        header.compression = u32_at(&info, 16);
+        header.colors_used = u32_at(&info, 32);
+        // later versions of the header hold the masks themselves
+        let bitfields = header.compression == BI_BITFIELDS ||
+                        header.compression == BI_ALPHABITFIELDS;
+        if header_size > INFO_HEADER_SIZE && bitfields {
+            let alpha = if header_size >= 56 { u32_at(&info, 52) } else { 0 };
+            header.masks = Some([u32_at(&info, 40), u32_at(&info, 44), u32_at(&info, 48), alpha]);
+        }
+        Ok(header)
+    }
+}
+
+impl ImageLoader for BmpImage {
+    fn read_from<R: BufRead>(inp: &mut R) -> Result<Pixmap> {
+        Self::read(inp).map_err(io::Error::from)
+    }
+}
+
+impl ImageDecoder for BmpImage {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["bmp", "dib"]
+    }
+
+    fn detect(&self, header: &[u8]) -> bool {
+        header.len() >= 10 && &header[..2] == b"BM" && u32_at(header, 6) == 0
+    }
+
+    fn decode(&self, mut inp: &mut dyn BufRead) -> Result<Pixmap> {
+        Self::read(&mut inp).map_err(io::Error::from)
+    }
+}
+
+/// BMP encoder, 24 bit or 32 bit with an alpha channel. Rows are stored from the
+/// bottom up like most programs expect.
+#[derive(Copy, Clone, Debug)]
+pub struct BmpWriter {
+    pub alpha: bool,
+}
+
+impl Default for BmpWriter {
+    fn default() -> BmpWriter {
+        BmpWriter { alpha: false }
+    }
+}
+
+impl BmpWriter {
+    pub fn new(alpha: bool) -> BmpWriter {
+        BmpWriter { alpha: alpha }
+    }
+
+    pub fn write<W: Write>(&self, pixmap: &Pixmap, out: &mut W) -> Result<()> {
+        let (w, h) = (pixmap.width(), pixmap.height());
+        if w > i32::max_value() as usize / 4 || h > i32::max_value() as usize / 4 {
+            return Err(Error::new(ErrorKind::InvalidInput, "image is too large for bmp"));
+        }
+
+        // an alpha channel needs the masks of a version 4 header
+        let (bitcount, header_size) = if self.alpha {
+            (32, V4_HEADER_SIZE)
+        } else {
+            (24, INFO_HEADER_SIZE)
+        };
+        let stride = (w * bitcount + 31) / 32 * 4;
+        let data_offset = FILE_HEADER_SIZE + header_size;
+        let file_size = data_offset as u64 + (stride * h) as u64;
+        if file_size > u32::max_value() as u64 {
+            return Err(Error::new(ErrorKind::InvalidInput, "image is too large for bmp"));
+        }
+
+        let mut header = Vec::with_capacity(data_offset as usize);
+        let push_u32 = |header: &mut Vec<u8>, v: u32| {
+            header.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+        };
+        header.extend_from_slice(b"BM");
+        push_u32(&mut header, file_size as u32);
+        push_u32(&mut header, 0);
+        push_u32(&mut header, data_offset);
+
+        push_u32(&mut header, header_size);
+        push_u32(&mut header, w as u32);
+        push_u32(&mut header, h as u32);
+        header.extend_from_slice(&[1, 0, bitcount as u8, 0]);
+        push_u32(&mut header, if self.alpha { BI_BITFIELDS } else { BI_RGB });
+        push_u32(&mut header, (stride * h) as u32);
+        // 72 dpi, no palette
+        for &v in &[2835, 2835, 0, 0] {
+            push_u32(&mut header, v);
+        }
+        if self.alpha {
+            for &mask in &[0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000] {
+                push_u32(&mut header, mask);
+            }
+            // "sRGB" color space, its end points and gamma are unused
+            push_u32(&mut header, 0x73524742);
+            header.extend_from_slice(&[0; 48]);
+        }
+        try!(out.write_all(&header));
+
+        let mut line = Vec::with_capacity(stride);
+        for y in (0..h).rev() {
+            line.clear();
+            for c in pixmap.row(y) {
+                line.extend_from_slice(&[c.b, c.g, c.r]);
+                if self.alpha {
+                    line.push(c.a);
+                }
+            }
+            line.resize(stride, 0);
+            try!(out.write_all(&line));
+        }
+        Ok(())
+    }
+}
+
+impl ImageWriter for BmpWriter {
+    fn save(&self, pixmap: &Pixmap, path: &str) -> Result<()> {
+        let mut file = BufWriter::new(try!(File::create(&Path::new(path))));
+        try!(self.write(pixmap, &mut file));
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BmpError, BmpImage, BmpWriter};
+    use color::Rgba8;
+    use pixmap::Pixmap;
+
+    fn decode(bytes: &[u8]) -> Pixmap {
+        BmpImage::read(&mut &bytes[..]).unwrap()
+    }
+
+    /// File and info header of an uncompressed image, the pixels follow directly.
+    fn header(width: i32, height: i32, bitcount: u16, palette: &[[u8; 4]]) -> Vec<u8> {
+        let offset = 14 + 40 + palette.len() as u32 * 4;
+        let mut bytes = b"BM".to_vec();
+        for &v in &[0, 0, offset, 40, width as u32, height as u32] {
+            bytes.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+        }
+        bytes.extend_from_slice(&[1, 0, bitcount as u8, 0]);
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, palette.len() as u8, 0, 0, 0, 0, 0, 0, 0]);
+        for entry in palette {
+            bytes.extend_from_slice(entry);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut pixmap = Pixmap::new(5, 3, Rgba8::default());
+        for y in 0..3 {
+            for x in 0..5 {
+                pixmap[(x, y)] = Rgba8::new((x * 50) as u8, (y * 80) as u8, 7, (x * 60) as u8);
+            }
+        }
+
+        for &alpha in &[false, true] {
+            let mut out = Vec::new();
+            BmpWriter::new(alpha).write(&pixmap, &mut out).unwrap();
+            let expected = if alpha {
+                pixmap.clone()
+            } else {
+                pixmap.map(|c| Rgba8::rgb(c.r, c.g, c.b))
+            };
+            assert!(decode(&out) == expected);
+        }
+    }
+
+    #[test]
+    fn test_palette_and_bottom_up() {
+        // two rows of 1 bit pixels, the white one is stored first as the bottom row
+        let mut bytes = header(3, 2, 1, &[[0, 0, 0, 0], [255, 255, 255, 0]]);
+        bytes.extend_from_slice(&[0b1010_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0]);
+        let pixmap = decode(&bytes);
+        let (b, w) = (Rgba8::rgb(0, 0, 0), Rgba8::rgb(255, 255, 255));
+        assert_eq!(pixmap.row(0), &[b, w, b]);
+        assert_eq!(pixmap.row(1), &[w, b, w]);
+
+        // 4 bit pixels, top down, an index past the palette
+        let mut bytes = header(2, -1, 4, &[[0, 0, 255, 0], [0, 255, 0, 0]]);
+        bytes.extend_from_slice(&[0x10, 0, 0, 0]);
+        assert_eq!(decode(&bytes).row(0), &[Rgba8::rgb(0, 255, 0), Rgba8::rgb(255, 0, 0)]);
+        let last = bytes.len() - 4;
+        bytes[last] = 0x20;
+        match BmpImage::read(&mut &bytes[..]) {
+            Err(BmpError::ColorIndexOutOfRange(2)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_16_bit() {
+        // 5-5-5 by default, the red channel is full and green half
+        let mut bytes = header(1, 1, 16, &[]);
+        bytes.extend_from_slice(&[0x00, 0x7e, 0, 0]);
+        assert_eq!(decode(&bytes).row(0), &[Rgba8::rgb(255, 0x84, 0)]);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let check = |bytes: &[u8], expected: &str| {
+            match BmpImage::read(&mut &bytes[..]) {
+                Err(err) => assert_eq!(format!("{:?}", err), expected),
+                Ok(_) => panic!("{:?} should not be decoded", bytes),
+            }
+        };
+
+        check(b"BM\0\0", "Truncated");
+        check(&header(1, 1, 24, &[])[..50], "Truncated");
+        check(&header(1, 1, 24, &[]), "Truncated");
+        // the header alone must not allocate 16 GiB of pixels
+        check(&header(1 << 16, 1 << 16, 32, &[]), "Truncated");
+        let mut bytes = header(1, 1, 24, &[]);
+        bytes[0] = b'X';
+        check(&bytes, "InvalidSignature");
+        check(&header(1, 1, 7, &[]), "UnsupportedBitCount(7)");
+        check(&header(0, 1, 24, &[]), "InvalidSize { width: 0, height: 1 }");
+        let mut bytes = header(1, 1, 8, &[]);
+        bytes[30] = 1;
+        check(&bytes, "UnsupportedCompression(1)");
+        let mut bytes = header(1, 1, 24, &[]);
+        bytes[10] = 20;
+        check(&bytes, "InvalidDataOffset(20)");
+    }
+}
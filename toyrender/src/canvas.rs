@@ -0,0 +1,720 @@
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use bmp::BmpWriter;
+use clip::{clip_segment, clip_triangle};
+use color::Rgba8;
+use depthbuffer::{DepthBuffer, DepthState};
+use linerasterizer::{ClipBox, LineCap, LineRasterizer, ThickLineRasterizer, WuLineRasterizer};
+use matrix::{Matrix4, Vec4f};
+use netpbm::{NetpbmFormat, NetpbmWriter};
+use pixmap::Pixmap;
+use png::PngWriter;
+use shader::{Derivatives, FragmentShader, Varying, VertexShader};
+use simd::F32x4;
+use tgaimage::{ImageWriter, Origin, TgaWriter};
+use trianglerasterizer::{RasterizerState, TriangleRasterizer, Winding, GUARD_BAND};
+use vector3d::{Vec3f, Vec3i};
+
+pub type Result<T> = io::Result<T>;
+
+/// Receives finished frames from a `Canvas`, e.g. a window or an image file.
+pub trait RenderTarget {
+    fn present(&mut self, buffer: &Pixmap) -> Result<()>;
+}
+
+/// Width and height of the tiles shaded in parallel by `Canvas::draw_parallel`.
+pub const TILE_SIZE: usize = 64;
+
+/// Backend independent software rasterizer which owns the color and depth buffers.
+pub struct Canvas {
+    buffer: Pixmap<Rgba8>,
+    depth: DepthBuffer,
+    viewport: Matrix4,
+    rasterizer_state: RasterizerState,
+    threads: usize,
+
+    width: usize,
+    height: usize,
+}
+
+impl Canvas {
+    pub fn new(w: usize, h: usize) -> Canvas {
+        Canvas {
+            buffer: Pixmap::new(w, h, Rgba8::default()),
+            depth: DepthBuffer::new(w, h, DepthState::new()),
+            viewport: Matrix4::viewport(0.0, 0.0, w as f32, h as f32, 0.0, 1.0),
+            rasterizer_state: RasterizerState::new(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            width: w,
+            height: h,
+        }
+    }
+
+    /// Maps normalized device coordinates to pixels, see `Matrix4::viewport`. Defaults
+    /// to the whole canvas.
+    pub fn set_viewport(&mut self, viewport: Matrix4) {
+        self.viewport = viewport;
+    }
+
+    pub fn viewport(&self) -> Matrix4 {
+        self.viewport
+    }
+
+    /// Face culling of `draw` and `draw_parallel`, the winding is taken from the
+    /// triangle after projection. Nothing is culled by default.
+    pub fn set_rasterizer_state(&mut self, state: RasterizerState) {
+        self.rasterizer_state = state;
+    }
+
+    pub fn rasterizer_state(&self) -> RasterizerState {
+        self.rasterizer_state
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn buffer(&self) -> &Pixmap {
+        &self.buffer
+    }
+
+    pub fn depth_buffer(&self) -> &DepthBuffer {
+        &self.depth
+    }
+
+    /// Depth test, write mask and clear value, takes effect for the following draws.
+    /// The depth range of the viewport has to match, see `DepthState::reversed`.
+    pub fn set_depth_state(&mut self, state: DepthState) {
+        self.depth.set_state(state);
+    }
+
+    pub fn depth_state(&self) -> DepthState {
+        self.depth.state()
+    }
+
+    pub fn clear(&mut self) {
+        self.depth.clear();
+        self.buffer.fill(Rgba8::default());
+    }
+
+    /// Hands the current frame over to the target and clears the canvas for the next one.
+    pub fn present<T: RenderTarget>(&mut self, target: &mut T) -> Result<()> {
+        try!(target.present(&self.buffer));
+        self.clear();
+
+        Ok(())
+    }
+
+    /// Aliased line, the part outside of the canvas is clipped away up front. Depth is
+    /// handled like in `set_pixel`.
+    pub fn line(&mut self, a: Vec3i, b: Vec3i, color: Rgba8) {
+        for p in LineRasterizer::with_clip(a, b, ClipBox::screen(self.width, self.height)) {
+            self.set_pixel(p, color);
+        }
+    }
+
+    /// Anti-aliased one pixel wide line, see `WuLineRasterizer`. Depth is handled like
+    /// in `set_pixel`. The line is clipped to a margin around the canvas up front.
+    pub fn smooth_line(&mut self, a: Vec3f, b: Vec3f, color: Rgba8) {
+        let (a, b) = match self.clip_line(a, b, 0.0) {
+            Some(line) => line,
+            None => return,
+        };
+        for (p, coverage) in WuLineRasterizer::new(a, b) {
+            self.set_pixel(p, color.with_coverage(coverage));
+        }
+    }
+
+    /// Anti-aliased line of any width with 4x4 samples per pixel, see
+    /// `ThickLineRasterizer`. Depth is handled like in `set_pixel`, the line is clipped
+    /// like in `smooth_line`.
+    pub fn thick_line(&mut self, a: Vec3f, b: Vec3f, width: f32, cap: LineCap, color: Rgba8) {
+        let (a, b) = match self.clip_line(a, b, width) {
+            Some(line) => line,
+            None => return,
+        };
+        for (p, coverage) in ThickLineRasterizer::new(a, b, width, cap, 4) {
+            self.set_pixel(p, color.with_coverage(coverage));
+        }
+    }
+
+    /// Part of a line of `width` which can touch the canvas. The margin covers the
+    /// width, the caps and the neighbouring pixels of an anti-aliased line, so the
+    /// cut ends stay out of sight.
+    fn clip_line(&self, a: Vec3f, b: Vec3f, width: f32) -> Option<(Vec3f, Vec3f)> {
+        let margin = width.max(0.0) + 2.0;
+        let max = (self.width as f32 - 1.0 + margin, self.height as f32 - 1.0 + margin);
+        clip_segment(a, b, (-margin, -margin), max)
+    }
+
+    /// Flat shaded triangle, depth is interpolated across it and handled like in
+    /// `set_pixel`. Corners may lie far outside of the canvas, the triangle is clipped
+    /// to `GUARD_BAND` first.
+    pub fn triangle(&mut self, a: Vec3i, b: Vec3i, c: Vec3i, color: Rgba8) {
+        let v = [a.to::<f32>(), b.to::<f32>(), c.to::<f32>()];
+        if v.iter().all(|p| p.x.abs() <= GUARD_BAND && p.y.abs() <= GUARD_BAND) {
+            self.flat_triangle(v, color);
+            return;
+        }
+
+        // the band becomes the x and y planes of the clipper, depth rides along as varying
+        let to_clip = |p: Vec3f| (Vec4f::new(p.x / GUARD_BAND, p.y / GUARD_BAND, 0.0, 1.0), p.z);
+        let to_screen = |&(p, z): &(Vec4f, f32)| {
+            Vec3f::new(p.x * GUARD_BAND, p.y * GUARD_BAND, z)
+        };
+        let polygon: Vec<Vec3f> = clip_triangle([to_clip(v[0]), to_clip(v[1]), to_clip(v[2])])
+            .iter()
+            .map(to_screen)
+            .collect();
+        for i in 2..polygon.len() {
+            self.flat_triangle([polygon[0], polygon[i - 1], polygon[i]], color);
+        }
+    }
+
+    fn flat_triangle(&mut self, v: [Vec3f; 3], color: Rgba8) {
+        for (x, y, w) in TriangleRasterizer::new(v, self.width, self.height) {
+            let z = v[0].z * w[0] + v[1].z * w[1] + v[2].z * w[2];
+            self.plot(x, y, z, color);
+        }
+    }
+
+    /// Runs the vertex shader on the corners of every triangle, clips the triangles
+    /// against the view frustum and fills them with the output of the fragment shader.
+    pub fn draw<VS, FS, I>(&mut self, vertex_shader: &VS, fragment_shader: &FS, triangles: I)
+        where VS: VertexShader,
+              FS: FragmentShader<VS::Varying>,
+              I: IntoIterator<Item = [VS::Input; 3]>
+    {
+        let (viewport, state) = (self.viewport, self.rasterizer_state);
+        let mut frame = self.frame();
+        for triangle in triangles {
+            setup_triangle(vertex_shader, &viewport, &state, &triangle, |t| {
+                frame.rasterize(&t, fragment_shader)
+            });
+        }
+    }
+
+    /// Same as `draw`, but the fragments are shaded on `threads()` threads. The canvas
+    /// is split into tiles and every tile receives its triangles in submission order,
+    /// the result is identical to `draw`.
+    pub fn draw_parallel<VS, FS, I>(&mut self,
+                                    vertex_shader: &VS,
+                                    fragment_shader: &FS,
+                                    triangles: I)
+        where VS: VertexShader,
+              VS::Varying: Send + Sync,
+              FS: FragmentShader<VS::Varying> + Sync,
+              I: IntoIterator<Item = [VS::Input; 3]>
+    {
+        let (viewport, state) = (self.viewport, self.rasterizer_state);
+        let mut screen_triangles = Vec::new();
+        for triangle in triangles {
+            setup_triangle(vertex_shader, &viewport, &state, &triangle, |t| {
+                screen_triangles.push(t)
+            });
+        }
+
+        let tiles_x = (self.width + TILE_SIZE - 1) / TILE_SIZE;
+        let tiles_y = (self.height + TILE_SIZE - 1) / TILE_SIZE;
+        let mut bins = vec![Vec::new(); tiles_x * tiles_y];
+        for (i, t) in screen_triangles.iter().enumerate() {
+            let xs = t.iter().map(|c| c.0.x);
+            let ys = t.iter().map(|c| c.0.y);
+            let min_x = xs.clone().fold(f32::INFINITY, f32::min).max(0.0) as usize / TILE_SIZE;
+            let max_x = xs.fold(f32::NEG_INFINITY, f32::max).max(0.0) as usize / TILE_SIZE;
+            let min_y = ys.clone().fold(f32::INFINITY, f32::min).max(0.0) as usize / TILE_SIZE;
+            let max_y = ys.fold(f32::NEG_INFINITY, f32::max).max(0.0) as usize / TILE_SIZE;
+
+            for ty in min_y..(max_y + 1).min(tiles_y) {
+                for tx in min_x..(max_x + 1).min(tiles_x) {
+                    bins[ty * tiles_x + tx].push(i);
+                }
+            }
+        }
+
+        // workers take tiles until none are left and hand back the finished ones
+        let next_tile = AtomicUsize::new(0);
+        let (width, height) = (self.width, self.height);
+        let (buffer, depth) = (&self.buffer, &self.depth);
+        let worker = || {
+            let mut finished = Vec::new();
+            loop {
+                let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                if tile >= bins.len() {
+                    return finished;
+                }
+                if bins[tile].is_empty() {
+                    continue;
+                }
+
+                let left = tile % tiles_x * TILE_SIZE;
+                let top = tile / tiles_x * TILE_SIZE;
+                let w = TILE_SIZE.min(width - left);
+                let h = TILE_SIZE.min(height - top);
+                let mut tile_buffer = buffer.view(left, top, w, h).unwrap().to_pixmap();
+                let mut tile_depth = depth.region(left, top, w, h).unwrap();
+                {
+                    let mut frame = Frame {
+                        buffer: &mut tile_buffer,
+                        depth: &mut tile_depth,
+                        left: left as i32,
+                        top: top as i32,
+                    };
+                    for &i in &bins[tile] {
+                        frame.rasterize(&screen_triangles[i], fragment_shader);
+                    }
+                }
+                finished.push((tile, tile_buffer, tile_depth));
+            }
+        };
+        let finished: Vec<_> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads).map(|_| scope.spawn(&worker)).collect();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+        });
+
+        for (tile, tile_buffer, tile_depth) in finished {
+            let left = tile % tiles_x * TILE_SIZE;
+            let top = tile / tiles_x * TILE_SIZE;
+            let w = tile_buffer.width();
+            for y in 0..tile_buffer.height() {
+                self.buffer.row_mut(top + y)[left..left + w].copy_from_slice(tile_buffer.row(y));
+            }
+            self.depth.copy_from(&tile_depth, left, top);
+        }
+    }
+
+    /// Number of threads used by `draw_parallel`, defaults to the available parallelism.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    fn frame<'a>(&'a mut self) -> Frame<'a> {
+        Frame {
+            buffer: &mut self.buffer,
+            depth: &mut self.depth,
+            left: 0,
+            top: 0,
+        }
+    }
+
+    /// Draws one pixel, depth tested like `draw`. `z` is in the units of the caller
+    /// while the default test only keeps depths below one, so pick a state which
+    /// suits them: `DepthFunc::Greater` with a clear value of negative infinity keeps
+    /// the largest `z`, `DepthState::disabled` lets the last pixel win.
+    pub fn set_pixel(&mut self, v: Vec3i, color: Rgba8) {
+        self.plot(v.x(), v.y(), v.z() as f32, color);
+    }
+
+    #[inline]
+    fn plot(&mut self, x: i32, y: i32, z: f32, color: Rgba8) {
+        self.frame().plot(x, y, z, color);
+    }
+}
+
+/// Corners of a triangle after the viewport transform with their varyings divided by
+/// `w`, and `1 / w` for perspective correct interpolation.
+type ScreenTriangle<V> = [(Vec3f, V, f32); 3];
+
+/// Vertex shading, clipping, the perspective divide and face culling. The clipped
+/// polygon is split into a fan of triangles which are handed to `emit`.
+fn setup_triangle<VS, F>(vertex_shader: &VS,
+                         viewport: &Matrix4,
+                         state: &RasterizerState,
+                         triangle: &[VS::Input; 3],
+                         mut emit: F)
+    where VS: VertexShader,
+          F: FnMut(ScreenTriangle<VS::Varying>)
+{
+    let a = vertex_shader.vertex(&triangle[0]);
+    let b = vertex_shader.vertex(&triangle[1]);
+    let c = vertex_shader.vertex(&triangle[2]);
+
+    let polygon = clip_triangle([a, b, c]);
+    if polygon.len() < 3 {
+        return;
+    }
+
+    // varyings are divided by w, interpolated linearly on screen and multiplied
+    // by the interpolated w afterwards, see `Frame::rasterize`
+    let corners: Vec<_> = polygon.iter()
+                                 .map(|&(clip, varying)| {
+                                     let inv_w = 1.0 / clip.w;
+                                     let screen = viewport.transform_point(clip.project());
+                                     (screen, varying * inv_w, inv_w)
+                                 })
+                                 .collect();
+    // clipping keeps the winding, all triangles of the fan share it
+    for i in 1..corners.len() - 1 {
+        let t = [corners[0], corners[i], corners[i + 1]];
+        match Winding::of([t[0].0, t[1].0, t[2].0]) {
+            Some(winding) if !state.culls(winding) => emit(t),
+            _ => {}
+        }
+    }
+}
+
+/// Color and depth buffer of the whole canvas or of one tile, `left` and `top` are the
+/// canvas position of the first pixel.
+struct Frame<'a> {
+    buffer: &'a mut Pixmap<Rgba8>,
+    depth: &'a mut DepthBuffer,
+    left: i32,
+    top: i32,
+}
+
+impl<'a> Frame<'a> {
+    /// Fills a screen space triangle, pixels outside of the frame are skipped. Spans are
+    /// processed in quads of four pixels, depth and `w` are computed for the whole quad
+    /// at once and fragments which would fail the depth test are not shaded. Shaders
+    /// which use derivatives get those of the 2x2 pixel quad around the fragment.
+    fn rasterize<V, FS>(&mut self, v: &ScreenTriangle<V>, fragment_shader: &FS)
+        where V: Varying,
+              FS: FragmentShader<V>
+    {
+        let positions = [v[0].0, v[1].0, v[2].0];
+        let raster = TriangleRasterizer::with_scissor(positions,
+                                                      self.left,
+                                                      self.top,
+                                                      self.left + self.buffer.width() as i32,
+                                                      self.top + self.buffer.height() as i32);
+        let z = F32x4::new(positions[0].z, positions[1].z, positions[2].z, 0.0);
+        let inv_w = F32x4::new(v[0].2, v[1].2, v[2].2, 0.0);
+        let uses_derivatives = fragment_shader.uses_derivatives();
+
+        for span in raster.spans() {
+            let weights = F32x4::new(span.weights[0], span.weights[1], span.weights[2], 0.0);
+            let step = F32x4::new(span.step[0], span.step[1], span.step[2], 0.0);
+            let w = span.weights;
+            let varying = v[0].1 * w[0] + v[1].1 * w[1] + v[2].1 * w[2];
+            let s = span.step;
+            let varying_step = v[0].1 * s[0] + v[1].1 * s[1] + v[2].1 * s[2];
+
+            let (z_start, z_step) = (F32x4::splat(weights.dot(z)), F32x4::splat(step.dot(z)));
+            let (inv_w_start, inv_w_step) = (F32x4::splat(weights.dot(inv_w)),
+                                             F32x4::splat(step.dot(inv_w)));
+
+            // varyings `dx` pixels right and `dy` rows below the first pixel, the top
+            // left corner of a quad is at even coordinates
+            let quad_derivatives = |quad_x: i32| {
+                let s = span.step_y;
+                let step_y = F32x4::new(s[0], s[1], s[2], 0.0);
+                let varying_step_y = v[0].1 * s[0] + v[1].1 * s[1] + v[2].1 * s[2];
+                let at = |dx: i32, dy: i32| {
+                    let (kx, ky) = (dx as f32, dy as f32);
+                    let inv_w = (weights + step * kx + step_y * ky).dot(inv_w);
+                    (varying + varying_step * kx + varying_step_y * ky) * (1.0 / inv_w)
+                };
+                let (dx, dy) = (quad_x - span.left, (span.y & !1) - span.y);
+                let origin = at(dx, dy);
+                Derivatives {
+                    ddx: at(dx + 1, dy) - origin,
+                    ddy: at(dx, dy + 1) - origin,
+                }
+            };
+            let mut quad: Option<(i32, Derivatives<V>)> = None;
+
+            let y = span.y - self.top;
+            let mut x = span.left;
+            while x < span.right {
+                let k = F32x4::splat((x - span.left) as f32) + F32x4::lanes();
+                let depths = (z_start + z_step * k).to_array();
+                let ws = (F32x4::splat(1.0) / (inv_w_start + inv_w_step * k)).to_array();
+
+                for lane in 0..((span.right - x) as usize).min(4) {
+                    let px = x + lane as i32 - self.left;
+                    let depth = depths[lane];
+                    if !self.depth.test(px as usize, y as usize, depth) {
+                        continue;
+                    }
+
+                    let k = (x - span.left) as f32 + lane as f32;
+                    let varying = (varying + varying_step * k) * ws[lane];
+                    let color = if uses_derivatives {
+                        let quad_x = (px + self.left) & !1;
+                        if quad.map_or(true, |(x, _)| x != quad_x) {
+                            quad = Some((quad_x, quad_derivatives(quad_x)));
+                        }
+                        fragment_shader.fragment_with_derivatives(&varying, &quad.unwrap().1)
+                    } else {
+                        fragment_shader.fragment(&varying)
+                    };
+                    if let Some(color) = color {
+                        self.plot(px + self.left, span.y, depth, color);
+                    }
+                }
+                x += 4;
+            }
+        }
+    }
+
+    /// Depth tested write, translucent colors are composited over the buffer contents.
+    /// Fully transparent fragments and fragments outside of the frame are dropped
+    /// without touching the depth buffer.
+    #[inline]
+    fn plot(&mut self, x: i32, y: i32, z: f32, color: Rgba8) {
+        if color.is_transparent() {
+            return;
+        }
+
+        let (x, y) = (x - self.left, y - self.top);
+        if self.depth.test_and_set(x, y, z) {
+            let color = if color.is_opaque() {
+                color
+            } else {
+                color.over(self.buffer.get(x, y).unwrap())
+            };
+            self.buffer.set(x, y, color);
+        }
+    }
+}
+
+/// Writes every presented frame into an image file, the format is chosen by the file extension.
+pub struct FileTarget {
+    path: String,
+}
+
+impl FileTarget {
+    pub fn new(path: &str) -> FileTarget {
+        FileTarget { path: path.to_string() }
+    }
+
+}
+
+impl RenderTarget for FileTarget {
+    fn present(&mut self, buffer: &Pixmap) -> Result<()> {
+        let extension = Path::new(&self.path).extension().and_then(|ext| ext.to_str());
+        match extension {
+            Some("ppm") => NetpbmWriter::new(NetpbmFormat::Rgb, 255).save(buffer, &self.path),
+            Some("pgm") => NetpbmWriter::new(NetpbmFormat::Gray, 255).save(buffer, &self.path),
+            Some("pam") => NetpbmWriter::new(NetpbmFormat::Pam, 255).save(buffer, &self.path),
+            Some("tga") => TgaWriter::new(24, true, Origin::TopLeft).save(buffer, &self.path),
+            Some("bmp") => BmpWriter::default().save(buffer, &self.path),
+            Some("png") => PngWriter::default().save(buffer, &self.path),
+            _ => {
+                Err(Error::new(ErrorKind::InvalidInput,
+                               format!("unsupported output format: {}", self.path)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canvas, RenderTarget};
+    use color::Rgba8;
+    use depthbuffer::{DepthFunc, DepthState};
+    use linerasterizer::LineCap;
+    use pixmap::Pixmap;
+    use vector3d::{Vec3f, Vec3i};
+
+    struct CountingTarget {
+        frames: usize,
+        lit: usize,
+    }
+
+    impl RenderTarget for CountingTarget {
+        fn present(&mut self, buffer: &Pixmap) -> super::Result<()> {
+            self.frames += 1;
+            self.lit += buffer.data().iter().filter(|&&c| c != Rgba8::default()).count();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_present_clears() {
+        let mut canvas = Canvas::new(16, 16);
+        let mut target = CountingTarget { frames: 0, lit: 0 };
+
+        canvas.line(Vec3i::new(0, 0, 0), Vec3i::new(15, 0, 0), Rgba8::rgb(255, 255, 255));
+        canvas.present(&mut target).unwrap();
+        assert_eq!(target.lit, 16);
+
+        canvas.present(&mut target).unwrap();
+        assert_eq!(target.frames, 2);
+        assert_eq!(target.lit, 16);
+    }
+
+    #[test]
+    fn test_offscreen_pixels_dropped() {
+        let mut canvas = Canvas::new(8, 8);
+        let white = Rgba8::rgb(255, 255, 255);
+        canvas.line(Vec3i::new(-4, 2, 0), Vec3i::new(12, 2, 0), white);
+        canvas.set_pixel(Vec3i::new(100, 100, 0), white);
+        canvas.set_pixel(Vec3i::new(-1, 5, 0), white);
+        canvas.set_pixel(Vec3i::new(8, 5, 0), white);
+        // control pixel, in bounds
+        canvas.set_pixel(Vec3i::new(3, 6, 0), white);
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let expected = if y == 2 || (x, y) == (3, 6) {
+                    white
+                } else {
+                    Rgba8::default()
+                };
+                assert_eq!(canvas.buffer().get(x, y), Some(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_square() {
+        let mut canvas = Canvas::new(64, 8);
+        let white = Rgba8::rgb(255, 255, 255);
+
+        canvas.line(Vec3i::new(0, 7, 0), Vec3i::new(63, 7, 0), white);
+        canvas.triangle(Vec3i::new(40, 0, 0), Vec3i::new(63, 0, 0), Vec3i::new(63, 6, 0), white);
+
+        assert_eq!(canvas.buffer().get(63, 7), Some(white));
+        assert_eq!(canvas.buffer().get(60, 1), Some(white));
+        assert_eq!(canvas.buffer().get(7, 63), None);
+    }
+
+    #[test]
+    fn test_offscreen_vertices() {
+        // the hypotenuse x + y = 32 crosses the canvas, the corners are millions of
+        // pixels away
+        let (far, white) = (1 << 25, Rgba8::rgb(255, 255, 255));
+        let mut canvas = Canvas::new(32, 32);
+        canvas.triangle(Vec3i::new(-far, -far, 0),
+                        Vec3i::new(far + 32, -far, 0),
+                        Vec3i::new(-far, far + 32, 0),
+                        white);
+
+        let mut expected = Canvas::new(32, 32);
+        expected.triangle(Vec3i::new(0, 0, 0), Vec3i::new(32, 0, 0), Vec3i::new(0, 32, 0), white);
+        assert_eq!(expected.buffer().get(15, 15), Some(white));
+        assert!(canvas.buffer() == expected.buffer());
+    }
+
+    #[test]
+    fn test_alpha_blending() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.set_depth_state(DepthState {
+            func: DepthFunc::Greater,
+            write: true,
+            clear_value: f32::NEG_INFINITY,
+        });
+        canvas.clear();
+        let p = Vec3i::new(1, 1, 0);
+
+        canvas.set_pixel(p, Rgba8::rgb(255, 0, 0));
+        // fully transparent fragments must not occlude anything behind them
+        canvas.set_pixel(Vec3i::new(1, 1, 10), Rgba8::new(0, 255, 0, 0));
+        canvas.set_pixel(Vec3i::new(1, 1, 5), Rgba8::new(0, 0, 255, 128));
+        // hidden behind the translucent fragment
+        canvas.set_pixel(Vec3i::new(1, 1, 1), Rgba8::rgb(0, 255, 0));
+
+        assert_eq!(canvas.buffer().get(1, 1), Some(Rgba8::new(127, 0, 128, 255)));
+    }
+
+    #[test]
+    fn test_smooth_lines() {
+        let mut canvas = Canvas::new(8, 8);
+        let white = Rgba8::rgb(255, 255, 255);
+
+        canvas.smooth_line(Vec3f::new(1.0, 2.5, 0.0), Vec3f::new(6.0, 2.5, 0.0), white);
+        assert_eq!(canvas.buffer().get(3, 2), Some(Rgba8::new(255, 255, 255, 128)));
+        assert_eq!(canvas.buffer().get(3, 3), Some(Rgba8::new(255, 255, 255, 128)));
+        assert_eq!(canvas.buffer().get(3, 4), Some(Rgba8::default()));
+
+        let (a, b) = (Vec3f::new(1.0, 6.0, 0.0), Vec3f::new(6.0, 6.0, 0.0));
+        canvas.thick_line(a, b, 2.0, LineCap::Butt, white);
+        assert_eq!(canvas.buffer().get(3, 6), Some(white));
+        assert_eq!(canvas.buffer().get(3, 7), Some(Rgba8::new(255, 255, 255, 128)));
+    }
+
+    #[test]
+    fn test_far_line_end_points() {
+        // millions of pixels past the canvas, only the visible part is walked
+        let (far, white) = ((1 << 24) as f32, Rgba8::rgb(255, 255, 255));
+        let mut canvas = Canvas::new(8, 8);
+        let mut expected = Canvas::new(8, 8);
+
+        canvas.smooth_line(Vec3f::new(2.0, 3.5, 0.0), Vec3f::new(far, 3.5, 0.0), white);
+        expected.smooth_line(Vec3f::new(2.0, 3.5, 0.0), Vec3f::new(20.0, 3.5, 0.0), white);
+        assert_eq!(expected.buffer().get(7, 3), Some(Rgba8::new(255, 255, 255, 128)));
+        assert!(canvas.buffer() == expected.buffer());
+
+        for &cap in &[LineCap::Butt, LineCap::Square, LineCap::Round] {
+            canvas.clear();
+            expected.clear();
+            let (a, b) = (Vec3f::new(-far, -far, 0.0), Vec3f::new(far, far, 0.0));
+            canvas.thick_line(a, b, 2.0, cap, white);
+            let (a, b) = (Vec3f::new(-20.0, -20.0, 0.0), Vec3f::new(20.0, 20.0, 0.0));
+            expected.thick_line(a, b, 2.0, cap, white);
+            assert_eq!(expected.buffer().get(4, 4), Some(white));
+            assert!(canvas.buffer() == expected.buffer());
+        }
+    }
+
+    #[test]
+    fn test_depth_state() {
+        let mut canvas = Canvas::new(4, 4);
+        let (red, green) = (Rgba8::rgb(255, 0, 0), Rgba8::rgb(0, 255, 0));
+
+        canvas.set_depth_state(DepthState { write: false, ..DepthState::new() });
+        canvas.set_pixel(Vec3i::new(0, 0, 0), red);
+        canvas.set_pixel(Vec3i::new(0, 0, 0), green);
+        assert_eq!(canvas.buffer().get(0, 0), Some(green));
+        assert_eq!(canvas.depth_buffer().get(0, 0), Some(1.0));
+
+        canvas.set_depth_state(DepthState { func: DepthFunc::Never, ..DepthState::new() });
+        canvas.set_pixel(Vec3i::new(1, 0, 0), red);
+        assert_eq!(canvas.buffer().get(1, 0), Some(Rgba8::default()));
+
+        // reversed-Z keeps the larger depth, the clear value is used from now on
+        canvas.set_depth_state(DepthState::reversed());
+        canvas.clear();
+        canvas.set_pixel(Vec3i::new(2, 0, 1), red);
+        canvas.set_pixel(Vec3i::new(2, 0, 0), green);
+        assert_eq!(canvas.buffer().get(2, 0), Some(red));
+    }
+
+    #[test]
+    fn test_integer_depth() {
+        let mut canvas = Canvas::new(8, 8);
+        let (red, green) = (Rgba8::rgb(255, 0, 0), Rgba8::rgb(0, 255, 0));
+
+        // the default test keeps depths below the clear value of one
+        canvas.line(Vec3i::new(0, 1, 0), Vec3i::new(7, 1, 0), red);
+        canvas.triangle(Vec3i::new(0, 0, 100), Vec3i::new(8, 0, 100), Vec3i::new(0, 8, 100), green);
+        assert_eq!(canvas.buffer().get(1, 1), Some(red));
+        assert_eq!(canvas.buffer().get(1, 2), Some(Rgba8::default()));
+        assert_eq!(canvas.depth_buffer().get(1, 1), Some(0.0));
+
+        // without a test any z is drawn and the last pixel wins
+        canvas.set_depth_state(DepthState::disabled());
+        canvas.clear();
+        canvas.line(Vec3i::new(0, 1, 3), Vec3i::new(7, 1, 3), red);
+        canvas.triangle(Vec3i::new(0, 0, 100), Vec3i::new(8, 0, 100), Vec3i::new(0, 8, 100), green);
+        canvas.set_pixel(Vec3i::new(5, 0, -7), red);
+        assert_eq!(canvas.buffer().get(7, 1), Some(red));
+        assert_eq!(canvas.buffer().get(1, 1), Some(green));
+        assert_eq!(canvas.buffer().get(5, 0), Some(red));
+        assert_eq!(canvas.depth_buffer().get(1, 1), Some(1.0));
+
+        // the largest z wins once the test is configured for it
+        canvas.set_depth_state(DepthState {
+            func: DepthFunc::Greater,
+            write: true,
+            clear_value: f32::NEG_INFINITY,
+        });
+        canvas.clear();
+        canvas.triangle(Vec3i::new(0, 0, 100), Vec3i::new(8, 0, 100), Vec3i::new(0, 8, 100), green);
+        canvas.line(Vec3i::new(0, 1, 3), Vec3i::new(7, 1, 3), red);
+        assert_eq!(canvas.buffer().get(1, 1), Some(green));
+        assert_eq!(canvas.buffer().get(7, 1), Some(red));
+    }
+}
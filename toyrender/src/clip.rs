@@ -0,0 +1,156 @@
+use matrix::Vec4f;
+use shader::Varying;
+use vector3d::Vec3f;
+
+/// Inner sides of the view frustum in clip space, a vertex `v` is inside when
+/// `plane * v >= 0`, i.e. `-w <= x, y, z <= w`.
+const PLANES: [[f32; 4]; 6] = [[1.0, 0.0, 0.0, 1.0],
+                               [-1.0, 0.0, 0.0, 1.0],
+                               [0.0, 1.0, 0.0, 1.0],
+                               [0.0, -1.0, 0.0, 1.0],
+                               [0.0, 0.0, 1.0, 1.0],
+                               [0.0, 0.0, -1.0, 1.0]];
+
+fn plane(i: usize) -> Vec4f {
+    let p = PLANES[i];
+    Vec4f::new(p[0], p[1], p[2], p[3])
+}
+
+/// Sutherland-Hodgman clipping of a clip space triangle against the view frustum.
+/// Returns the corners of the convex polygon which remains, empty if the triangle is
+/// not visible at all. Varyings of new corners are interpolated in clip space.
+pub fn clip_triangle<V: Varying>(triangle: [(Vec4f, V); 3]) -> Vec<(Vec4f, V)> {
+    let inside = |v: &Vec4f| (0..PLANES.len()).all(|i| plane(i) * *v >= 0.0);
+    if triangle.iter().all(|corner| inside(&corner.0)) {
+        return triangle.to_vec();
+    }
+
+    let mut polygon = triangle.to_vec();
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for i in 0..PLANES.len() {
+        let plane = plane(i);
+        clipped.clear();
+
+        for j in 0..polygon.len() {
+            let a = polygon[j];
+            let b = polygon[(j + 1) % polygon.len()];
+            let da = plane * a.0;
+            let db = plane * b.0;
+
+            if da >= 0.0 {
+                clipped.push(a);
+            }
+            if (da >= 0.0) != (db >= 0.0) {
+                let t = da / (da - db);
+                clipped.push((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t));
+            }
+        }
+
+        ::std::mem::swap(&mut polygon, &mut clipped);
+        if polygon.is_empty() {
+            break;
+        }
+    }
+    polygon
+}
+
+/// Liang-Barsky clipping of a screen space segment to the rectangle from `min` to
+/// `max`, depth is interpolated along it. Runs in `f64`, so end points millions of
+/// pixels away don't move the part which is left. `None` if nothing is.
+pub fn clip_segment(a: Vec3f,
+                    b: Vec3f,
+                    min: (f32, f32),
+                    max: (f32, f32))
+                    -> Option<(Vec3f, Vec3f)> {
+    let (ax, ay, az) = (a.x as f64, a.y as f64, a.z as f64);
+    let (dx, dy, dz) = (b.x as f64 - ax, b.y as f64 - ay, b.z as f64 - az);
+    // the segment is inside side `i` while `t * p <= q`
+    let sides = [(-dx, ax - min.0 as f64),
+                 (dx, max.0 as f64 - ax),
+                 (-dy, ay - min.1 as f64),
+                 (dy, max.1 as f64 - ay)];
+
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for &(p, q) in &sides {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+
+    let at = |t: f64| Vec3f::new((ax + dx * t) as f32, (ay + dy * t) as f32, (az + dz * t) as f32);
+    Some((at(t0), at(t1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clip_segment, clip_triangle};
+    use matrix::Vec4f;
+    use vector3d::Vec3f;
+
+    #[test]
+    fn test_inside_untouched() {
+        let triangle = [(Vec4f::new(0.0, 0.0, 0.0, 1.0), 1.0),
+                        (Vec4f::new(0.5, 0.0, 0.0, 1.0), 2.0),
+                        (Vec4f::new(0.0, 0.5, 0.0, 1.0), 3.0)];
+        assert_eq!(clip_triangle(triangle), triangle.to_vec());
+    }
+
+    #[test]
+    fn test_outside_rejected() {
+        let triangle = [(Vec4f::new(2.0, 0.0, 0.0, 1.0), 0.0),
+                        (Vec4f::new(3.0, 0.0, 0.0, 1.0), 0.0),
+                        (Vec4f::new(2.0, 0.5, 0.0, 1.0), 0.0)];
+        assert!(clip_triangle(triangle).is_empty());
+
+        // behind the camera
+        let triangle = [(Vec4f::new(0.0, 0.0, 1.0, -1.0), 0.0),
+                        (Vec4f::new(0.5, 0.0, 1.0, -1.0), 0.0),
+                        (Vec4f::new(0.0, 0.5, 1.0, -1.0), 0.0)];
+        assert!(clip_triangle(triangle).is_empty());
+    }
+
+    #[test]
+    fn test_partially_clipped() {
+        // one corner sticks out on the right and is cut into two
+        let triangle = [(Vec4f::new(0.0, 0.0, 0.0, 1.0), 0.0),
+                        (Vec4f::new(2.0, 0.0, 0.0, 1.0), 2.0),
+                        (Vec4f::new(0.0, 0.5, 0.0, 1.0), 0.0)];
+        let polygon = clip_triangle(triangle);
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|&(v, _)| v.x <= v.w));
+        assert!(polygon.contains(&(Vec4f::new(1.0, 0.0, 0.0, 1.0), 1.0)));
+
+        // crossing the near plane
+        let triangle = [(Vec4f::new(0.0, 0.0, -3.0, -1.0), 0.0),
+                        (Vec4f::new(0.0, 0.0, 1.0, 3.0), 0.0),
+                        (Vec4f::new(0.5, 0.5, 1.0, 3.0), 0.0)];
+        let polygon = clip_triangle(triangle);
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|&(v, _)| v.z >= -v.w - 1e-6 && v.w > 0.0));
+    }
+
+    #[test]
+    fn test_clip_segment() {
+        let (min, max) = ((0.0, 0.0), (10.0, 10.0));
+        let (a, b) = (Vec3f::new(2.0, 3.0, 0.0), Vec3f::new(8.0, 5.0, 1.0));
+        assert_eq!(clip_segment(a, b, min, max), Some((a, b)));
+        assert_eq!(clip_segment(Vec3f::new(11.0, 0.0, 0.0), Vec3f::new(20.0, 5.0, 0.0), min, max),
+                   None);
+        assert_eq!(clip_segment(a, a, min, max), Some((a, a)));
+
+        // both ends far away, depth follows the cut
+        let far = (1 << 24) as f32;
+        let (a, b) = (Vec3f::new(-far, 5.0, -far), Vec3f::new(far, 5.0, far));
+        assert_eq!(clip_segment(a, b, min, max),
+                   Some((Vec3f::new(0.0, 5.0, 0.0), Vec3f::new(10.0, 5.0, 10.0))));
+    }
+}
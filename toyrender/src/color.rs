@@ -0,0 +1,171 @@
+/// 8 bit per channel color without alpha.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Rgb8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb8 {
+    pub fn new(r: u8, g: u8, b: u8) -> Rgb8 {
+        Rgb8 { r: r, g: g, b: b }
+    }
+}
+
+/// 8 bit per channel color with straight (not premultiplied) alpha.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Rgba8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba8 {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba8 {
+        Rgba8 {
+            r: r,
+            g: g,
+            b: b,
+            a: a,
+        }
+    }
+
+    /// Opaque color.
+    pub fn rgb(r: u8, g: u8, b: u8) -> Rgba8 {
+        Rgba8::new(r, g, b, 255)
+    }
+
+    /// Unpacks `0xAARRGGBB`, the layout used by the pixmaps.
+    pub fn from_argb(v: u32) -> Rgba8 {
+        Rgba8::new((v >> (8 * 2)) as u8,
+                   (v >> (8 * 1)) as u8,
+                   v as u8,
+                   (v >> (8 * 3)) as u8)
+    }
+
+    pub fn to_argb(self) -> u32 {
+        (self.a as u32) << (8 * 3) | (self.r as u32) << (8 * 2) | (self.g as u32) << (8 * 1) |
+        self.b as u32
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.a == 0
+    }
+
+    /// Rec. 601 luma in integer arithmetic.
+    pub fn luma(&self) -> u8 {
+        ((self.r as u32 * 77 + self.g as u32 * 150 + self.b as u32 * 29) >> 8) as u8
+    }
+
+    /// Multiplies color channels by `k`, alpha is left untouched.
+    pub fn scaled(self, k: f32) -> Rgba8 {
+        let scale = |c: u8| (c as f32 * k).max(0.0).min(255.0) as u8;
+        Rgba8::new(scale(self.r), scale(self.g), scale(self.b), self.a)
+    }
+
+    /// Scales alpha by the fraction `k` of a pixel covered by a shape.
+    pub fn with_coverage(self, k: f32) -> Rgba8 {
+        let a = (self.a as f32 * k.max(0.0).min(1.0) + 0.5) as u8;
+        Rgba8::new(self.r, self.g, self.b, a)
+    }
+
+    /// Porter-Duff source-over: composites `self` on top of `dst`.
+    pub fn over(self, dst: Rgba8) -> Rgba8 {
+        if self.is_opaque() || dst.is_transparent() {
+            return self;
+        }
+
+        let sa = self.a as u32;
+        let da = (dst.a as u32 * (255 - sa) + 127) / 255;
+        let a = sa + da;
+        if a == 0 {
+            return Rgba8::default();
+        }
+
+        let mix = |s: u8, d: u8| ((s as u32 * sa + d as u32 * da + a / 2) / a) as u8;
+        Rgba8::new(mix(self.r, dst.r),
+                   mix(self.g, dst.g),
+                   mix(self.b, dst.b),
+                   a as u8)
+    }
+}
+
+impl From<Rgb8> for Rgba8 {
+    fn from(c: Rgb8) -> Rgba8 {
+        Rgba8::rgb(c.r, c.g, c.b)
+    }
+}
+
+impl From<Rgba8> for Rgb8 {
+    fn from(c: Rgba8) -> Rgb8 {
+        Rgb8::new(c.r, c.g, c.b)
+    }
+}
+
+impl From<u8> for Rgba8 {
+    fn from(v: u8) -> Rgba8 {
+        Rgba8::rgb(v, v, v)
+    }
+}
+
+impl From<Rgba8> for u8 {
+    fn from(c: Rgba8) -> u8 {
+        c.luma()
+    }
+}
+
+impl From<u16> for Rgba8 {
+    fn from(v: u16) -> Rgba8 {
+        Rgba8::from((v >> 8) as u8)
+    }
+}
+
+impl From<Rgba8> for u16 {
+    fn from(c: Rgba8) -> u16 {
+        c.luma() as u16 * 257
+    }
+}
+
+/// Values in `[0, 1]` become shades of gray, handy to look at depth buffers.
+impl From<f32> for Rgba8 {
+    fn from(v: f32) -> Rgba8 {
+        Rgba8::from((v.max(0.0).min(1.0) * 255.0 + 0.5) as u8)
+    }
+}
+
+impl From<Rgba8> for f32 {
+    fn from(c: Rgba8) -> f32 {
+        c.luma() as f32 / 255.0
+    }
+}
+
+#[test]
+fn test_argb_round_trip() {
+    let c = Rgba8::new(0x12, 0x34, 0x56, 0x78);
+
+    assert_eq!(c.to_argb(), 0x78123456);
+    assert_eq!(Rgba8::from_argb(c.to_argb()), c);
+}
+
+#[test]
+fn test_over() {
+    let red = Rgba8::rgb(255, 0, 0);
+    let half_blue = Rgba8::new(0, 0, 255, 128);
+
+    assert_eq!(red.over(half_blue), red);
+    assert_eq!(half_blue.over(Rgba8::default()), half_blue);
+
+    let c = half_blue.over(red);
+    assert_eq!(c.a, 255);
+    assert_eq!(c.r, 127);
+    assert_eq!(c.b, 128);
+
+    let c = half_blue.over(Rgba8::new(255, 0, 0, 128));
+    assert_eq!(c.a, 192);
+    assert!(c.b > c.r);
+}
@@ -0,0 +1,73 @@
+//! The african_head scene drawn by the `toyrender` binary and timed by
+//! `examples/bench.rs`.
+
+use canvas::Canvas;
+use depthbuffer::DepthState;
+use matrix::{Matrix4, Transforms};
+use model::{self, Loader, Model, NormalSpace};
+use shader::{ModelShader, PhongShader};
+use shading::{Light, Surface};
+use trianglerasterizer::{CullMode, RasterizerState, Winding};
+use vector3d::Vec3f;
+
+/// Loads the head, paths are relative to the `toyrender` directory.
+pub fn load() -> model::Result<Model> {
+    let mut model = try!(Loader::from_obj("obj/african/african_head.obj"));
+    // the normal map shipped with the head was baked in object space
+    model.set_normal_space(NormalSpace::Object);
+    Ok(model)
+}
+
+/// Canvas of the given size set up for `render`, cleared.
+pub fn canvas(w: usize, h: usize) -> Canvas {
+    let mut canvas = Canvas::new(w, h);
+    // reversed-Z, the head is small compared to the depth range of the camera
+    canvas.set_depth_state(DepthState::reversed());
+    canvas.set_viewport(Matrix4::viewport(0.0, 0.0, w as f32, h as f32, 1.0, 0.0));
+    canvas.clear();
+    canvas.set_rasterizer_state(RasterizerState {
+        cull_mode: CullMode::Back,
+        front_face: Winding::CounterClockwise,
+    });
+    canvas
+}
+
+/// Draws `model` lit from the front left into `canvas`.
+pub fn render(canvas: &mut Canvas, model: &Model) {
+    let w = canvas.width() as f32;
+    let h = canvas.height() as f32;
+    let aspect = w / h;
+    // keep the aspect ratio, the model fits into the smaller dimension
+    let fov = 0.7f32;
+    let fovy = if aspect < 1.0 {
+        2.0 * ((fov / 2.0).tan() / aspect).atan()
+    } else {
+        fov
+    };
+
+    let eye = Vec3f::new(0.0, 0.0, 3.0);
+    let transforms = Transforms {
+        model: Matrix4::identity(),
+        view: Matrix4::look_at(eye, Vec3f::zero(), Vec3f::new(0.0, 1.0, 0.0)),
+        projection: Matrix4::perspective(fovy, aspect, 0.1, 10.0),
+    };
+    let vertex_shader = ModelShader::new(model, transforms);
+    let light = Light::new(Vec3f::new(-0.3, -0.3, -1.0));
+    let eye_dir = eye.normalized();
+
+    for mesh in &model.meshes {
+        let surface = match model.material(mesh) {
+            Some(material) => Surface::with_material(&model.diffuse, material),
+            None => Surface::new(&model.diffuse),
+        };
+        let surface = Surface { normal_matrix: transforms.normal_matrix(), ..surface };
+        let fragment_shader = PhongShader {
+            surface: surface,
+            light: light,
+            eye_dir: eye_dir,
+        };
+
+        let triangles = mesh.faces.clone().map(|i| [(i, 0), (i, 1), (i, 2)]);
+        canvas.draw_parallel(&vertex_shader, &fragment_shader, triangles);
+    }
+}
@@ -0,0 +1,211 @@
+use pixmap::Pixmap;
+
+/// Comparison of an incoming depth value against the stored one, the fragment is kept
+/// when it returns true.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DepthFunc {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Always,
+    Never,
+}
+
+impl DepthFunc {
+    #[inline]
+    pub fn passes(self, incoming: f32, stored: f32) -> bool {
+        match self {
+            DepthFunc::Less => incoming < stored,
+            DepthFunc::LessEqual => incoming <= stored,
+            DepthFunc::Greater => incoming > stored,
+            DepthFunc::GreaterEqual => incoming >= stored,
+            DepthFunc::Always => true,
+            DepthFunc::Never => false,
+        }
+    }
+}
+
+/// How fragments are tested against and written to a `DepthBuffer`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthState {
+    pub func: DepthFunc,
+    /// Fragments which pass the test store their depth, disable for e.g. translucent
+    /// geometry drawn after the opaque one.
+    pub write: bool,
+    /// Value the buffer is filled with by `DepthBuffer::clear`.
+    pub clear_value: f32,
+}
+
+impl DepthState {
+    /// Depth grows with the distance to the camera, from `0` at the near to `1` at the
+    /// far plane.
+    pub fn new() -> DepthState {
+        DepthState {
+            func: DepthFunc::Less,
+            write: true,
+            clear_value: 1.0,
+        }
+    }
+
+    /// Reversed-Z, `1` at the near and `0` at the far plane. Distant surfaces end up
+    /// close to zero where floats are densest, which offsets the loss of precision of
+    /// the perspective divide. Pair it with a viewport from `1` to `0`.
+    pub fn reversed() -> DepthState {
+        DepthState {
+            func: DepthFunc::Greater,
+            write: true,
+            clear_value: 0.0,
+        }
+    }
+
+    /// Every fragment is drawn and the buffer is never written, later pixels simply
+    /// overwrite earlier ones.
+    pub fn disabled() -> DepthState {
+        DepthState {
+            func: DepthFunc::Always,
+            write: false,
+            clear_value: 1.0,
+        }
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> DepthState {
+        DepthState::new()
+    }
+}
+
+/// Per pixel `f32` depth together with the test which is applied to it.
+#[derive(Clone, Debug)]
+pub struct DepthBuffer {
+    values: Pixmap<f32>,
+    state: DepthState,
+}
+
+impl DepthBuffer {
+    pub fn new(w: usize, h: usize, state: DepthState) -> DepthBuffer {
+        DepthBuffer {
+            values: Pixmap::new(w, h, state.clear_value),
+            state: state,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.values.width()
+    }
+    pub fn height(&self) -> usize {
+        self.values.height()
+    }
+
+    pub fn state(&self) -> DepthState {
+        self.state
+    }
+
+    /// Changes the test, the stored values are kept until the next `clear`.
+    pub fn set_state(&mut self, state: DepthState) {
+        self.state = state;
+    }
+
+    pub fn clear(&mut self) {
+        self.values.fill(self.state.clear_value);
+    }
+
+    /// Stored depth values, e.g. to convert them into a gray image.
+    pub fn values(&self) -> &Pixmap<f32> {
+        &self.values
+    }
+
+    /// Depth values in `[0, 1]` scaled to 16 bits, see `NetpbmWriter::write_gray16`.
+    pub fn to_gray16(&self) -> Pixmap<u16> {
+        self.values.map(|z| (z.max(0.0).min(1.0) * 65535.0 + 0.5) as u16)
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<f32> {
+        self.values.get(x, y)
+    }
+
+    /// Whether a fragment at depth `z` passes the test, without writing anything.
+    /// Panics if the pixel is outside of the buffer.
+    #[inline]
+    pub fn test(&self, x: usize, y: usize, z: f32) -> bool {
+        self.state.func.passes(z, self.values[(x, y)])
+    }
+
+    /// Tests a fragment and stores its depth if it passes and writes are enabled.
+    /// Fragments outside of the buffer fail.
+    #[inline]
+    pub fn test_and_set(&mut self, x: i32, y: i32, z: f32) -> bool {
+        let passes = match self.values.get(x, y) {
+            Some(stored) => self.state.func.passes(z, stored),
+            None => false,
+        };
+        if passes && self.state.write {
+            self.values.set(x, y, z);
+        }
+        passes
+    }
+
+    /// Copy of the `w` x `h` region at `(x, y)` with the same state.
+    pub fn region(&self, x: usize, y: usize, w: usize, h: usize) -> Option<DepthBuffer> {
+        self.values.view(x, y, w, h).map(|view| {
+            DepthBuffer {
+                values: view.to_pixmap(),
+                state: self.state,
+            }
+        })
+    }
+
+    /// Writes a region obtained by `region` back to `(x, y)`.
+    pub fn copy_from(&mut self, region: &DepthBuffer, x: usize, y: usize) {
+        let w = region.width();
+        for row in 0..region.height() {
+            self.values.row_mut(y + row)[x..x + w].copy_from_slice(region.values.row(row));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DepthBuffer, DepthFunc, DepthState};
+
+    #[test]
+    fn test_compare_functions() {
+        let cases = [(DepthFunc::Less, [true, false, false]),
+                     (DepthFunc::LessEqual, [true, true, false]),
+                     (DepthFunc::Greater, [false, false, true]),
+                     (DepthFunc::GreaterEqual, [false, true, true]),
+                     (DepthFunc::Always, [true, true, true]),
+                     (DepthFunc::Never, [false, false, false])];
+        for &(func, expected) in &cases {
+            let passes = [func.passes(0.25, 0.5), func.passes(0.5, 0.5), func.passes(0.75, 0.5)];
+            assert_eq!(passes, expected, "{:?}", func);
+        }
+    }
+
+    #[test]
+    fn test_write_mask_and_clear() {
+        let mut depth = DepthBuffer::new(2, 2, DepthState::new());
+        assert!(depth.test_and_set(0, 0, 0.5));
+        assert!(!depth.test_and_set(0, 0, 0.75));
+        assert!(!depth.test_and_set(2, 0, 0.0));
+        assert_eq!(depth.get(0, 0), Some(0.5));
+        assert_eq!(depth.to_gray16().data(), &[32768, 65535, 65535, 65535]);
+
+        depth.set_state(DepthState { write: false, ..DepthState::new() });
+        assert!(depth.test_and_set(0, 0, 0.25));
+        assert_eq!(depth.get(0, 0), Some(0.5));
+        assert_eq!(depth.to_gray16().data(), &[32768, 65535, 65535, 65535]);
+
+        depth.set_state(DepthState::reversed());
+        depth.clear();
+        assert_eq!(depth.get(1, 1), Some(0.0));
+        assert!(depth.test(1, 1, 0.1));
+        assert!(!depth.test(1, 1, 0.0));
+
+        depth.set_state(DepthState::disabled());
+        assert!(depth.test_and_set(1, 1, -5.0));
+        assert!(depth.test_and_set(1, 1, 1e9));
+        assert_eq!(depth.get(1, 1), Some(0.0));
+    }
+}
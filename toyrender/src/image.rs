@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+
+use bmp::BmpImage;
+use netpbm::NetpbmImage;
+use pixmap::Pixmap;
+use png::PngImage;
+use tgaimage::{Result, TgaImage};
+
+/// Image format which an `ImageRegistry` can pick to decode a file.
+pub trait ImageDecoder {
+    /// Lowercase file extensions without the dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Whether `header`, the first bytes of a file, identify the format. Formats
+    /// without a magic number return false and are only picked by extension.
+    fn detect(&self, header: &[u8]) -> bool;
+
+    fn decode(&self, inp: &mut dyn BufRead) -> Result<Pixmap>;
+}
+
+/// Bytes of a file which are passed to `ImageDecoder::detect`.
+pub const MAGIC_LEN: usize = 16;
+
+/// Picks a decoder for image files by their magic number and falls back to the file
+/// extension, so files with a wrong extension are still decoded properly.
+pub struct ImageRegistry {
+    decoders: Vec<Box<dyn ImageDecoder>>,
+}
+
+impl ImageRegistry {
+    /// Registry without any formats.
+    pub fn empty() -> ImageRegistry {
+        ImageRegistry { decoders: Vec::new() }
+    }
+
+    /// TGA, BMP, PNG and Netpbm.
+    pub fn new() -> ImageRegistry {
+        let mut registry = ImageRegistry::empty();
+        registry.register(TgaImage);
+        registry.register(BmpImage);
+        registry.register(PngImage);
+        registry.register(NetpbmImage);
+        registry
+    }
+
+    /// Adds a format, it takes precedence over the formats registered before.
+    pub fn register<D: ImageDecoder + 'static>(&mut self, decoder: D) {
+        self.decoders.push(Box::new(decoder));
+    }
+
+    /// Decoder for a file starting with `header`, if no magic number matches the
+    /// extension of `path` decides.
+    pub fn find(&self, header: &[u8], path: Option<&str>) -> Option<&dyn ImageDecoder> {
+        if let Some(decoder) = self.decoders.iter().rev().find(|d| d.detect(header)) {
+            return Some(&**decoder);
+        }
+
+        let extension = path.and_then(|path| Path::new(path).extension())
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        let extension = match extension {
+            Some(extension) => extension,
+            None => return None,
+        };
+        self.decoders
+            .iter()
+            .rev()
+            .find(|d| d.extensions().contains(&&extension[..]))
+            .map(|decoder| &**decoder)
+    }
+
+    /// Decodes an image from `inp`, `path` is only used to look at the extension.
+    pub fn read<R: Read>(&self, mut inp: R, path: Option<&str>) -> Result<Pixmap> {
+        let mut header = Vec::with_capacity(MAGIC_LEN);
+        try!(inp.by_ref().take(MAGIC_LEN as u64).read_to_end(&mut header));
+
+        let decoder = match self.find(&header, path) {
+            Some(decoder) => decoder,
+            None => return Err(Error::new(ErrorKind::InvalidData, "unknown image format")),
+        };
+        decoder.decode(&mut BufReader::new((&header[..]).chain(inp)))
+    }
+
+    pub fn load(&self, path: &str) -> Result<Pixmap> {
+        let file = try!(File::open(&Path::new(path)));
+        self.read(file, Some(path))
+    }
+}
+
+impl Default for ImageRegistry {
+    fn default() -> ImageRegistry {
+        ImageRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::ImageRegistry;
+    use bmp::BmpWriter;
+    use color::Rgba8;
+    use netpbm::NetpbmWriter;
+    use pixmap::Pixmap;
+    use png::PngWriter;
+    use tgaimage::{ImageWriter, Origin, TgaWriter};
+
+    #[test]
+    fn test_detect() {
+        let mut pixmap = Pixmap::new(5, 3, Rgba8::rgb(10, 20, 30));
+        pixmap[(4, 2)] = Rgba8::rgb(200, 100, 0);
+        let registry = ImageRegistry::new();
+
+        let writers: [(&dyn ImageWriter, &str); 4] =
+            [(&TgaWriter::new(24, true, Origin::TopLeft), "tga"),
+             (&BmpWriter::default(), "bmp"),
+             (&PngWriter::default(), "png"),
+             (&NetpbmWriter::default(), "ppm")];
+        for &(writer, extension) in &writers {
+            let path = env::temp_dir().join(format!("toyrender_registry.{}", extension));
+            let path = path.to_str().unwrap();
+            writer.save(&pixmap, path).unwrap();
+            assert!(registry.load(path).unwrap() == pixmap, "{}", extension);
+
+            // TGA has no magic number, the others are found regardless of the name
+            let bytes = fs::read(path).unwrap();
+            let by_magic = registry.read(&bytes[..], Some("image.dat"));
+            if extension == "tga" {
+                assert!(by_magic.is_err());
+                assert!(registry.read(&bytes[..], Some("IMAGE.TGA")).unwrap() == pixmap);
+            } else {
+                assert!(by_magic.unwrap() == pixmap, "{}", extension);
+            }
+        }
+
+        assert!(ImageRegistry::empty().read(&b"P6\n1 1\n255\n\0\0\0"[..], None).is_err());
+    }
+}
@@ -3,8 +3,26 @@ extern crate sdl2;
 #[macro_use]
 extern crate log;
 
+pub mod simd;
 pub mod vector3d;
+pub mod matrix;
+pub mod color;
 pub mod linerasterizer;
+pub mod trianglerasterizer;
 pub mod pixmap;
+pub mod texture;
+pub mod sampler;
+pub mod depthbuffer;
+pub mod zlib;
 pub mod tgaimage;
+pub mod netpbm;
+pub mod bmp;
+pub mod png;
+pub mod image;
 pub mod model;
+pub mod wavefront;
+pub mod shading;
+pub mod shader;
+pub mod clip;
+pub mod canvas;
+pub mod demo;
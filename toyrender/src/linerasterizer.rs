@@ -1,121 +1,454 @@
-extern crate sdl2;
+use std::i32;
 
-// use std::ops::{ Sub, Index, Mul, Add, IndexMut };
+use vector3d::{Vec3f, Vec3i};
 
-use num;
-// use num::traits::{ Num, Zero, One };
+const SIZE: usize = 3;
 
-use vector3d::Vec3i;
+/// Inclusive bounds of the points a `LineRasterizer` yields.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClipBox {
+    pub min: Vec3i,
+    pub max: Vec3i,
+}
 
-const SIZE: usize = 3;
+impl ClipBox {
+    pub fn new(min: Vec3i, max: Vec3i) -> ClipBox {
+        ClipBox { min: min, max: max }
+    }
 
+    /// Pixels of a `w` x `h` target at any depth.
+    pub fn screen(w: usize, h: usize) -> ClipBox {
+        ClipBox::new(Vec3i::new(0, 0, i32::MIN),
+                     Vec3i::new(w as i32 - 1, h as i32 - 1, i32::MAX))
+    }
+
+    pub fn contains(&self, p: Vec3i) -> bool {
+        (0..SIZE).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+}
+
+/// Position on the line together with the Bresenham error terms of the minor axes.
+#[derive(Copy, Clone, Debug)]
+struct Cursor {
+    pos: Vec3i,
+    err: [i64; SIZE],
+}
+
+/// 3D Bresenham line from `from` to `to`, both included. The `k`-th point moves `k`
+/// steps along the major axis and `floor(k * d / d_major)` steps along the others, so
+/// clipping only narrows the range of `k` and never changes the visible points. The
+/// line can be walked from both ends.
+#[derive(Clone, Debug)]
 pub struct LineRasterizer {
     from: Vec3i,
-    to: Vec3i,
-
     step: Vec3i,
-    d: Vec3i,
+    /// Absolute distance along each axis.
+    d: [i64; SIZE],
     major_axis: usize,
-    first_step: bool,
+
+    front: Cursor,
+    back: Cursor,
+    remaining: usize,
 }
 
 impl LineRasterizer {
     pub fn new(from: Vec3i, to: Vec3i) -> LineRasterizer {
-        let mut n = from;
-        let mut s = n;
+        let everything = ClipBox::new(Vec3i::new(i32::MIN, i32::MIN, i32::MIN),
+                                      Vec3i::new(i32::MAX, i32::MAX, i32::MAX));
+        LineRasterizer::with_clip(from, to, everything)
+    }
 
-        let mut axis = 0;
-        let mut max = 0;
+    /// Only the points of the line inside `clip`.
+    pub fn with_clip(from: Vec3i, to: Vec3i, clip: ClipBox) -> LineRasterizer {
+        let mut step = from;
+        let mut d = [0; SIZE];
+        let mut major_axis = 0;
         for i in 0..SIZE {
-            n[i] = 0;
+            let delta = to[i] as i64 - from[i] as i64;
+            step[i] = if delta > 0 { 1 } else { -1 };
+            d[i] = delta.abs();
+            if d[i] > d[major_axis] {
+                major_axis = i;
+            }
+        }
 
-            let d = to[i] - from[i];
-            s[i] = if d > 0 {
-                1
-            } else {
-                -1
-            };
+        let mut raster = LineRasterizer {
+            from: from,
+            step: step,
+            d: d,
+            major_axis: major_axis,
+            front: Cursor { pos: from, err: [0; SIZE] },
+            back: Cursor { pos: from, err: [0; SIZE] },
+            remaining: 0,
+        };
+
+        // every coordinate is monotonic in `k`, the visible part is a single range
+        let mut first = 0;
+        let mut last = d[major_axis];
+        for i in 0..SIZE {
+            let (lo, hi) = raster.visible_steps(i, clip);
+            first = first.max(lo);
+            last = last.min(hi);
+        }
+        if first <= last {
+            raster.front = raster.cursor(first);
+            raster.back = raster.cursor(last);
+            raster.remaining = (last - first + 1) as usize;
+        }
+        raster
+    }
 
-            let d = num::abs(d);
-            if d > max {
-                max = d;
-                axis = i as usize;
-            };
+    /// Range of `k` for which axis `i` of the `k`-th point lies inside `clip`, empty
+    /// if `lo > hi`.
+    fn visible_steps(&self, i: usize, clip: ClipBox) -> (i64, i64) {
+        let (n, r) = (self.d[self.major_axis] as i128, self.d[i] as i128);
+        let f = self.from[i] as i128;
+        let (min, max) = (clip.min[i] as i128, clip.max[i] as i128);
+        if r == 0 {
+            return if f >= min && f <= max { (0, n as i64) } else { (1, 0) };
         }
 
-        LineRasterizer {
-            from: from,
-            to: to,
+        // the coordinate is `f + step * q` with `q = floor(k * r / n)` moves so far
+        let (q_min, q_max) = if self.step[i] > 0 { (min - f, max - f) } else { (f - max, f - min) };
+        let q_min = q_min.max(0);
+        if q_max < q_min {
+            return (1, 0);
+        }
+        let first = (q_min * n + r - 1) / r;
+        let last = (((q_max + 1) * n - 1) / r).min(n);
+        (first as i64, last as i64)
+    }
 
-            step: s,
-            d: n,
-            major_axis: axis,
-            first_step: true,
+    /// The `k`-th point of the line.
+    fn cursor(&self, k: i64) -> Cursor {
+        let n = self.d[self.major_axis];
+        let mut cursor = Cursor { pos: self.from, err: [0; SIZE] };
+        for i in 0..SIZE {
+            if n == 0 {
+                break;
+            }
+            let moved = k as i128 * self.d[i] as i128;
+            cursor.pos[i] += self.step[i] * (moved / n as i128) as i32;
+            cursor.err[i] = (moved % n as i128) as i64;
         }
+        cursor
     }
 
+    fn advance(&self, cursor: &mut Cursor) {
+        let n = self.d[self.major_axis];
+        for i in 0..SIZE {
+            if i == self.major_axis {
+                cursor.pos[i] += self.step[i];
+                continue;
+            }
+            cursor.err[i] += self.d[i];
+            if cursor.err[i] >= n {
+                cursor.err[i] -= n;
+                cursor.pos[i] += self.step[i];
+            }
+        }
+    }
+
+    fn retreat(&self, cursor: &mut Cursor) {
+        let n = self.d[self.major_axis];
+        for i in 0..SIZE {
+            if i == self.major_axis {
+                cursor.pos[i] -= self.step[i];
+                continue;
+            }
+            if cursor.err[i] < self.d[i] {
+                cursor.err[i] += n - self.d[i];
+                cursor.pos[i] -= self.step[i];
+            } else {
+                cursor.err[i] -= self.d[i];
+            }
+        }
+    }
+}
+
+impl Iterator for LineRasterizer {
+    type Item = Vec3i;
+
     #[inline]
-    pub fn has_next(&mut self) -> bool {
-        if self.from == self.to && !self.first_step {
-            return false;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let p = self.front.pos;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            let mut front = self.front;
+            self.advance(&mut front);
+            self.front = front;
         }
-        return true;
+        Some(p)
     }
 
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for LineRasterizer {
     #[inline]
-    pub fn next_point(&mut self) -> bool {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let p = self.back.pos;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            let mut back = self.back;
+            self.retreat(&mut back);
+            self.back = back;
+        }
+        Some(p)
+    }
+}
+
+impl ExactSizeIterator for LineRasterizer {}
+
+/// Fractional part, `x - floor(x)`.
+fn fpart(x: f32) -> f32 {
+    x - x.floor()
+}
+
+/// Anti-aliased line after Xiaolin Wu. Every column along the major axis is covered
+/// by the two pixels closest to the line, weighted by their distance to it, the end
+/// columns by the part of them the line actually spans. Like with `LineRasterizer`
+/// pixels are centered on integer coordinates, depth is interpolated along the line.
+/// Yields points together with their coverage in `(0, 1]`.
+pub struct WuLineRasterizer {
+    from: Vec3f,
+    to: Vec3f,
+    /// `x` and `y` are exchanged, the line is steeper than 45 degrees.
+    steep: bool,
+    gradient: f32,
+
+    column: i32,
+    last_column: i32,
+    pending: Option<(Vec3i, f32)>,
+}
 
-        if !self.has_next() {
-            return false;
+impl WuLineRasterizer {
+    pub fn new(from: Vec3f, to: Vec3f) -> WuLineRasterizer {
+        let steep = (to.y - from.y).abs() > (to.x - from.x).abs();
+        let swap = |v: Vec3f| if steep { Vec3f::new(v.y, v.x, v.z) } else { v };
+        let (mut from, mut to) = (swap(from), swap(to));
+        if from.x > to.x {
+            ::std::mem::swap(&mut from, &mut to);
         }
 
-        if self.first_step {
-            self.first_step = false;
-            return true;
+        let dx = to.x - from.x;
+        WuLineRasterizer {
+            from: from,
+            to: to,
+            steep: steep,
+            gradient: if dx == 0.0 { 0.0 } else { (to.y - from.y) / dx },
+            column: (from.x + 0.5).floor() as i32,
+            last_column: (to.x + 0.5).floor() as i32,
+            pending: None,
         }
+    }
 
-        let from = self.from;
-        let to = self.to;
-        let calc_rs = |axis| num::abs(to[axis] - from[axis]);
+    fn point(&self, major: i32, minor: i32, t: f32) -> Vec3i {
+        let z = self.from.z + (self.to.z - self.from.z) * t;
+        if self.steep {
+            Vec3i::new(minor, major, z.round() as i32)
+        } else {
+            Vec3i::new(major, minor, z.round() as i32)
+        }
+    }
+}
 
-        self.from[self.major_axis] += self.step[self.major_axis];
+impl Iterator for WuLineRasterizer {
+    type Item = (Vec3i, f32);
 
-        let rs_base = calc_rs(self.major_axis);
-        for i in 0..SIZE {
-            let rs = calc_rs(i);
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
 
-            if rs > 0 && i != self.major_axis {
-                self.d[i] += rs;
-                if self.d[i] >= rs_base {
-                    self.d[i] -= rs_base;
-                    self.from[i] += self.step[i];
-                }
+        while self.column <= self.last_column {
+            let c = self.column;
+            self.column += 1;
+
+            // part of the column between the end points
+            let center = c as f32;
+            let gap = self.to.x.min(center + 0.5) - self.from.x.max(center - 0.5);
+            if gap <= 0.0 {
+                continue;
+            }
+
+            let length = self.to.x - self.from.x;
+            let t = ((center - self.from.x) / length).max(0.0).min(1.0);
+            let y = self.from.y + self.gradient * (center - self.from.x);
+            let minor = y.floor() as i32;
+            let upper = (1.0 - fpart(y)) * gap;
+            let lower = fpart(y) * gap;
+
+            let first = (self.point(c, minor, t), upper);
+            let second = (self.point(c, minor + 1, t), lower);
+            if upper <= 0.0 {
+                return Some(second);
+            }
+            if lower > 0.0 {
+                self.pending = Some(second);
             }
+            return Some(first);
         }
-        return true;
+        None
     }
+}
 
-    #[inline]
-    pub fn point(&self) -> Vec3i {
-        return self.from;
+/// End of a `ThickLineRasterizer` line.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineCap {
+    /// Ends exactly at the end point.
+    Butt,
+    /// Extends past the end point by half the width.
+    Square,
+    /// Half circle around the end point.
+    Round,
+}
+
+/// Line of arbitrary width with caps, anti-aliased by testing `samples` x `samples`
+/// points in every pixel. Pixels are centered on integer coordinates, depth is
+/// interpolated along the line. Yields points together with their coverage in `(0, 1]`.
+pub struct ThickLineRasterizer {
+    from: Vec3f,
+    /// Unit direction and length of the line.
+    dir: (f32, f32),
+    length: f32,
+    dz: f32,
+    half_width: f32,
+    /// Distance from the line which contains everything the caps add.
+    reach: f32,
+    cap: LineCap,
+    samples: u32,
+
+    /// Pixels are visited row by row along the major axis, `x` and `y` are exchanged
+    /// if that is the `y` axis.
+    steep: bool,
+    major: i32,
+    last_major: i32,
+    minor: i32,
+    last_minor: i32,
+}
+
+impl ThickLineRasterizer {
+    pub fn new(from: Vec3f,
+               to: Vec3f,
+               width: f32,
+               cap: LineCap,
+               samples: u32)
+               -> ThickLineRasterizer {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        let dir = if length > 0.0 { (dx / length, dy / length) } else { (1.0, 0.0) };
+        let half_width = width.max(0.0) / 2.0;
+        let reach = match cap {
+            LineCap::Butt | LineCap::Round => half_width,
+            LineCap::Square => half_width * 2f32.sqrt(),
+        };
+
+        let steep = dy.abs() > dx.abs();
+        let (a, b) = if steep { (from.y, to.y) } else { (from.x, to.x) };
+        let mut raster = ThickLineRasterizer {
+            from: from,
+            dir: dir,
+            length: length,
+            dz: to.z - from.z,
+            half_width: half_width,
+            reach: reach,
+            cap: cap,
+            samples: samples.max(1),
+            steep: steep,
+            major: (a.min(b) - reach - 0.5).floor() as i32,
+            last_major: (a.max(b) + reach + 0.5).ceil() as i32,
+            minor: 0,
+            last_minor: 0,
+        };
+        raster.start_row();
+        raster
     }
 
-    #[inline]
-    pub fn end_point(&self) -> Vec3i {
-        return self.to;
+    /// Range of the current row which may be covered. The distance to the infinite
+    /// line is at most the distance to the segment, so only a band around it is visited.
+    fn start_row(&mut self) {
+        let (dir_major, dir_minor, from_major, from_minor) = if self.steep {
+            (self.dir.1, self.dir.0, self.from.y, self.from.x)
+        } else {
+            (self.dir.0, self.dir.1, self.from.x, self.from.y)
+        };
+
+        // `dir_major` is at least `1 / sqrt(2)`
+        let slope = dir_minor / dir_major;
+        let center = from_minor + slope * (self.major as f32 - from_major);
+        let extent = self.reach / dir_major.abs() + 0.5 * (1.0 + slope.abs());
+        self.minor = (center - extent).floor() as i32;
+        self.last_minor = (center + extent).ceil() as i32;
+    }
+
+    fn inside(&self, x: f32, y: f32) -> bool {
+        let (px, py) = (x - self.from.x, y - self.from.y);
+        let along = px * self.dir.0 + py * self.dir.1;
+        let across = (px * self.dir.1 - py * self.dir.0).abs();
+        let h = self.half_width;
+        match self.cap {
+            LineCap::Butt => across <= h && along >= 0.0 && along <= self.length,
+            LineCap::Square => across <= h && along >= -h && along <= self.length + h,
+            LineCap::Round => {
+                let beyond = along.min(0.0) + (along - self.length).max(0.0);
+                beyond * beyond + across * across <= h * h
+            }
+        }
+    }
+
+    fn coverage(&self, x: i32, y: i32) -> f32 {
+        let n = self.samples;
+        let offset = |i: u32| (i as f32 + 0.5) / n as f32 - 0.5;
+        let mut hits = 0;
+        for i in 0..n {
+            for j in 0..n {
+                if self.inside(x as f32 + offset(i), y as f32 + offset(j)) {
+                    hits += 1;
+                }
+            }
+        }
+        hits as f32 / (n * n) as f32
     }
 }
 
-impl Iterator for LineRasterizer {
-    type Item = Vec3i;
+impl Iterator for ThickLineRasterizer {
+    type Item = (Vec3i, f32);
 
-    #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.has_next() {
-            None
-        } else {
-            self.next_point();
-            Some(self.point())
+        loop {
+            while self.minor <= self.last_minor {
+                let minor = self.minor;
+                self.minor += 1;
+
+                let (x, y) = if self.steep { (minor, self.major) } else { (self.major, minor) };
+                let coverage = self.coverage(x, y);
+                if coverage > 0.0 {
+                    let along = (x as f32 - self.from.x) * self.dir.0 +
+                                (y as f32 - self.from.y) * self.dir.1;
+                    let t = if self.length > 0.0 {
+                        (along / self.length).max(0.0).min(1.0)
+                    } else {
+                        0.0
+                    };
+                    let z = self.from.z + self.dz * t;
+                    return Some((Vec3i::new(x, y, z.round() as i32), coverage));
+                }
+            }
+
+            if self.major >= self.last_major {
+                return None;
+            }
+            self.major += 1;
+            self.start_row();
         }
     }
 }
@@ -159,8 +492,8 @@ impl Iterator for LineRasterizer {
 
 #[cfg(test)]
 mod tests {
-    use super::LineRasterizer;
-    use vector3d::Vec3i;
+    use super::{ClipBox, LineCap, LineRasterizer, ThickLineRasterizer, WuLineRasterizer};
+    use vector3d::{Vec3f, Vec3i};
 
     #[test]
     fn test_rasterizer_simple() {
@@ -221,4 +554,118 @@ mod tests {
             break;
         }
     }
+
+    #[test]
+    fn test_rasterizer_clipped() {
+        let mut seed = 3u32;
+        let mut random = |range: i32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            ((seed >> 8) % (2 * range as u32 + 1)) as i32 - range
+        };
+        for _ in 0..500 {
+            let from = Vec3i::new(random(40), random(40), random(40));
+            let to = Vec3i::new(random(40), random(40), random(40));
+            let a = Vec3i::new(random(20), random(20), random(20));
+            let b = Vec3i::new(random(20), random(20), random(20));
+            let min = Vec3i::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+            let max = Vec3i::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+            let clip = ClipBox::new(min, max);
+
+            // clipping keeps exactly the visible points of the full line
+            let full: Vec<_> = LineRasterizer::new(from, to).collect();
+            let expected: Vec<_> = full.iter().cloned().filter(|&p| clip.contains(p)).collect();
+            let clipped = LineRasterizer::with_clip(from, to, clip);
+            assert_eq!(clipped.len(), expected.len());
+            assert_eq!(clipped.clone().collect::<Vec<_>>(), expected);
+
+            let mut reversed: Vec<_> = clipped.rev().collect();
+            reversed.reverse();
+            assert_eq!(reversed, expected);
+        }
+    }
+
+    #[test]
+    fn test_rasterizer_both_ends() {
+        let mut raster = LineRasterizer::new(Vec3i::new(0, 0, 0), Vec3i::new(7, 3, -2));
+        assert_eq!(raster.len(), 8);
+        assert_eq!(raster.next(), Some(Vec3i::new(0, 0, 0)));
+        assert_eq!(raster.next_back(), Some(Vec3i::new(7, 3, -2)));
+        assert_eq!(raster.len(), 6);
+        let middle: Vec<_> = raster.collect();
+        assert_eq!(middle.len(), 6);
+
+        // far off screen, only the visible part is walked
+        let clip = ClipBox::screen(800, 600);
+        let raster = LineRasterizer::with_clip(Vec3i::new(-2000000000, 300, 0),
+                                               Vec3i::new(2000000000, 301, 0),
+                                               clip);
+        assert_eq!(raster.len(), 800);
+        assert!(raster.clone().all(|p| p.y() == 300 || p.y() == 301));
+        assert_eq!(raster.last(), Some(Vec3i::new(799, 300, 0)));
+
+        let outside = LineRasterizer::with_clip(Vec3i::new(-5, -5, 0), Vec3i::new(-1, 10, 0), clip);
+        assert_eq!(outside.len(), 0);
+    }
+
+    #[test]
+    fn test_wu_coverage() {
+        // horizontal line between pixel centers, the coverage is split evenly
+        let line = WuLineRasterizer::new(Vec3f::new(0.0, 2.5, 0.0), Vec3f::new(4.0, 2.5, 8.0));
+        let points: Vec<_> = line.collect();
+        assert_eq!(points.len(), 10);
+        for &(p, coverage) in &points {
+            assert!(p.y() == 2 || p.y() == 3);
+            let expected = if p.x() == 0 || p.x() == 4 { 0.25 } else { 0.5 };
+            assert!((coverage - expected).abs() < 1e-6, "{:?} {}", p, coverage);
+        }
+        assert_eq!(points[4].0, Vec3i::new(2, 2, 4));
+
+        // a diagonal through pixel centers covers exactly those pixels, left to right
+        let line = WuLineRasterizer::new(Vec3f::new(3.0, 0.0, 0.0), Vec3f::new(0.0, 3.0, 0.0));
+        let points: Vec<_> = line.collect();
+        let full: Vec<_> = points.iter()
+                                 .filter(|p| p.1 > 0.99)
+                                 .map(|p| (p.0.x(), p.0.y()))
+                                 .collect();
+        assert_eq!(full, vec![(1, 2), (2, 1)]);
+        let total: f32 = points.iter().map(|p| p.1).sum();
+        assert!((total - 3.0).abs() < 1e-5);
+
+        // steep lines are split between columns
+        let line = WuLineRasterizer::new(Vec3f::new(1.25, 0.0, 0.0), Vec3f::new(1.25, 4.0, 0.0));
+        for (p, coverage) in line {
+            let expected = if p.x() == 1 { 0.75 } else { 0.25 };
+            let expected = if p.y() == 0 || p.y() == 4 { expected / 2.0 } else { expected };
+            assert!((coverage - expected).abs() < 1e-6, "{:?} {}", p, coverage);
+        }
+
+        let point = Vec3f::new(1.0, 1.0, 0.0);
+        assert_eq!(WuLineRasterizer::new(point, point).count(), 0);
+    }
+
+    #[test]
+    fn test_thick_line() {
+        let area = |from: Vec3f, to: Vec3f, width: f32, cap: LineCap| {
+            ThickLineRasterizer::new(from, to, width, cap, 8).map(|p| p.1).sum::<f32>()
+        };
+        let (a, b) = (Vec3f::new(10.0, 10.0, 0.0), Vec3f::new(30.0, 10.0, 0.0));
+        assert!((area(a, b, 4.0, LineCap::Butt) - 80.0).abs() < 1.0);
+        assert!((area(a, b, 4.0, LineCap::Square) - 96.0).abs() < 1.0);
+        let round = 80.0 + ::std::f32::consts::PI * 4.0;
+        assert!((area(a, b, 4.0, LineCap::Round) - round).abs() < 1.0);
+
+        // rotating the line keeps its area
+        let c = Vec3f::new(10.0 + 20.0 * 0.6, 10.0 + 20.0 * 0.8, 0.0);
+        assert!((area(a, c, 4.0, LineCap::Butt) - 80.0).abs() < 1.0);
+        assert!((area(a, c, 4.0, LineCap::Round) - round).abs() < 1.0);
+
+        let dot = area(a, a, 3.0, LineCap::Round);
+        assert!((dot - ::std::f32::consts::PI * 2.25).abs() < 0.5);
+        assert_eq!(area(a, a, 3.0, LineCap::Butt), 0.0);
+
+        for (p, coverage) in ThickLineRasterizer::new(a, c, 2.0, LineCap::Round, 4) {
+            assert!(coverage > 0.0 && coverage <= 1.0);
+            assert!(p.x() >= 8 && p.x() <= 24 && p.y() >= 8 && p.y() <= 28);
+        }
+    }
 }
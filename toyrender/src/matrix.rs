@@ -0,0 +1,460 @@
+use std::ops::{Add, Index, IndexMut, Mul, Sub};
+
+use simd::F32x4;
+use vector3d::Vec3f;
+
+/// Homogeneous coordinate.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Vector4<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+pub type Vec4f = Vector4<f32>;
+
+impl<T> Vector4<T> where T: Copy + Clone
+{
+    pub fn new(x: T, y: T, z: T, w: T) -> Vector4<T> {
+        Vector4 {
+            x: x,
+            y: y,
+            z: z,
+            w: w,
+        }
+    }
+}
+
+impl Vector4<f32> {
+    /// Position, affected by translation.
+    pub fn point(v: Vec3f) -> Vec4f {
+        Vector4::new(v.x, v.y, v.z, 1.0)
+    }
+
+    /// Direction, not affected by translation.
+    pub fn direction(v: Vec3f) -> Vec4f {
+        Vector4::new(v.x, v.y, v.z, 0.0)
+    }
+
+    pub fn xyz(&self) -> Vec3f {
+        Vec3f::new(self.x, self.y, self.z)
+    }
+
+    /// Perspective divide.
+    pub fn project(&self) -> Vec3f {
+        self.xyz() * (1.0 / self.w)
+    }
+
+    #[inline]
+    pub fn simd(self) -> F32x4 {
+        F32x4::new(self.x, self.y, self.z, self.w)
+    }
+
+    #[inline]
+    pub fn from_simd(v: F32x4) -> Vec4f {
+        let a = v.to_array();
+        Vector4::new(a[0], a[1], a[2], a[3])
+    }
+}
+
+impl Add for Vector4<f32> {
+    type Output = Vec4f;
+
+    #[inline]
+    fn add(self, other: Vec4f) -> Vec4f {
+        Vec4f::from_simd(self.simd() + other.simd())
+    }
+}
+
+impl Sub for Vector4<f32> {
+    type Output = Vec4f;
+
+    #[inline]
+    fn sub(self, other: Vec4f) -> Vec4f {
+        Vec4f::from_simd(self.simd() - other.simd())
+    }
+}
+
+impl Mul for Vector4<f32> {
+    type Output = f32;
+
+    #[inline]
+    fn mul(self, other: Vec4f) -> f32 {
+        self.simd().dot(other.simd())
+    }
+}
+
+impl Mul<f32> for Vector4<f32> {
+    type Output = Vec4f;
+
+    #[inline]
+    fn mul(self, other: f32) -> Vec4f {
+        Vec4f::from_simd(self.simd() * other)
+    }
+}
+
+impl<T> Index<usize> for Vector4<T> {
+    type Output = T;
+
+    fn index<'a>(&'a self, index: usize) -> &'a Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            3 => &self.w,
+            _ => panic!("Vector4 index {} out of range", index),
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for Vector4<T> {
+    fn index_mut<'a>(&'a mut self, index: usize) -> &'a mut Self::Output {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            3 => &mut self.w,
+            _ => panic!("Vector4 index {} out of range", index),
+        }
+    }
+}
+
+/// Row major 4x4 matrix, vectors are columns and multiplied from the right. Builders
+/// follow the OpenGL conventions: right handed, the camera looks down `-z` and clip
+/// space depth ranges from `-1` at the near to `1` at the far plane.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Matrix4 {
+    m: [[f32; 4]; 4],
+}
+
+impl Default for Matrix4 {
+    fn default() -> Matrix4 {
+        Matrix4::identity()
+    }
+}
+
+impl Matrix4 {
+    pub fn new(rows: [[f32; 4]; 4]) -> Matrix4 {
+        Matrix4 { m: rows }
+    }
+
+    pub fn identity() -> Matrix4 {
+        Matrix4::new([[1.0, 0.0, 0.0, 0.0],
+                      [0.0, 1.0, 0.0, 0.0],
+                      [0.0, 0.0, 1.0, 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn translation(t: Vec3f) -> Matrix4 {
+        Matrix4::new([[1.0, 0.0, 0.0, t.x],
+                      [0.0, 1.0, 0.0, t.y],
+                      [0.0, 0.0, 1.0, t.z],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn scale(s: Vec3f) -> Matrix4 {
+        Matrix4::new([[s.x, 0.0, 0.0, 0.0],
+                      [0.0, s.y, 0.0, 0.0],
+                      [0.0, 0.0, s.z, 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// Counter-clockwise rotation by `angle` radians around `axis`.
+    pub fn rotation(axis: Vec3f, angle: f32) -> Matrix4 {
+        let a = axis.normalized();
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+
+        Matrix4::new([[t * a.x * a.x + c, t * a.x * a.y - s * a.z, t * a.x * a.z + s * a.y, 0.0],
+                      [t * a.x * a.y + s * a.z, t * a.y * a.y + c, t * a.y * a.z - s * a.x, 0.0],
+                      [t * a.x * a.z - s * a.y, t * a.y * a.z + s * a.x, t * a.z * a.z + c, 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn rotation_x(angle: f32) -> Matrix4 {
+        Matrix4::rotation(Vec3f::new(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn rotation_y(angle: f32) -> Matrix4 {
+        Matrix4::rotation(Vec3f::new(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn rotation_z(angle: f32) -> Matrix4 {
+        Matrix4::rotation(Vec3f::new(0.0, 0.0, 1.0), angle)
+    }
+
+    /// View matrix of a camera at `eye` looking at `center`.
+    pub fn look_at(eye: Vec3f, center: Vec3f, up: Vec3f) -> Matrix4 {
+        let z = (eye - center).normalized();
+        let x = (up ^ z).normalized();
+        let y = z ^ x;
+
+        Matrix4::new([[x.x, x.y, x.z, -(x * eye)],
+                      [y.x, y.y, y.z, -(y * eye)],
+                      [z.x, z.y, z.z, -(z * eye)],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// Perspective projection with a vertical field of view of `fovy` radians.
+    pub fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4 {
+        let f = 1.0 / (fovy / 2.0).tan();
+        let depth = near - far;
+
+        Matrix4::new([[f / aspect, 0.0, 0.0, 0.0],
+                      [0.0, f, 0.0, 0.0],
+                      [0.0, 0.0, (far + near) / depth, 2.0 * far * near / depth],
+                      [0.0, 0.0, -1.0, 0.0]])
+    }
+
+    pub fn orthographic(left: f32,
+                        right: f32,
+                        bottom: f32,
+                        top: f32,
+                        near: f32,
+                        far: f32)
+                        -> Matrix4 {
+        let w = right - left;
+        let h = top - bottom;
+        let d = far - near;
+
+        Matrix4::new([[2.0 / w, 0.0, 0.0, -(right + left) / w],
+                      [0.0, 2.0 / h, 0.0, -(top + bottom) / h],
+                      [0.0, 0.0, -2.0 / d, -(far + near) / d],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// Maps normalized device coordinates to the `w` x `h` pixel rectangle at `(x, y)`
+    /// with `y` growing downwards, and depth from `near` at the near to `far` at the far
+    /// plane. `(0, 1)` suits the default depth test, `(1, 0)` gives reversed-Z.
+    pub fn viewport(x: f32, y: f32, w: f32, h: f32, near: f32, far: f32) -> Matrix4 {
+        Matrix4::new([[w / 2.0, 0.0, 0.0, x + w / 2.0],
+                      [0.0, -h / 2.0, 0.0, y + h / 2.0],
+                      [0.0, 0.0, (far - near) / 2.0, (far + near) / 2.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn row(&self, i: usize) -> Vec4f {
+        let r = self.m[i];
+        Vector4::new(r[0], r[1], r[2], r[3])
+    }
+
+    pub fn column(&self, j: usize) -> Vec4f {
+        Vector4::new(self.m[0][j], self.m[1][j], self.m[2][j], self.m[3][j])
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut t = Matrix4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    /// Inverse by Gauss-Jordan elimination with partial pivoting, `None` if the matrix
+    /// is singular or not finite.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inv = Matrix4::identity().m;
+
+        for col in 0..4 {
+            // NaN sorts above every number, so a NaN in the column becomes the pivot
+            let pivot = (col..4)
+                            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                            .unwrap();
+            if !a[pivot][col].is_finite() || a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let k = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= k;
+                inv[col][j] *= k;
+            }
+
+            for i in 0..4 {
+                if i == col {
+                    continue;
+                }
+                let f = a[i][col];
+                for j in 0..4 {
+                    a[i][j] -= f * a[col][j];
+                    inv[i][j] -= f * inv[col][j];
+                }
+            }
+        }
+        Some(Matrix4::new(inv))
+    }
+
+    /// Transforms a position and divides by `w`.
+    pub fn transform_point(&self, p: Vec3f) -> Vec3f {
+        (*self * Vector4::point(p)).project()
+    }
+
+    /// Transforms a direction, translation is ignored.
+    pub fn transform_vector(&self, v: Vec3f) -> Vec3f {
+        (*self * Vector4::direction(v)).xyz()
+    }
+}
+
+impl Index<(usize, usize)> for Matrix4 {
+    type Output = f32;
+
+    fn index<'a>(&'a self, (i, j): (usize, usize)) -> &'a f32 {
+        &self.m[i][j]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix4 {
+    fn index_mut<'a>(&'a mut self, (i, j): (usize, usize)) -> &'a mut f32 {
+        &mut self.m[i][j]
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        // every row of the product combines the rows of `other`
+        let rows = [other.row(0).simd(), other.row(1).simd(), other.row(2).simd(), other.row(3).simd()];
+        let mut m = [[0.0; 4]; 4];
+        for i in 0..4 {
+            let r = self.m[i];
+            let row = rows[0] * r[0] + rows[1] * r[1] + rows[2] * r[2] + rows[3] * r[3];
+            m[i] = row.to_array();
+        }
+        Matrix4::new(m)
+    }
+}
+
+impl Mul<Vec4f> for Matrix4 {
+    type Output = Vec4f;
+
+    #[inline]
+    fn mul(self, v: Vec4f) -> Vec4f {
+        let columns = self.column(0).simd() * v.x + self.column(1).simd() * v.y +
+                      self.column(2).simd() * v.z + self.column(3).simd() * v.w;
+        Vec4f::from_simd(columns)
+    }
+}
+
+/// Model, view and projection transforms applied to every vertex, in that order.
+/// The viewport transform is applied by the canvas after clipping.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Transforms {
+    /// Places the model in the world.
+    pub model: Matrix4,
+    /// Moves the world in front of the camera.
+    pub view: Matrix4,
+    /// Camera space to clip space.
+    pub projection: Matrix4,
+}
+
+impl Transforms {
+    /// Model space to clip space.
+    pub fn model_view_projection(&self) -> Matrix4 {
+        self.projection * self.view * self.model
+    }
+
+    /// Transforms model space normals to world space, unlike `model` it keeps them
+    /// perpendicular to the surface under non-uniform scaling.
+    pub fn normal_matrix(&self) -> Matrix4 {
+        self.model.inverse().unwrap_or_default().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::{Matrix4, Transforms, Vec4f};
+    use vector3d::Vec3f;
+
+    const EPS: f32 = 1e-5;
+
+    fn assert_close(a: Vec3f, b: Vec3f) {
+        assert!((a - b).norm() < EPS, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_multiply() {
+        let t = Matrix4::translation(Vec3f::new(1.0, 2.0, 3.0));
+        let s = Matrix4::scale(Vec3f::new(2.0, 2.0, 2.0));
+        let p = Vec3f::new(1.0, 1.0, 1.0);
+
+        assert_eq!((t * s).transform_point(p), Vec3f::new(3.0, 4.0, 5.0));
+        assert_eq!((s * t).transform_point(p), Vec3f::new(4.0, 6.0, 8.0));
+        assert_eq!(t.transform_vector(p), p);
+        assert_eq!(t * Matrix4::identity(), t);
+        assert_eq!(t * Vec4f::new(0.0, 0.0, 0.0, 2.0), Vec4f::new(2.0, 4.0, 6.0, 2.0));
+    }
+
+    #[test]
+    fn test_inverse_transpose() {
+        let m = Matrix4::translation(Vec3f::new(1.0, -2.0, 3.0)) *
+                Matrix4::rotation(Vec3f::new(1.0, 1.0, 0.0), 0.7) *
+                Matrix4::scale(Vec3f::new(2.0, 3.0, 4.0));
+        let inv = m.inverse().unwrap();
+        let id = m * inv;
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((id[(i, j)] - expected).abs() < EPS);
+            }
+        }
+
+        assert_eq!(m.transpose()[(0, 3)], m[(3, 0)]);
+        assert_eq!(m.transpose().transpose(), m);
+        assert_eq!(Matrix4::scale(Vec3f::new(1.0, 0.0, 1.0)).inverse(), None);
+        assert_eq!(Matrix4::scale(Vec3f::new(1.0, f32::NAN, 1.0)).inverse(), None);
+        assert_eq!(Matrix4::translation(Vec3f::new(f32::NAN, 0.0, 0.0)).inverse(), None);
+        assert_eq!(Matrix4::scale(Vec3f::new(f32::INFINITY, 1.0, 1.0)).inverse(), None);
+
+        // degenerate model transforms fall back to the identity instead of panicking
+        let transforms = Transforms {
+            model: Matrix4::scale(Vec3f::new(f32::NAN, 1.0, 1.0)),
+            ..Default::default()
+        };
+        assert_eq!(transforms.normal_matrix(), Matrix4::identity());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let r = Matrix4::rotation_z(PI / 2.0);
+        assert_close(r.transform_point(Vec3f::new(1.0, 0.0, 0.0)), Vec3f::new(0.0, 1.0, 0.0));
+
+        let r = Matrix4::rotation_y(PI / 2.0);
+        assert_close(r.transform_point(Vec3f::new(0.0, 0.0, 1.0)), Vec3f::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_camera() {
+        let eye = Vec3f::new(0.0, 0.0, 5.0);
+        let view = Matrix4::look_at(eye, Vec3f::zero(), Vec3f::new(0.0, 1.0, 0.0));
+        assert_close(view.transform_point(eye), Vec3f::zero());
+        assert_close(view.transform_point(Vec3f::zero()), Vec3f::new(0.0, 0.0, -5.0));
+
+        let projection = Matrix4::perspective(PI / 2.0, 2.0, 1.0, 10.0);
+        assert_close(projection.transform_point(Vec3f::new(2.0, 1.0, -1.0)),
+                     Vec3f::new(1.0, 1.0, -1.0));
+        assert_close(projection.transform_point(Vec3f::new(0.0, 0.0, -10.0)),
+                     Vec3f::new(0.0, 0.0, 1.0));
+
+        let ortho = Matrix4::orthographic(-2.0, 2.0, -1.0, 1.0, 0.0, 10.0);
+        assert_close(ortho.transform_point(Vec3f::new(2.0, -1.0, -10.0)),
+                     Vec3f::new(1.0, -1.0, 1.0));
+
+        let viewport = Matrix4::viewport(0.0, 0.0, 800.0, 600.0, 0.0, 1.0);
+        assert_close(viewport.transform_point(Vec3f::new(-1.0, 1.0, -1.0)),
+                     Vec3f::new(0.0, 0.0, 0.0));
+        assert_close(viewport.transform_point(Vec3f::new(1.0, -1.0, 1.0)),
+                     Vec3f::new(800.0, 600.0, 1.0));
+        let reversed = Matrix4::viewport(0.0, 0.0, 800.0, 600.0, 1.0, 0.0);
+        assert_close(reversed.transform_point(Vec3f::new(0.0, 0.0, -1.0)),
+                     Vec3f::new(400.0, 300.0, 1.0));
+    }
+}
@@ -1,91 +1,381 @@
 use std::fs::File;
 use std::io;
-use std::io::{BufReader, BufRead};
+use std::io::{BufRead, BufReader, Error, Read};
+use std::ops::Range;
 use std::path::Path;
 
-use pixmap::Pixmap;
-use tgaimage::{ImageLoader, TgaImage};
+use image::ImageRegistry;
+use texture::{Downsample, Texture};
 use vector3d::{Vec3f, Vec3i};
+use wavefront::{parse_mtl, parse_obj, ObjError, ObjErrorKind, ObjResult};
 
 pub type Result<T> = io::Result<T>;
 
+/// Faces which share object, group, material and smoothing group.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubMesh {
+    pub object: Option<String>,
+    pub group: Option<String>,
+    pub material: Option<String>,
+    /// Zero when smoothing is off.
+    pub smoothing: u32,
+    /// Range in `Model::faces`.
+    pub faces: Range<usize>,
+}
+
+/// Coordinate system the normals of a normal map are stored in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalSpace {
+    /// Relative to the tangent frame of the surface, reusable across meshes.
+    Tangent,
+    /// Model space, only valid for the mesh the map was baked for.
+    Object,
+}
+
+/// Surface description from a `.mtl` library.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Vec3f,
+    pub diffuse: Vec3f,
+    pub specular: Vec3f,
+    /// Specular exponent.
+    pub shininess: f32,
+    /// Opacity, one is fully opaque.
+    pub dissolve: f32,
+    pub diffuse_map: Option<Texture>,
+    pub normal_map: Option<Texture>,
+    /// Tangent space unless the application knows better, MTL files can't tell.
+    pub normal_space: NormalSpace,
+    /// Scales `specular`.
+    pub specular_map: Option<Texture>,
+    /// Scales `shininess`.
+    pub shininess_map: Option<Texture>,
+}
+
+impl Default for Material {
+    /// Defaults of the MTL specification, except for a black `specular` as in other
+    /// loaders. With the default `shininess` of zero every lit fragment would be white.
+    fn default() -> Material {
+        Material {
+            name: String::new(),
+            ambient: Vec3f::new(0.2, 0.2, 0.2),
+            diffuse: Vec3f::new(0.8, 0.8, 0.8),
+            specular: Vec3f::zero(),
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_map: None,
+            normal_map: None,
+            normal_space: NormalSpace::Tangent,
+            specular_map: None,
+            shininess_map: None,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Model {
     pub verticies: Vec<Vec3f>,
+    /// Vertex, texture and normal index for every corner, `-1` if missing.
     pub faces: Vec<[Vec3i; 3]>,
     pub normals: Vec<Vec3f>,
-    pub diffuse: Pixmap,
+    /// Diffuse map of the first textured material, used by meshes without one.
+    pub diffuse: Texture,
     pub uv: Vec<[f32; 2]>,
+    /// Direction of increasing u and v per texture coordinate, see `compute_tangents`.
+    pub tangents: Vec<Vec3f>,
+    pub bitangents: Vec<Vec3f>,
+    pub meshes: Vec<SubMesh>,
+    /// Material libraries referenced by `mtllib`.
+    pub material_libs: Vec<String>,
+    pub materials: Vec<Material>,
 }
 
 impl Model {
-    fn new() -> Model {
-        Model { ..Default::default() }
+    /// Material assigned to the sub-mesh by `usemtl`.
+    pub fn material(&self, mesh: &SubMesh) -> Option<&Material> {
+        mesh.material.as_ref().and_then(|name| self.materials.iter().find(|m| m.name == *name))
     }
 
+    /// Sets the space of the normal maps of all materials, e.g. for a model whose
+    /// maps were baked in object space.
+    pub fn set_normal_space(&mut self, space: NormalSpace) {
+        for material in &mut self.materials {
+            material.normal_space = space;
+        }
+    }
+
+    /// Normalized texture coordinates of a corner, see `Sampler`.
     pub fn uv(&self, iface: usize, nvert: usize) -> Vec3f {
-        let idx = self.faces[iface][nvert][1] as usize;
+        let idx = self.faces[iface][nvert][1];
+        if idx < 0 {
+            return Vec3f::zero();
+        }
 
-        Vec3f::new(self.uv[idx][0] * self.diffuse.width() as f32,
-                   self.uv[idx][1] * self.diffuse.height() as f32,
-                   0.0)
+        let uv = self.uv[idx as usize];
+        Vec3f::new(uv[0], uv[1], 0.0)
     }
 
+    /// Vertex normal, or the face normal if the file has none.
     pub fn normal(&self, iface: usize, nvert: usize) -> Vec3f {
-        let idx = self.faces[iface][nvert][2] as usize;
-        return self.normals[idx].normalized();
+        let idx = self.faces[iface][nvert][2];
+        if idx < 0 {
+            return self.face_normal(iface);
+        }
+        return self.normals[idx as usize].normalized();
+    }
+
+    /// Tangent and bitangent orthonormalized against the vertex normal, zero if the
+    /// corner has no texture coordinate.
+    pub fn tangent_frame(&self, iface: usize, nvert: usize) -> (Vec3f, Vec3f) {
+        let idx = self.faces[iface][nvert][1];
+        if idx < 0 || idx as usize >= self.tangents.len() {
+            return (Vec3f::zero(), Vec3f::zero());
+        }
+
+        let n = self.normal(iface, nvert);
+        let t = self.tangents[idx as usize];
+        let t = t - n * (n * t);
+        if t.norm() == 0.0 {
+            return (Vec3f::zero(), Vec3f::zero());
+        }
+        let t = t.normalized();
+
+        // mirrored texture coordinates flip the bitangent
+        let b = n ^ t;
+        let b = if b * self.bitangents[idx as usize] < 0.0 { b * -1.0 } else { b };
+        (t, b)
+    }
+
+    /// Accumulates the texture space derivatives of every face onto its texture
+    /// coordinates. Corners which share a position but not a texture coordinate, like
+    /// those on a texture seam, keep separate tangents.
+    pub fn compute_tangents(&mut self) {
+        self.tangents = vec![Vec3f::zero(); self.uv.len()];
+        self.bitangents = vec![Vec3f::zero(); self.uv.len()];
+
+        for face in &self.faces {
+            if face.iter().any(|corner| corner[1] < 0) {
+                continue;
+            }
+            let p = |i: usize| self.verticies[face[i][0] as usize];
+            let uv = |i: usize| self.uv[face[i][1] as usize];
+
+            let e1 = p(1) - p(0);
+            let e2 = p(2) - p(0);
+            let (du1, dv1) = (uv(1)[0] - uv(0)[0], uv(1)[1] - uv(0)[1]);
+            let (du2, dv2) = (uv(2)[0] - uv(0)[0], uv(2)[1] - uv(0)[1]);
+
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < 1e-12 {
+                continue;
+            }
+            let r = 1.0 / det;
+            let t = (e1 * dv2 - e2 * dv1) * r;
+            let b = (e2 * du1 - e1 * du2) * r;
+
+            for corner in face {
+                let idx = corner[1] as usize;
+                self.tangents[idx] = self.tangents[idx] + t;
+                self.bitangents[idx] = self.bitangents[idx] + b;
+            }
+        }
+    }
+
+    /// Normal of a counter-clockwise face.
+    pub fn face_normal(&self, iface: usize) -> Vec3f {
+        let face = self.faces[iface];
+        let v = |i: usize| self.verticies[face[i][0] as usize];
+
+        ((v(1) - v(0)) ^ (v(2) - v(0))).normalized()
     }
 }
 
 pub struct Loader; //TODO
 
+/// `name` relative to the directory of `base`. Both are `/` separated names as
+/// written in the model files, not file system paths, so that in-memory and archive
+/// lookups see the same names on every platform.
+fn relative_to(base: &str, name: &str) -> String {
+    match base.rfind('/') {
+        Some(i) if !name.starts_with('/') => format!("{}/{}", &base[..i], name),
+        _ => name.to_string(),
+    }
+}
+
 impl Loader {
     pub fn from_files(obj_path: &str, diffuse_path: &str) -> Result<Model> {
         let mut model = try!(Self::load_obj(obj_path));
-        model.diffuse = try!(TgaImage::load(diffuse_path));
+        let diffuse = try!(ImageRegistry::new().load(diffuse_path));
+        model.diffuse = Texture::with_mipmaps(diffuse, Downsample::Box);
 
         Ok(model)
     }
 
-    fn load_obj(path: &str) -> Result<Model> {
-        let path = Path::new(path);
-        let file = BufReader::new(try!(File::open(&path)));
+    /// Loads the model together with its material libraries and their textures,
+    /// all paths are relative to the file which refers to them. Mipmaps of the
+    /// textures are generated with a box filter.
+    pub fn from_obj(path: &str) -> Result<Model> {
+        let file = try!(File::open(path).map_err(|err| ObjError::new(path, 0, ObjErrorKind::Io(err))));
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
 
-        let mut model = Model::new();
+        Self::from_reader(path, BufReader::new(file), |name| File::open(dir.join(name)))
+    }
 
-        for line in file.lines() {
-            let line = try!(line);
+    /// Same as `from_obj` for a model which is not in a file. `open` is asked for
+    /// the material libraries and textures by their `/` separated path relative to
+    /// the model, `name` only appears in error messages.
+    pub fn from_reader<R, S, F>(name: &str, input: R, mut open: F) -> Result<Model>
+        where R: BufRead,
+              S: Read,
+              F: FnMut(&str) -> Result<S>
+    {
+        let mut model = try!(Self::read_obj(name, input));
 
-            let words: Vec<&str>;
-            words = line.split_whitespace().collect();
-            if line.starts_with("v ") {
-                let mut v = Vec3f { ..Default::default() };
-                for i in 0..3 {
-                    v[i] = words[i + 1].parse::<f32>().unwrap();
-                }
-                model.verticies.push(v);
-            } else if line.starts_with("f ") {
-                let mut face: [Vec3i; 3] = [Vec3i::new(-1, -1, -1); 3];
-                let words: Vec<&str> = line.split_whitespace().collect();
-                for i in 0..3 {
-                    let mut j = 0;
-                    for num in words[i + 1].split("/") {
-                        face[i][j] = num.parse::<i32>().unwrap() - 1;
-                        j += 1;
-                    }
-                }
-                model.faces.push(face);
-            } else if line.starts_with("vt ") {
-                let w: Vec<&str> = line.split_whitespace().collect();
-                model.uv.push([w[1].parse().unwrap(), w[2].parse().unwrap()]); //FIXME remove unwraps
-            } else if line.starts_with("vn ") {
-                let mut v = Vec3f { ..Default::default() };
-                for i in 0..3 {
-                    v[i] = words[i + 1].parse::<f32>().unwrap();
+        for lib in &model.material_libs {
+            let source = try!(open(lib)
+                .map_err(|err| ObjError::new(lib, 0, ObjErrorKind::Io(err))));
+            let materials = try!(Self::read_mtl(lib,
+                                                BufReader::new(source),
+                                                |map| open(&relative_to(lib, map))));
+            model.materials.extend(materials);
+        }
+
+        if let Some(diffuse) = model.materials.iter().filter_map(|m| m.diffuse_map.as_ref()).next() {
+            model.diffuse = diffuse.clone();
+        }
+        Ok(model)
+    }
+
+    pub fn load_mtl(path: &str) -> Result<Vec<Material>> {
+        let file = try!(File::open(path).map_err(|err| ObjError::new(path, 0, ObjErrorKind::Io(err))));
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        Self::read_mtl(path, BufReader::new(file), |name| File::open(dir.join(name)))
+    }
+
+    /// Reads a material library, `open` is asked for the textures by the names in it.
+    pub fn read_mtl<R, S, F>(name: &str, input: R, mut open: F) -> Result<Vec<Material>>
+        where R: BufRead,
+              S: Read,
+              F: FnMut(&str) -> Result<S>
+    {
+        let entries = try!(parse_mtl(name, input));
+
+        let registry = ImageRegistry::new();
+        let mut load_map = |map: &Option<String>| -> Result<Option<Texture>> {
+            match *map {
+                Some(ref map) => {
+                    open(map)
+                        .and_then(|source| registry.read(source, Some(map)))
+                        .map(|image| Some(Texture::with_mipmaps(image, Downsample::Box)))
+                        .map_err(|err| {
+                            Error::new(err.kind(), format!("{}: {}: {}", name, map, err))
+                        })
                 }
-                model.normals.push(v);
+                None => Ok(None),
             }
+        };
+
+        let mut materials = Vec::with_capacity(entries.len());
+        for (mut material, maps) in entries {
+            material.diffuse_map = try!(load_map(&maps.diffuse));
+            material.normal_map = try!(load_map(&maps.normal));
+            material.specular_map = try!(load_map(&maps.specular));
+            material.shininess_map = try!(load_map(&maps.shininess));
+            materials.push(material);
         }
-        Ok(model)
+        Ok(materials)
     }
+
+    pub fn load_obj(path: &str) -> ObjResult<Model> {
+        let file = try!(File::open(path).map_err(|err| ObjError::new(path, 0, ObjErrorKind::Io(err))));
+
+        Self::read_obj(path, BufReader::new(file))
+    }
+
+    /// Reads the geometry of a model, `name` only appears in error messages.
+    pub fn read_obj<R: BufRead>(name: &str, input: R) -> ObjResult<Model> {
+        parse_obj(name, input)
+    }
+}
+
+#[test]
+fn test_from_obj() {
+    let mut model = Loader::from_obj("obj/african/african_head.obj").unwrap();
+
+    assert_eq!(model.materials.len(), 1);
+    let material = model.material(&model.meshes[0]).unwrap();
+    assert_eq!(material.name, "head");
+    assert_eq!(material.shininess, 32.0);
+    assert!(material.normal_map.is_some());
+    assert_eq!(material.normal_space, NormalSpace::Tangent);
+    assert!(material.specular_map.is_some());
+    assert_eq!(model.diffuse.width(), material.diffuse_map.as_ref().unwrap().width());
+
+    model.set_normal_space(NormalSpace::Object);
+    assert_eq!(model.materials[0].normal_space, NormalSpace::Object);
+}
+
+#[test]
+fn test_relative_to() {
+    assert_eq!(relative_to("mat/box.mtl", "red.png"), "mat/red.png");
+    assert_eq!(relative_to("a/b/box.mtl", "tex/red.png"), "a/b/tex/red.png");
+    assert_eq!(relative_to("box.mtl", "tex/red.png"), "tex/red.png");
+    assert_eq!(relative_to("mat/box.mtl", "/abs/red.png"), "/abs/red.png");
+}
+
+#[test]
+fn test_from_reader() {
+    use color::Rgba8;
+    use pixmap::Pixmap;
+    use png::PngWriter;
+    use std::io::ErrorKind;
+
+    let obj = "mtllib mat/box.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n";
+    let mtl = "newmtl red\nmap_Kd red.png\nnewmtl blue\nmap_Kd blue.png\n";
+    let mut png = Vec::new();
+    PngWriter::default().write(&Pixmap::new(4, 2, Rgba8::rgb(255, 0, 0)), &mut png).unwrap();
+
+    // texture names are relative to the material library
+    let mut opened = Vec::new();
+    let model = Loader::from_reader("box.obj", obj.as_bytes(), |name| {
+            opened.push(name.to_string());
+            match name {
+                "mat/box.mtl" => Ok(mtl.as_bytes()),
+                "mat/red.png" | "mat/blue.png" => Ok(&png[..]),
+                _ => Err(Error::new(ErrorKind::NotFound, "no such asset")),
+            }
+        })
+        .unwrap();
+    assert_eq!(opened, ["mat/box.mtl", "mat/red.png", "mat/blue.png"]);
+    assert_eq!(model.faces.len(), 1);
+    assert_eq!(model.materials.len(), 2);
+    assert_eq!(model.material(&model.meshes[0]).unwrap().name, "red");
+    assert_eq!((model.diffuse.width(), model.diffuse.height()), (4, 2));
+
+    let err = Loader::read_mtl("box.mtl", mtl.as_bytes(), |_| Ok(&b"not an image"[..]))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("box.mtl: red.png: "));
+}
+
+#[test]
+fn test_tangent_frame() {
+    use std::io::Cursor;
+    use wavefront::parse_obj;
+
+    // a quad in the xy plane whose texture is mirrored along u on the right half
+    let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nv 2 1 0\n\
+                  vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvt 0 0\nvt 0 1\nvn 0 0 1\n\
+                  f 1/1/1 2/2/1 3/3/1 4/4/1\nf 2/2/1 5/5/1 6/6/1 3/3/1\n";
+    let model = parse_obj("quad.obj", Cursor::new(source)).unwrap();
+
+    let (t, b) = model.tangent_frame(0, 0);
+    assert_eq!(t, Vec3f::new(1.0, 0.0, 0.0));
+    assert_eq!(b, Vec3f::new(0.0, 1.0, 0.0));
+
+    let (t, b) = model.tangent_frame(2, 1);
+    assert_eq!(t, Vec3f::new(-1.0, 0.0, 0.0));
+    assert_eq!(b, Vec3f::new(0.0, 1.0, 0.0));
 }
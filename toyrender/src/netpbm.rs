@@ -0,0 +1,644 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::result;
+
+use color::Rgba8;
+use image::ImageDecoder;
+use pixmap::Pixmap;
+use tgaimage::{ImageLoader, ImageWriter, Result};
+
+pub type NetpbmResult<T> = result::Result<T, NetpbmError>;
+
+/// Describes why a PPM, PGM or PAM file could not be decoded.
+#[derive(Debug)]
+pub enum NetpbmError {
+    Io(io::Error),
+    /// The data ended before the whole image was read.
+    Truncated,
+    /// The file does not start with one of `P2`, `P3`, `P5`, `P6` or `P7`.
+    UnsupportedFormat([u8; 2]),
+    /// A header field or text sample is not a decimal number.
+    InvalidNumber,
+    /// The maximum sample value is zero or larger than 65535.
+    InvalidMaxval(u32),
+    /// A sample is larger than the maximum value of the header.
+    SampleOutOfRange(u32),
+    /// A PAM header line which is not understood or a missing field.
+    InvalidPamHeader(String),
+    /// PAM tuples with other than 1 to 4 samples.
+    UnsupportedDepth(u32),
+    /// Width or height is zero.
+    EmptyImage,
+}
+
+impl fmt::Display for NetpbmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetpbmError::Io(ref err) => write!(f, "netpbm: {}", err),
+            NetpbmError::Truncated => write!(f, "netpbm: unexpected end of image data"),
+            NetpbmError::UnsupportedFormat(magic) => {
+                write!(f, "netpbm: unsupported magic number {:?}", String::from_utf8_lossy(&magic))
+            }
+            NetpbmError::InvalidNumber => write!(f, "netpbm: expected a decimal number"),
+            NetpbmError::InvalidMaxval(maxval) => {
+                write!(f, "netpbm: maximum sample value {} is not in [1, 65535]", maxval)
+            }
+            NetpbmError::SampleOutOfRange(sample) => {
+                write!(f, "netpbm: sample {} exceeds the maximum value", sample)
+            }
+            NetpbmError::InvalidPamHeader(ref line) => {
+                write!(f, "netpbm: invalid pam header: {}", line)
+            }
+            NetpbmError::UnsupportedDepth(depth) => {
+                write!(f, "netpbm: unsupported pam depth {}", depth)
+            }
+            NetpbmError::EmptyImage => write!(f, "netpbm: image has zero width or height"),
+        }
+    }
+}
+
+impl error::Error for NetpbmError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            NetpbmError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NetpbmError {
+    fn from(err: io::Error) -> NetpbmError {
+        match err.kind() {
+            ErrorKind::UnexpectedEof => NetpbmError::Truncated,
+            _ => NetpbmError::Io(err),
+        }
+    }
+}
+
+impl From<NetpbmError> for io::Error {
+    fn from(err: NetpbmError) -> io::Error {
+        match err {
+            NetpbmError::Io(err) => err,
+            NetpbmError::Truncated => Error::new(ErrorKind::UnexpectedEof, err),
+            err => Error::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Flavours of Netpbm files which can be written.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NetpbmFormat {
+    /// `P2`, grayscale as decimal text.
+    GrayText,
+    /// `P3`, rgb as decimal text.
+    RgbText,
+    /// `P5`, binary grayscale.
+    Gray,
+    /// `P6`, binary rgb.
+    Rgb,
+    /// `P7`, binary with an alpha channel.
+    Pam,
+}
+
+impl NetpbmFormat {
+    fn magic(self) -> &'static str {
+        match self {
+            NetpbmFormat::GrayText => "P2",
+            NetpbmFormat::RgbText => "P3",
+            NetpbmFormat::Gray => "P5",
+            NetpbmFormat::Rgb => "P6",
+            NetpbmFormat::Pam => "P7",
+        }
+    }
+
+    fn from_magic(magic: [u8; 2]) -> Option<NetpbmFormat> {
+        match &magic {
+            b"P2" => Some(NetpbmFormat::GrayText),
+            b"P3" => Some(NetpbmFormat::RgbText),
+            b"P5" => Some(NetpbmFormat::Gray),
+            b"P6" => Some(NetpbmFormat::Rgb),
+            b"P7" => Some(NetpbmFormat::Pam),
+            _ => None,
+        }
+    }
+
+    fn is_text(self) -> bool {
+        self == NetpbmFormat::GrayText || self == NetpbmFormat::RgbText
+    }
+}
+
+// Longest PAM header line which is accepted.
+const MAX_LINE: u64 = 256;
+
+struct Header {
+    width: usize,
+    height: usize,
+    /// Samples per pixel: gray, gray and alpha, rgb or rgb and alpha.
+    depth: usize,
+    maxval: u32,
+}
+
+fn is_space(byte: u8) -> bool {
+    match byte {
+        b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c => true,
+        _ => false,
+    }
+}
+
+fn read_byte<R: BufRead>(inp: &mut R) -> NetpbmResult<Option<u8>> {
+    let byte = try!(inp.fill_buf()).first().cloned();
+    if byte.is_some() {
+        inp.consume(1);
+    }
+    Ok(byte)
+}
+
+fn skip_comment<R: BufRead>(inp: &mut R) -> NetpbmResult<()> {
+    let mut comment = Vec::new();
+    try!(inp.read_until(b'\n', &mut comment));
+    Ok(())
+}
+
+/// Next decimal number, whitespace and comments in front of it are skipped. Exactly
+/// one whitespace character after the number is consumed, which matters for the
+/// single separator between the header and binary samples.
+fn read_number<R: BufRead>(inp: &mut R) -> NetpbmResult<u32> {
+    let mut byte = try!(read_byte(inp));
+    loop {
+        match byte {
+            Some(b'#') => try!(skip_comment(inp)),
+            Some(b) if is_space(b) => {}
+            _ => break,
+        }
+        byte = try!(read_byte(inp));
+    }
+
+    let mut value: u32 = match byte {
+        Some(b) if b.is_ascii_digit() => (b - b'0') as u32,
+        Some(_) => return Err(NetpbmError::InvalidNumber),
+        None => return Err(NetpbmError::Truncated),
+    };
+    loop {
+        match try!(read_byte(inp)) {
+            Some(b) if b.is_ascii_digit() => {
+                value = try!(value.checked_mul(10)
+                    .and_then(|v| v.checked_add((b - b'0') as u32))
+                    .ok_or(NetpbmError::InvalidNumber));
+            }
+            Some(b'#') => {
+                try!(skip_comment(inp));
+                return Ok(value);
+            }
+            Some(b) if is_space(b) => return Ok(value),
+            Some(_) => return Err(NetpbmError::InvalidNumber),
+            None => return Ok(value),
+        }
+    }
+}
+
+/// PPM, PGM and PAM decoder for gray, rgb and their alpha variants. Samples are
+/// scaled from the maximum value of the file to the range of the pixmap.
+pub struct NetpbmImage;
+
+impl NetpbmImage {
+    /// Same as `ImageLoader::load`, but reports exactly what was wrong with the file.
+    pub fn open(path: &str) -> NetpbmResult<Pixmap> {
+        let file = try!(File::open(&Path::new(path)));
+        Self::read(&mut BufReader::new(file))
+    }
+
+    pub fn read<R: BufRead>(inp: &mut R) -> NetpbmResult<Pixmap> {
+        let (header, samples) = try!(Self::decode(inp));
+        let maxval = header.maxval;
+        let scale = |v: u16| ((v as u32 * 255 + maxval / 2) / maxval) as u8;
+
+        let pixels = samples.chunks(header.depth)
+            .map(|s| {
+                match s.len() {
+                    1 => Rgba8::from(scale(s[0])),
+                    2 => Rgba8::new(scale(s[0]), scale(s[0]), scale(s[0]), scale(s[1])),
+                    3 => Rgba8::rgb(scale(s[0]), scale(s[1]), scale(s[2])),
+                    _ => Rgba8::new(scale(s[0]), scale(s[1]), scale(s[2]), scale(s[3])),
+                }
+            })
+            .collect();
+        Ok(Pixmap::from_vec(header.width, header.height, pixels).unwrap())
+    }
+
+    /// Reads the image as 16 bit grayscale without rounding samples to 8 bits, e.g.
+    /// depth buffers written by `NetpbmWriter::write_gray16`. Colors are converted
+    /// to luma and alpha is dropped.
+    pub fn read_gray16<R: BufRead>(inp: &mut R) -> NetpbmResult<Pixmap<u16>> {
+        let (header, samples) = try!(Self::decode(inp));
+        let maxval = header.maxval;
+        let scale = |v: u16| (v as u32 * 65535 + maxval / 2) / maxval;
+
+        let pixels = samples.chunks(header.depth)
+            .map(|s| {
+                if s.len() < 3 {
+                    scale(s[0]) as u16
+                } else {
+                    ((scale(s[0]) * 77 + scale(s[1]) * 150 + scale(s[2]) * 29) >> 8) as u16
+                }
+            })
+            .collect();
+        Ok(Pixmap::from_vec(header.width, header.height, pixels).unwrap())
+    }
+
+    fn decode<R: BufRead>(inp: &mut R) -> NetpbmResult<(Header, Vec<u16>)> {
+        let mut magic = [0u8; 2];
+        try!(inp.read_exact(&mut magic));
+        let format = try!(NetpbmFormat::from_magic(magic)
+            .ok_or(NetpbmError::UnsupportedFormat(magic)));
+
+        let header = if format == NetpbmFormat::Pam {
+            try!(Self::read_pam_header(inp))
+        } else {
+            let width = try!(read_number(inp)) as usize;
+            let height = try!(read_number(inp)) as usize;
+            let maxval = try!(read_number(inp));
+            let depth = match format {
+                NetpbmFormat::GrayText | NetpbmFormat::Gray => 1,
+                _ => 3,
+            };
+            Header {
+                width: width,
+                height: height,
+                depth: depth,
+                maxval: maxval,
+            }
+        };
+
+        if header.maxval == 0 || header.maxval > 65535 {
+            return Err(NetpbmError::InvalidMaxval(header.maxval));
+        }
+        if header.width == 0 || header.height == 0 {
+            return Err(NetpbmError::EmptyImage);
+        }
+        let count = try!(header.width
+            .checked_mul(header.height)
+            .and_then(|n| n.checked_mul(header.depth))
+            .ok_or(NetpbmError::Truncated));
+
+        // The samples grow while reading, so a bogus header can not force a huge allocation.
+        let mut samples = Vec::new();
+        if format.is_text() {
+            for _ in 0..count {
+                let sample = try!(read_number(inp));
+                if sample > header.maxval {
+                    return Err(NetpbmError::SampleOutOfRange(sample));
+                }
+                samples.push(sample as u16);
+            }
+        } else {
+            let bytes_per_sample = if header.maxval < 256 { 1 } else { 2 };
+            let mut buffer = Vec::new();
+            let size = (count as u64).saturating_mul(bytes_per_sample);
+            try!(inp.take(size).read_to_end(&mut buffer));
+            if (buffer.len() as u64) < size {
+                return Err(NetpbmError::Truncated);
+            }
+
+            for bytes in buffer.chunks(bytes_per_sample as usize) {
+                // wider samples are big endian
+                let sample = bytes.iter().fold(0, |v, &b| v << 8 | b as u32);
+                if sample > header.maxval {
+                    return Err(NetpbmError::SampleOutOfRange(sample));
+                }
+                samples.push(sample as u16);
+            }
+        }
+
+        Ok((header, samples))
+    }
+
+    /// Reads `KEY value` lines up to `ENDHDR`, the tuple type is implied by the depth.
+    fn read_pam_header<R: BufRead>(inp: &mut R) -> NetpbmResult<Header> {
+        let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+        loop {
+            let mut line = Vec::new();
+            try!(inp.by_ref().take(MAX_LINE).read_until(b'\n', &mut line));
+            if line.is_empty() {
+                return Err(NetpbmError::Truncated);
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+
+            let mut fields = line.split_whitespace();
+            let key = match fields.next() {
+                Some(key) if !key.starts_with('#') => key,
+                _ => continue,
+            };
+            let field = match key {
+                "ENDHDR" => break,
+                "TUPLTYPE" => continue,
+                "WIDTH" => &mut width,
+                "HEIGHT" => &mut height,
+                "DEPTH" => &mut depth,
+                "MAXVAL" => &mut maxval,
+                _ => return Err(NetpbmError::InvalidPamHeader(line.to_string())),
+            };
+            *field = match fields.next().and_then(|v| v.parse::<u32>().ok()) {
+                Some(value) if fields.next().is_none() => Some(value),
+                _ => return Err(NetpbmError::InvalidPamHeader(line.to_string())),
+            };
+        }
+
+        let missing = |name: &str| NetpbmError::InvalidPamHeader(format!("missing {}", name));
+        let depth = try!(depth.ok_or_else(|| missing("DEPTH")));
+        if depth == 0 || depth > 4 {
+            return Err(NetpbmError::UnsupportedDepth(depth));
+        }
+        Ok(Header {
+            width: try!(width.ok_or_else(|| missing("WIDTH"))) as usize,
+            height: try!(height.ok_or_else(|| missing("HEIGHT"))) as usize,
+            depth: depth as usize,
+            maxval: try!(maxval.ok_or_else(|| missing("MAXVAL"))),
+        })
+    }
+}
+
+impl ImageLoader for NetpbmImage {
+    fn read_from<R: BufRead>(inp: &mut R) -> Result<Pixmap> {
+        Self::read(inp).map_err(io::Error::from)
+    }
+}
+
+impl ImageDecoder for NetpbmImage {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ppm", "pgm", "pnm", "pam"]
+    }
+
+    fn detect(&self, header: &[u8]) -> bool {
+        header.len() >= 3 && header[0] == b'P' && b"23567".contains(&header[1]) &&
+        is_space(header[2])
+    }
+
+    fn decode(&self, mut inp: &mut dyn BufRead) -> Result<Pixmap> {
+        Self::read(&mut inp).map_err(io::Error::from)
+    }
+}
+
+/// PPM, PGM and PAM encoder. Gray formats store the luma of colors and only `Pam`
+/// keeps the alpha channel.
+#[derive(Copy, Clone, Debug)]
+pub struct NetpbmWriter {
+    pub format: NetpbmFormat,
+    /// Samples are scaled to `[0, maxval]`, above 255 binary samples take two bytes.
+    pub maxval: u16,
+}
+
+impl Default for NetpbmWriter {
+    fn default() -> NetpbmWriter {
+        NetpbmWriter {
+            format: NetpbmFormat::Rgb,
+            maxval: 255,
+        }
+    }
+}
+
+impl NetpbmWriter {
+    pub fn new(format: NetpbmFormat, maxval: u16) -> NetpbmWriter {
+        NetpbmWriter {
+            format: format,
+            maxval: maxval,
+        }
+    }
+
+    pub fn write<W: Write>(&self, pixmap: &Pixmap, out: &mut W) -> Result<()> {
+        let depth = match self.format {
+            NetpbmFormat::GrayText | NetpbmFormat::Gray => 1,
+            NetpbmFormat::RgbText | NetpbmFormat::Rgb => 3,
+            NetpbmFormat::Pam => 4,
+        };
+        try!(self.write_header(pixmap.width(), pixmap.height(), depth, "RGB_ALPHA", out));
+
+        let wide = |c: u8| c as u16 * 257;
+        let mut line = Vec::with_capacity(pixmap.width() * depth);
+        for row in pixmap.rows() {
+            line.clear();
+            for c in row {
+                match depth {
+                    1 => line.push(wide(c.luma())),
+                    3 => line.extend_from_slice(&[wide(c.r), wide(c.g), wide(c.b)]),
+                    _ => line.extend_from_slice(&[wide(c.r), wide(c.g), wide(c.b), wide(c.a)]),
+                }
+            }
+            try!(self.write_samples(&line, out));
+        }
+        Ok(())
+    }
+
+    /// Writes 16 bit grayscale, e.g. a depth buffer converted with
+    /// `DepthBuffer::to_gray16`. Use a `maxval` of 65535 to keep every bit.
+    pub fn write_gray16<W: Write>(&self, pixmap: &Pixmap<u16>, out: &mut W) -> Result<()> {
+        let depth = match self.format {
+            NetpbmFormat::RgbText | NetpbmFormat::Rgb => 3,
+            _ => 1,
+        };
+        try!(self.write_header(pixmap.width(), pixmap.height(), depth, "GRAYSCALE", out));
+
+        let mut line = Vec::with_capacity(pixmap.width() * depth);
+        for row in pixmap.rows() {
+            line.clear();
+            for &v in row {
+                for _ in 0..depth {
+                    line.push(v);
+                }
+            }
+            try!(self.write_samples(&line, out));
+        }
+        Ok(())
+    }
+
+    pub fn save_gray16(&self, pixmap: &Pixmap<u16>, path: &str) -> Result<()> {
+        let mut file = BufWriter::new(try!(File::create(&Path::new(path))));
+        try!(self.write_gray16(pixmap, &mut file));
+        file.flush()
+    }
+
+    fn write_header<W: Write>(&self,
+                              w: usize,
+                              h: usize,
+                              depth: usize,
+                              tupltype: &str,
+                              out: &mut W)
+                              -> Result<()> {
+        if self.maxval == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "maximum sample value is zero"));
+        }
+        match self.format {
+            NetpbmFormat::Pam => {
+                write!(out,
+                       "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+                       w,
+                       h,
+                       depth,
+                       self.maxval,
+                       tupltype)
+            }
+            format => write!(out, "{}\n{} {}\n{}\n", format.magic(), w, h, self.maxval),
+        }
+    }
+
+    /// Scales a row of 16 bit samples to `maxval` and writes them.
+    fn write_samples<W: Write>(&self, samples: &[u16], out: &mut W) -> Result<()> {
+        let maxval = self.maxval as u32;
+        let scale = |v: u16| (v as u32 * maxval + 32767) / 65535;
+
+        if self.format.is_text() {
+            // text lines should not be longer than 70 characters
+            let mut text = String::new();
+            let mut line_start = 0;
+            for (i, &v) in samples.iter().enumerate() {
+                let sample = scale(v).to_string();
+                if i > 0 && text.len() - line_start + 1 + sample.len() > 70 {
+                    text.push('\n');
+                    line_start = text.len();
+                } else if i > 0 {
+                    text.push(' ');
+                }
+                text.push_str(&sample);
+            }
+            text.push('\n');
+            out.write_all(text.as_bytes())
+        } else {
+            let mut bytes = Vec::with_capacity(samples.len() * 2);
+            for &v in samples {
+                let sample = scale(v);
+                if maxval > 255 {
+                    bytes.push((sample >> 8) as u8);
+                }
+                bytes.push(sample as u8);
+            }
+            out.write_all(&bytes)
+        }
+    }
+}
+
+impl ImageWriter for NetpbmWriter {
+    fn save(&self, pixmap: &Pixmap, path: &str) -> Result<()> {
+        let mut file = BufWriter::new(try!(File::create(&Path::new(path))));
+        try!(self.write(pixmap, &mut file));
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{NetpbmError, NetpbmFormat, NetpbmImage, NetpbmWriter};
+    use color::Rgba8;
+    use pixmap::Pixmap;
+    use tgaimage::{ImageLoader, ImageWriter};
+
+    fn gradient(w: usize, h: usize) -> Pixmap {
+        let mut pixmap = Pixmap::new(w, h, Rgba8::default());
+        for y in 0..h {
+            for x in 0..w {
+                let (r, g, b, a) = (x * 7, y * 13, x + y, x * 8);
+                pixmap[(x, y)] = Rgba8::new(r as u8, g as u8, b as u8, a as u8);
+            }
+        }
+        pixmap
+    }
+
+    fn decode(bytes: &[u8]) -> Pixmap {
+        NetpbmImage::read(&mut &bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pixmap = gradient(30, 7);
+        let gray = pixmap.map(|c| Rgba8::from(c.luma()));
+        let opaque = pixmap.map(|c| Rgba8::rgb(c.r, c.g, c.b));
+
+        let formats = [(NetpbmFormat::GrayText, &gray),
+                       (NetpbmFormat::RgbText, &opaque),
+                       (NetpbmFormat::Gray, &gray),
+                       (NetpbmFormat::Rgb, &opaque),
+                       (NetpbmFormat::Pam, &pixmap)];
+        for &(format, expected) in &formats {
+            for &maxval in &[255, 65535] {
+                let mut out = Vec::new();
+                NetpbmWriter::new(format, maxval).write(&pixmap, &mut out).unwrap();
+                assert!(decode(&out) == *expected, "{:?} {}", format, maxval);
+                if format.is_text() {
+                    assert!(out.split(|&b| b == b'\n').all(|line| line.len() <= 70));
+                }
+            }
+        }
+
+        let path = env::temp_dir().join("toyrender_round_trip.ppm");
+        let path = path.to_str().unwrap();
+        NetpbmWriter::default().save(&pixmap, path).unwrap();
+        assert!(NetpbmImage::load(path).unwrap() == opaque);
+    }
+
+    #[test]
+    fn test_gray16() {
+        let depth = Pixmap::from_vec(3, 1, vec![0, 1234, 65535]).unwrap();
+        let mut out = Vec::new();
+        NetpbmWriter::new(NetpbmFormat::Gray, 65535).write_gray16(&depth, &mut out).unwrap();
+        assert_eq!(&out[..15], b"P5\n3 1\n65535\n\x00\x00");
+        assert!(NetpbmImage::read_gray16(&mut &out[..]).unwrap() == depth);
+
+        // fewer bits round to the nearest value
+        out.clear();
+        NetpbmWriter::new(NetpbmFormat::GrayText, 255).write_gray16(&depth, &mut out).unwrap();
+        assert_eq!(out, b"P2\n3 1\n255\n0 5 255\n".to_vec());
+        assert_eq!(NetpbmImage::read_gray16(&mut &out[..]).unwrap().data(), &[0, 1285, 65535]);
+    }
+
+    #[test]
+    fn test_headers() {
+        // comments, odd whitespace and a maximum value other than 255
+        let pixmap = decode(b"P3 # rgb\n2 1\n# max\n15\n15 0 0\t0 0 15");
+        assert_eq!(pixmap.data(), &[Rgba8::rgb(255, 0, 0), Rgba8::rgb(0, 0, 255)]);
+
+        // the header ends after a single whitespace, the next byte is a sample
+        let pixmap = decode(b"P5 2 1 255\n\x0a\xff");
+        assert_eq!(pixmap.data(), &[Rgba8::from(10u8), Rgba8::from(255u8)]);
+
+        let pam = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 2\nMAXVAL 255\n\
+                    TUPLTYPE GRAYSCALE_ALPHA\n# comment\nENDHDR\n\x40\x80\x00\xff";
+        let pixmap = decode(pam);
+        assert_eq!(pixmap.data(), &[Rgba8::new(64, 64, 64, 128), Rgba8::new(0, 0, 0, 255)]);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let check = |bytes: &[u8], expected: &str| {
+            match NetpbmImage::read(&mut &bytes[..]) {
+                Err(err) => assert_eq!(format!("{:?}", err), expected),
+                Ok(_) => panic!("{:?} should not be decoded", String::from_utf8_lossy(bytes)),
+            }
+        };
+
+        check(b"P", "Truncated");
+        check(b"P4\n1 1\n", "UnsupportedFormat([80, 52])");
+        check(b"P6\n2 2\n255\n\x00\x00", "Truncated");
+        check(b"P2\n2 1\n255\n0", "Truncated");
+        check(b"P2\nx 1\n255\n", "InvalidNumber");
+        check(b"P2\n1 1\n9999999999\n", "InvalidNumber");
+        check(b"P2\n1 1\n0\n0", "InvalidMaxval(0)");
+        check(b"P5\n1 1\n70000\n", "InvalidMaxval(70000)");
+        check(b"P2\n1 1\n7\n8", "SampleOutOfRange(8)");
+        check(b"P5\n1 1\n300\n\x01\x2d", "SampleOutOfRange(301)");
+        check(b"P6\n0 1\n255\n", "EmptyImage");
+        check(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 5\nMAXVAL 255\nENDHDR\n", "UnsupportedDepth(5)");
+        check(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nENDHDR\n",
+              "InvalidPamHeader(\"missing MAXVAL\")");
+        check(b"P7\nWIDTH one\n", "InvalidPamHeader(\"WIDTH one\")");
+
+        match NetpbmImage::read(&mut &b"P6\n1 1\n255\n"[..]) {
+            Err(NetpbmError::Truncated) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,29 +1,300 @@
 use std::ops::{Index, IndexMut};
+use std::iter::StepBy;
+use std::slice::{Chunks, ChunksMut, Iter};
 
 use std::vec::Vec;
 
-// TODO error handling
-#[derive(Default)]
-pub struct Pixmap {
+use color::Rgba8;
+
+/// What `Pixmap::get_with` returns for coordinates outside of the image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EdgeMode {
+    /// Use the nearest edge pixel.
+    Clamp,
+    /// Repeat the image, like a tiled texture.
+    Wrap,
+    /// Repeat the image, every other copy flipped so that the edges match.
+    Mirror,
+}
+
+impl EdgeMode {
+    /// Maps a coordinate to `[0, n)`, `n` must be positive.
+    #[inline]
+    pub fn apply(self, i: i32, n: i32) -> i32 {
+        match self {
+            EdgeMode::Clamp => i.max(0).min(n - 1),
+            EdgeMode::Wrap => ((i % n) + n) % n,
+            EdgeMode::Mirror => {
+                let i = ((i % (2 * n)) + 2 * n) % (2 * n);
+                if i < n { i } else { 2 * n - 1 - i }
+            }
+        }
+    }
+}
+
+/// Two dimensional image, generic over the pixel type so that the same container
+/// holds colors, depth values or luminance.
+///
+/// Pixels are addressed as `(x, y)`: `x` is the column counted from the left edge and
+/// `y` is the row counted from the top edge. Storage is row-major, the pixel `(x, y)`
+/// lives at `data()[y * stride() + x]`. The stride is at least the width, any extra
+/// elements at the end of a row are padding and never returned by the pixel accessors.
+#[derive(Default, Clone, Debug)]
+pub struct Pixmap<P = Rgba8> {
     w: usize,
     h: usize,
+    stride: usize,
 
-    data: Vec<i32>,
+    data: Vec<P>,
 }
 
-impl Pixmap {
-    pub fn new(w: usize, h: usize, fill_value: i32) -> Pixmap {
+impl<P: Copy> Pixmap<P> {
+    pub fn new(w: usize, h: usize, fill_value: P) -> Pixmap<P> {
+        Self::with_stride(w, h, w, fill_value)
+    }
+
+    /// Allocates rows of `stride` elements, e.g. to keep rows aligned.
+    pub fn with_stride(w: usize, h: usize, stride: usize, fill_value: P) -> Pixmap<P> {
+        assert!(stride >= w, "stride {} is less than width {}", stride, w);
+
         Pixmap {
             w: w,
             h: h,
-            data: vec![fill_value; w*h],
+            stride: stride,
+            data: vec![fill_value; stride*h],
+        }
+    }
+
+    /// Wraps pixels stored row by row, returns `None` if the length does not match.
+    pub fn from_vec(w: usize, h: usize, data: Vec<P>) -> Option<Pixmap<P>> {
+        Self::from_vec_with_stride(w, h, w, data)
+    }
+
+    /// Wraps rows of `stride` elements, returns `None` if the length does not match.
+    pub fn from_vec_with_stride(w: usize,
+                                h: usize,
+                                stride: usize,
+                                data: Vec<P>)
+                                -> Option<Pixmap<P>> {
+        if stride < w || Some(data.len()) != stride.checked_mul(h) {
+            return None;
+        }
+
+        Some(Pixmap {
+            w: w,
+            h: h,
+            stride: stride,
+            data: data,
+        })
+    }
+
+    pub fn fill(&mut self, fill_value: P) {
+        for p in self.data.iter_mut() {
+            *p = fill_value;
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.w
+    }
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
+    /// Number of elements between the starts of two consecutive rows.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Raw storage including row padding, see the layout description of `Pixmap`.
+    pub fn data(&self) -> &[P] {
+        &self.data
+    }
+
+    #[inline]
+    fn offset(&self, x: usize, y: usize) -> usize {
+        y * self.stride + x
+    }
+
+    #[inline]
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.w && (y as usize) < self.h
+    }
+
+    #[inline]
+    pub fn get(&self, x: i32, y: i32) -> Option<P> {
+        if !self.contains(x, y) {
+            return None;
         }
+
+        Some(self.data[self.offset(x as usize, y as usize)])
+    }
+
+    /// Returns false if the pixel is outside of the image.
+    #[inline]
+    pub fn set(&mut self, x: i32, y: i32, value: P) -> bool {
+        if !self.contains(x, y) {
+            return false;
+        }
+
+        let i = self.offset(x as usize, y as usize);
+        self.data[i] = value;
+        true
+    }
+
+    /// Never fails for a non-empty image, out of range coordinates are mapped back
+    /// according to `mode`.
+    pub fn get_with(&self, x: i32, y: i32, mode: EdgeMode) -> P
+        where P: Default
+    {
+        if self.w == 0 || self.h == 0 {
+            return P::default();
+        }
+
+        let (x, y) = (mode.apply(x, self.w as i32), mode.apply(y, self.h as i32));
+        self.data[self.offset(x as usize, y as usize)]
+    }
+
+    /// Pixels of row `y` without the padding.
+    pub fn row(&self, y: usize) -> &[P] {
+        let i = self.offset(0, y);
+        &self.data[i..i + self.w]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [P] {
+        let i = self.offset(0, y);
+        &mut self.data[i..i + self.w]
+    }
+
+    /// Iterates rows from top to bottom.
+    pub fn rows<'a>(&'a self) -> Rows<'a, P> {
+        Rows {
+            chunks: self.data.chunks(self.stride.max(1)),
+            w: self.w,
+        }
+    }
+
+    pub fn rows_mut<'a>(&'a mut self) -> RowsMut<'a, P> {
+        RowsMut {
+            chunks: self.data.chunks_mut(self.stride.max(1)),
+            w: self.w,
+        }
+    }
+
+    pub fn column<'a>(&'a self, x: usize) -> StepBy<Iter<'a, P>> {
+        assert!(x < self.w, "column {} is out of range", x);
+        self.data[x..].iter().step_by(self.stride)
+    }
+
+    pub fn columns<'a>(&'a self) -> Columns<'a, P> {
+        Columns {
+            pixmap: self,
+            x: 0,
+        }
+    }
+
+    /// Borrows a rectangular part of the image, `None` if it does not fit inside.
+    pub fn view<'a>(&'a self, x: usize, y: usize, w: usize, h: usize) -> Option<View<'a, P>> {
+        if x + w > self.w || y + h > self.h {
+            return None;
+        }
+
+        Some(View {
+            pixmap: self,
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+        })
+    }
+
+    /// Applies `f` to every pixel, the result is tightly packed.
+    pub fn map<Q, F>(&self, f: F) -> Pixmap<Q>
+        where F: Fn(P) -> Q
+    {
+        let mut data = Vec::with_capacity(self.w * self.h);
+        for row in self.rows() {
+            data.extend(row.iter().map(|&p| f(p)));
+        }
+
+        Pixmap {
+            w: self.w,
+            h: self.h,
+            stride: self.w,
+            data: data,
+        }
+    }
+
+    /// Converts to another pixel format, e.g. `Pixmap<f32>` depth into a gray `Pixmap<Rgba8>`.
+    pub fn convert<Q: From<P>>(&self) -> Pixmap<Q> {
+        self.map(Q::from)
+    }
+}
+
+impl<P: Copy + PartialEq> PartialEq for Pixmap<P> {
+    /// Padding does not take part in the comparison.
+    fn eq(&self, other: &Pixmap<P>) -> bool {
+        self.w == other.w && self.h == other.h && self.rows().eq(other.rows())
+    }
+}
+
+pub struct Rows<'a, P: 'a> {
+    chunks: Chunks<'a, P>,
+    w: usize,
+}
+
+impl<'a, P> Iterator for Rows<'a, P> {
+    type Item = &'a [P];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let w = self.w;
+        self.chunks.next().map(|row| &row[..w])
     }
+}
+
+pub struct RowsMut<'a, P: 'a> {
+    chunks: ChunksMut<'a, P>,
+    w: usize,
+}
+
+impl<'a, P> Iterator for RowsMut<'a, P> {
+    type Item = &'a mut [P];
 
-    pub fn fill(&mut self, fill_value: i32) {
-        self.data = vec![fill_value; self.w*self.h]
+    fn next(&mut self) -> Option<Self::Item> {
+        let w = self.w;
+        self.chunks.next().map(|row| &mut row[..w])
     }
+}
+
+pub struct Columns<'a, P: 'a> {
+    pixmap: &'a Pixmap<P>,
+    x: usize,
+}
+
+impl<'a, P: Copy> Iterator for Columns<'a, P> {
+    type Item = StepBy<Iter<'a, P>>;
 
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.x >= self.pixmap.w {
+            return None;
+        }
+
+        self.x += 1;
+        Some(self.pixmap.column(self.x - 1))
+    }
+}
+
+/// Read only rectangular window into a `Pixmap`.
+#[derive(Copy, Clone)]
+pub struct View<'a, P: 'a> {
+    pixmap: &'a Pixmap<P>,
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+}
+
+impl<'a, P: Copy> View<'a, P> {
     pub fn width(&self) -> usize {
         self.w
     }
@@ -31,35 +302,99 @@ impl Pixmap {
         self.h
     }
 
-    pub fn get(&self, x: i32, y: i32) -> i32 {
-        if x < 0 || y < 0 {
-            return 0;
+    pub fn get(&self, x: i32, y: i32) -> Option<P> {
+        if x < 0 || y < 0 || x as usize >= self.w || y as usize >= self.h {
+            return None;
+        }
+
+        self.pixmap.get(self.x as i32 + x, self.y as i32 + y)
+    }
+
+    pub fn rows(&self) -> ViewRows<'a, P> {
+        ViewRows {
+            view: *self,
+            y: 0,
+        }
+    }
+
+    pub fn view(&self, x: usize, y: usize, w: usize, h: usize) -> Option<View<'a, P>> {
+        if x + w > self.w || y + h > self.h {
+            return None;
         }
-        if x >= self.w as i32 || y >= self.h as i32 {
-            return 0;
+
+        Some(View {
+            pixmap: self.pixmap,
+            x: self.x + x,
+            y: self.y + y,
+            w: w,
+            h: h,
+        })
+    }
+
+    pub fn to_pixmap(&self) -> Pixmap<P> {
+        let mut data = Vec::with_capacity(self.w * self.h);
+        for row in self.rows() {
+            data.extend_from_slice(row);
         }
 
-        self[x as usize][y as usize]
+        Pixmap {
+            w: self.w,
+            h: self.h,
+            stride: self.w,
+            data: data,
+        }
     }
 }
 
-impl Index<usize> for Pixmap {
-    type Output = [i32];
+pub struct ViewRows<'a, P: 'a> {
+    view: View<'a, P>,
+    y: usize,
+}
+
+impl<'a, P: Copy> Iterator for ViewRows<'a, P> {
+    type Item = &'a [P];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.y >= self.view.h {
+            return None;
+        }
+
+        let pixmap = self.view.pixmap;
+        let start = pixmap.offset(self.view.x, self.view.y + self.y);
+        self.y += 1;
+        Some(&pixmap.data[start..start + self.view.w])
+    }
+}
+
+/// `pixmap[(x, y)]`, panics outside of the image.
+impl<P: Copy> Index<(usize, usize)> for Pixmap<P> {
+    type Output = P;
 
     #[inline]
-    fn index<'a>(&'a self, _index: usize) -> &'a Self::Output {
-        let i = _index * self.w;
+    fn index<'a>(&'a self, (x, y): (usize, usize)) -> &'a Self::Output {
+        assert!(x < self.w && y < self.h,
+                "pixel ({}, {}) is out of {}x{} pixmap",
+                x,
+                y,
+                self.w,
+                self.h);
 
-        &self.data[i..i + self.w]
+        &self.data[self.offset(x, y)]
     }
 }
 
-impl IndexMut<usize> for Pixmap {
+impl<P: Copy> IndexMut<(usize, usize)> for Pixmap<P> {
     #[inline]
-    fn index_mut<'a>(&'a mut self, _index: usize) -> &'a mut Self::Output {
-        let i = _index * self.w;
+    fn index_mut<'a>(&'a mut self, (x, y): (usize, usize)) -> &'a mut Self::Output {
+        assert!(x < self.w && y < self.h,
+                "pixel ({}, {}) is out of {}x{} pixmap",
+                x,
+                y,
+                self.w,
+                self.h);
 
-        &mut self.data[i..i + self.w]
+        let i = self.offset(x, y);
+        &mut self.data[i]
     }
 }
 
@@ -70,7 +405,130 @@ fn test_slice() {
 
     let mut p = Pixmap::new(w, h, 0);
 
-    p[10][10] = 10;
+    p[(10, 10)] = 10;
+
+    assert!(p[(10, 10)] == p.data[10 * w + 10]);
+}
+
+#[test]
+fn test_non_square() {
+    let w = 192;
+    let h = 108;
+
+    let mut p = Pixmap::new(w, h, 0);
+    for y in 0..h {
+        for x in 0..w {
+            p[(x, y)] = x * 1000 + y;
+        }
+    }
+
+    assert_eq!(p.data[107 * w + 191], 191107);
+    assert_eq!(p.get(191, 107), Some(191107));
+    assert_eq!(p.get(107, 191), None);
+    assert_eq!(p.row(5)[7], 7005);
+    assert_eq!(p.column(7).nth(5), Some(&7005));
+    assert_eq!(p.rows().count(), h);
+    assert_eq!(p.columns().count(), w);
+}
+
+#[test]
+#[should_panic]
+fn test_index_out_of_range() {
+    let p = Pixmap::new(4, 2, 0);
+    let _ = p[(2, 3)];
+}
+
+#[test]
+fn test_stride() {
+    let mut p = Pixmap::with_stride(3, 2, 4, 0);
+    p[(2, 1)] = 5;
+    p.set(0, 1, 4);
+
+    assert_eq!(p.stride(), 4);
+    assert_eq!(p.data(), &[0, 0, 0, 0, 4, 0, 5, 0]);
+    assert_eq!(p.row(1), &[4, 0, 5]);
+
+    let rows: Vec<&[i32]> = p.rows().collect();
+    assert_eq!(rows, vec![&[0, 0, 0][..], &[4, 0, 5][..]]);
+    assert_eq!(p.column(2).cloned().collect::<Vec<i32>>(), vec![0, 5]);
+
+    let packed = Pixmap::from_vec(3, 2, vec![0, 0, 0, 4, 0, 5]).unwrap();
+    assert!(p == packed);
+    assert_eq!(p.map(|v| v).stride(), 3);
+
+    let v = p.view(1, 1, 2, 1).unwrap();
+    assert_eq!(v.to_pixmap().data(), &[0, 5]);
+
+    assert!(Pixmap::from_vec_with_stride(3, 2, 4, vec![0; 7]).is_none());
+    assert!(Pixmap::from_vec_with_stride(3, 2, 2, vec![0; 4]).is_none());
+}
+
+#[test]
+fn test_get_set() {
+    let mut p = Pixmap::new(3, 2, 0u16);
+
+    assert!(p.set(2, 1, 7));
+    assert!(!p.set(3, 1, 7));
+    assert!(!p.set(-1, 0, 7));
+
+    assert_eq!(p.get(2, 1), Some(7));
+    assert_eq!(p.get(1, 2), None);
+    assert_eq!(p.data()[5], 7);
+}
+
+#[test]
+fn test_edge_modes() {
+    let p = Pixmap::from_vec(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+    assert_eq!(p.get_with(-5, 0, EdgeMode::Clamp), 1);
+    assert_eq!(p.get_with(7, 9, EdgeMode::Clamp), 6);
+    assert_eq!(p.get_with(-1, 0, EdgeMode::Wrap), 3);
+    assert_eq!(p.get_with(4, 3, EdgeMode::Wrap), 5);
+    assert_eq!(p.get_with(-1, 0, EdgeMode::Mirror), 1);
+    assert_eq!(p.get_with(4, 0, EdgeMode::Mirror), 2);
+    assert_eq!(p.get_with(6, 2, EdgeMode::Mirror), 4);
+
+    // padding alone does not make the image non-empty
+    let empty = Pixmap::with_stride(0, 2, 3, 7);
+    for &mode in &[EdgeMode::Clamp, EdgeMode::Wrap, EdgeMode::Mirror] {
+        assert_eq!(empty.get_with(1, 1, mode), 0);
+    }
+}
+
+#[test]
+fn test_rows_columns() {
+    let p = Pixmap::from_vec(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+    let rows: Vec<Vec<i32>> = p.rows().map(|r| r.to_vec()).collect();
+    assert_eq!(rows, vec![vec![1, 2, 3], vec![4, 5, 6]]);
+
+    let columns: Vec<Vec<i32>> = p.columns().map(|c| c.cloned().collect()).collect();
+    assert_eq!(columns, vec![vec![1, 4], vec![2, 5], vec![3, 6]]);
+}
+
+#[test]
+fn test_view() {
+    let p = Pixmap::from_vec(4, 3, (0..12).collect()).unwrap();
+
+    assert!(p.view(2, 1, 3, 1).is_none());
+
+    let v = p.view(1, 1, 3, 2).unwrap();
+    assert_eq!(v.get(0, 0), Some(5));
+    assert_eq!(v.get(2, 1), Some(11));
+    assert_eq!(v.get(3, 0), None);
+
+    let inner = v.view(1, 0, 2, 2).unwrap();
+    assert_eq!(inner.to_pixmap().data(), &[6, 7, 10, 11]);
+}
+
+#[test]
+fn test_convert() {
+    let depth = Pixmap::from_vec(2, 1, vec![0.0f32, 1.0]).unwrap();
+    let gray: Pixmap<Rgba8> = depth.convert();
+
+    assert_eq!(gray.get(0, 0), Some(Rgba8::rgb(0, 0, 0)));
+    assert_eq!(gray.get(1, 0), Some(Rgba8::rgb(255, 255, 255)));
 
-    assert!(p[10][10] == p.data[10 * w + 10]);
+    let luma: Pixmap<u16> = gray.convert();
+    assert_eq!(luma.data(), &[0, 0xffff]);
 }
@@ -0,0 +1,707 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::result;
+
+use color::Rgba8;
+use image::ImageDecoder;
+use pixmap::Pixmap;
+use tgaimage::{ImageLoader, ImageWriter, Result};
+use zlib;
+use zlib::InflateError;
+
+pub type PngResult<T> = result::Result<T, PngError>;
+
+/// Describes why a PNG file could not be decoded.
+#[derive(Debug)]
+pub enum PngError {
+    Io(io::Error),
+    /// The data ended before the `IEND` chunk.
+    Truncated,
+    /// The file does not start with the PNG signature.
+    InvalidSignature,
+    /// The CRC of the named chunk does not match its contents.
+    ChecksumMismatch(String),
+    /// The named chunk has the wrong size or is in the wrong place.
+    InvalidChunk(String),
+    /// A critical chunk which is not understood.
+    UnsupportedChunk(String),
+    /// Combination of color type and bit depth which the standard doesn't allow.
+    UnsupportedColor {
+        color_type: u8,
+        bit_depth: u8,
+    },
+    /// Compression, filter or interlace method other than the standard ones.
+    UnsupportedMethod,
+    /// Palette image without a `PLTE` chunk.
+    MissingPalette,
+    /// A pixel refers to a palette entry which does not exist.
+    ColorIndexOutOfRange(usize),
+    /// A scanline filter type other than 0 to 4.
+    InvalidFilter(u8),
+    /// The image data could not be decompressed.
+    Inflate(InflateError),
+    /// The decompressed image data is shorter than the image.
+    MissingData,
+    /// Width or height is zero.
+    EmptyImage,
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PngError::Io(ref err) => write!(f, "png: {}", err),
+            PngError::Truncated => write!(f, "png: unexpected end of file"),
+            PngError::InvalidSignature => write!(f, "png: invalid signature"),
+            PngError::ChecksumMismatch(ref chunk) => write!(f, "png: crc mismatch in {}", chunk),
+            PngError::InvalidChunk(ref chunk) => write!(f, "png: invalid {} chunk", chunk),
+            PngError::UnsupportedChunk(ref chunk) => {
+                write!(f, "png: unsupported critical chunk {}", chunk)
+            }
+            PngError::UnsupportedColor { color_type, bit_depth } => {
+                write!(f,
+                       "png: bit depth {} is not allowed for color type {}",
+                       bit_depth,
+                       color_type)
+            }
+            PngError::UnsupportedMethod => {
+                write!(f, "png: unsupported compression, filter or interlace method")
+            }
+            PngError::MissingPalette => write!(f, "png: palette image without palette"),
+            PngError::ColorIndexOutOfRange(index) => {
+                write!(f, "png: color index {} is out of the palette", index)
+            }
+            PngError::InvalidFilter(filter) => write!(f, "png: invalid filter type {}", filter),
+            PngError::Inflate(err) => write!(f, "png: {}", err),
+            PngError::MissingData => write!(f, "png: image data is too short"),
+            PngError::EmptyImage => write!(f, "png: image has zero width or height"),
+        }
+    }
+}
+
+impl error::Error for PngError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PngError::Io(ref err) => Some(err),
+            PngError::Inflate(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PngError {
+    fn from(err: io::Error) -> PngError {
+        match err.kind() {
+            ErrorKind::UnexpectedEof => PngError::Truncated,
+            _ => PngError::Io(err),
+        }
+    }
+}
+
+impl From<InflateError> for PngError {
+    fn from(err: InflateError) -> PngError {
+        PngError::Inflate(err)
+    }
+}
+
+impl From<PngError> for io::Error {
+    fn from(err: PngError) -> io::Error {
+        match err {
+            PngError::Io(err) => err,
+            PngError::Truncated => Error::new(ErrorKind::UnexpectedEof, err),
+            err => Error::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Origin and spacing of the pixels in the seven passes of an interlaced image.
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8),
+                                                  (4, 0, 8, 8),
+                                                  (0, 4, 4, 8),
+                                                  (2, 0, 4, 4),
+                                                  (0, 2, 2, 4),
+                                                  (1, 0, 2, 2),
+                                                  (0, 1, 1, 2)];
+
+fn be32(bytes: &[u8]) -> u32 {
+    bytes.iter().take(4).fold(0, |v, &b| v << 8 | b as u32)
+}
+
+fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    table
+}
+
+/// CRC-32 of the chunk type followed by the chunk data.
+fn crc32(table: &[u32; 256], kind: &[u8], data: &[u8]) -> u32 {
+    let mut c = 0xffffffffu32;
+    for &byte in kind.iter().chain(data) {
+        c = table[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+fn chunk_name(kind: [u8; 4]) -> String {
+    String::from_utf8_lossy(&kind).into_owned()
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    /// Start and step of the pixels in every pass, a single one without interlacing.
+    fn passes(&self) -> &'static [(usize, usize, usize, usize)] {
+        if self.interlaced {
+            &ADAM7
+        } else {
+            &[(0, 0, 1, 1)]
+        }
+    }
+
+    /// Length of the filtered scanlines of all passes.
+    fn raw_size(&self) -> u64 {
+        let (w, h) = (self.width, self.height);
+        let bits_per_pixel = (self.channels() * self.bit_depth as usize) as u64;
+        let mut size = 0;
+        for &(x0, y0, dx, dy) in self.passes() {
+            if x0 < w && y0 < h {
+                let (pw, ph) = ((w - x0 + dx - 1) / dx, (h - y0 + dy - 1) / dy);
+                size += ph as u64 * (1 + (pw as u64 * bits_per_pixel + 7) / 8);
+            }
+        }
+        size
+    }
+
+    fn parse(bytes: &[u8]) -> PngResult<PngHeader> {
+        if bytes.len() != 13 {
+            return Err(PngError::InvalidChunk("IHDR".to_string()));
+        }
+        let (width, height) = (be32(&bytes[0..]), be32(&bytes[4..]));
+        let (bit_depth, color_type) = (bytes[8], bytes[9]);
+
+        let allowed = match color_type {
+            0 => [1, 2, 4, 8, 16].contains(&bit_depth),
+            3 => [1, 2, 4, 8].contains(&bit_depth),
+            2 | 4 | 6 => bit_depth == 8 || bit_depth == 16,
+            _ => false,
+        };
+        if !allowed {
+            return Err(PngError::UnsupportedColor {
+                color_type: color_type,
+                bit_depth: bit_depth,
+            });
+        }
+        if bytes[10] != 0 || bytes[11] != 0 || bytes[12] > 1 {
+            return Err(PngError::UnsupportedMethod);
+        }
+        if width == 0 || height == 0 {
+            return Err(PngError::EmptyImage);
+        }
+
+        Ok(PngHeader {
+            width: width as usize,
+            height: height as usize,
+            bit_depth: bit_depth,
+            color_type: color_type,
+            interlaced: bytes[12] == 1,
+        })
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// Reverses the filter of a scanline, `prev` is the unfiltered previous line.
+fn unfilter(filter: u8, bpp: usize, prev: &[u8], row: &mut [u8]) -> PngResult<()> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        2 => {
+            for i in 0..row.len() {
+                row[i] = row[i].wrapping_add(prev[i]);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] } else { 0 };
+                row[i] = row[i].wrapping_add(((left as u16 + prev[i] as u16) / 2) as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (a, b) = (if i >= bpp { row[i - bpp] } else { 0 }, prev[i]);
+                let c = if i >= bpp { prev[i - bpp] } else { 0 };
+                // the neighbour closest to the linear prediction
+                let p = a as i16 + b as i16 - c as i16;
+                let (pa, pb) = ((p - a as i16).abs(), (p - b as i16).abs());
+                let pc = (p - c as i16).abs();
+                let predictor = if pa <= pb && pa <= pc {
+                    a
+                } else if pb <= pc {
+                    b
+                } else {
+                    c
+                };
+                row[i] = row[i].wrapping_add(predictor);
+            }
+        }
+        filter => return Err(PngError::InvalidFilter(filter)),
+    }
+    Ok(())
+}
+
+/// PNG decoder for every color type and bit depth of the standard including
+/// interlaced images. 16 bit samples are rounded to 8 bits.
+pub struct PngImage;
+
+impl PngImage {
+    /// Same as `ImageLoader::load`, but reports exactly what was wrong with the file.
+    pub fn open(path: &str) -> PngResult<Pixmap> {
+        let file = try!(File::open(&Path::new(path)));
+        Self::read(&mut BufReader::new(file))
+    }
+
+    pub fn read<R: Read>(inp: &mut R) -> PngResult<Pixmap> {
+        let mut signature = [0u8; 8];
+        try!(inp.read_exact(&mut signature));
+        if signature != SIGNATURE {
+            return Err(PngError::InvalidSignature);
+        }
+
+        let table = crc_table();
+        let mut data = Vec::new();
+        if &try!(Self::read_chunk(inp, &table, &mut data)) != b"IHDR" {
+            return Err(PngError::InvalidChunk("IHDR".to_string()));
+        }
+        let header = try!(PngHeader::parse(&data));
+
+        let mut palette: Vec<Rgba8> = Vec::new();
+        // samples of the color which is transparent in gray and rgb images
+        let mut transparent: Option<[u16; 3]> = None;
+        let mut compressed = Vec::new();
+        loop {
+            let kind = try!(Self::read_chunk(inp, &table, &mut data));
+            match &kind {
+                b"IEND" => break,
+                b"IDAT" => compressed.extend_from_slice(&data),
+                b"PLTE" => {
+                    if data.is_empty() || data.len() % 3 != 0 || data.len() > 256 * 3 {
+                        return Err(PngError::InvalidChunk(chunk_name(kind)));
+                    }
+                    palette = data.chunks(3).map(|c| Rgba8::rgb(c[0], c[1], c[2])).collect();
+                }
+                b"tRNS" => {
+                    let sample = |i: usize| (data[i] as u16) << 8 | data[i + 1] as u16;
+                    match header.color_type {
+                        3 if data.len() <= palette.len() => {
+                            for (entry, &alpha) in palette.iter_mut().zip(data.iter()) {
+                                entry.a = alpha;
+                            }
+                        }
+                        0 if data.len() == 2 => transparent = Some([sample(0); 3]),
+                        2 if data.len() == 6 => {
+                            transparent = Some([sample(0), sample(2), sample(4)])
+                        }
+                        _ => return Err(PngError::InvalidChunk(chunk_name(kind))),
+                    }
+                }
+                // lowercase first letters mark chunks which may be ignored
+                _ if kind[0] & 0x20 == 0 => {
+                    return Err(PngError::UnsupportedChunk(chunk_name(kind)))
+                }
+                _ => {}
+            }
+        }
+        if header.color_type == 3 && palette.is_empty() {
+            return Err(PngError::MissingPalette);
+        }
+
+        // a small stream could inflate to gigabytes, stop at the size of the image
+        let limit = header.raw_size().min(usize::max_value() as u64) as usize;
+        let mut raw = try!(zlib::decompress(&compressed, limit));
+        Self::decode_pixels(&header, &palette, transparent, &mut raw)
+    }
+
+    /// Reads the next chunk into `data` and returns its type.
+    fn read_chunk<R: Read>(inp: &mut R,
+                           table: &[u32; 256],
+                           data: &mut Vec<u8>)
+                           -> PngResult<[u8; 4]> {
+        let mut head = [0u8; 8];
+        try!(inp.read_exact(&mut head));
+        let len = be32(&head);
+        let kind = [head[4], head[5], head[6], head[7]];
+        if len > 0x7fffffff {
+            return Err(PngError::InvalidChunk(chunk_name(kind)));
+        }
+
+        // the data grows while reading, so a bogus length can not force a huge allocation
+        data.clear();
+        try!(inp.by_ref().take(len as u64).read_to_end(data));
+        if data.len() < len as usize {
+            return Err(PngError::Truncated);
+        }
+        let mut crc = [0u8; 4];
+        try!(inp.read_exact(&mut crc));
+        if be32(&crc) != crc32(table, &kind, data) {
+            return Err(PngError::ChecksumMismatch(chunk_name(kind)));
+        }
+        Ok(kind)
+    }
+
+    fn decode_pixels(header: &PngHeader,
+                     palette: &[Rgba8],
+                     transparent: Option<[u16; 3]>,
+                     raw: &mut [u8])
+                     -> PngResult<Pixmap> {
+        let (w, h) = (header.width, header.height);
+        let channels = header.channels();
+        let depth = header.bit_depth as usize;
+        let bits_per_pixel = channels * depth;
+        // filters work on whole bytes, on the one to the left for less than 8 bits
+        let bpp = (bits_per_pixel / 8).max(1);
+
+        let sample = |row: &[u8], index: usize| -> u16 {
+            match depth {
+                8 => row[index] as u16,
+                16 => (row[2 * index] as u16) << 8 | row[2 * index + 1] as u16,
+                _ => {
+                    let bit = index * depth;
+                    ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16
+                }
+            }
+        };
+        let max = (1u32 << depth) - 1;
+        let to8 = |v: u16| match depth {
+            16 => ((v as u32 + 128) / 257) as u8,
+            _ => (v as u32 * 255 / max) as u8,
+        };
+
+        // the data has to cover the image before the pixmap is allocated
+        if (raw.len() as u64) < header.raw_size() {
+            return Err(PngError::MissingData);
+        }
+
+        let mut pixmap = Pixmap::new(w, h, Rgba8::default());
+        let mut offset = 0;
+        for &(x0, y0, dx, dy) in header.passes() {
+            if x0 >= w || y0 >= h {
+                continue;
+            }
+            let (pw, ph) = ((w - x0 + dx - 1) / dx, (h - y0 + dy - 1) / dy);
+            let stride = (pw * bits_per_pixel + 7) / 8;
+            let mut prev = vec![0u8; stride];
+
+            for j in 0..ph {
+                let filter = raw[offset];
+                let row = &mut raw[offset + 1..offset + 1 + stride];
+                try!(unfilter(filter, bpp, &prev, row));
+                offset += 1 + stride;
+
+                let y = y0 + j * dy;
+                for i in 0..pw {
+                    let s = |c: usize| sample(row, i * channels + c);
+                    let color = match header.color_type {
+                        0 => {
+                            let alpha = if transparent == Some([s(0); 3]) { 0 } else { 255 };
+                            let g = to8(s(0));
+                            Rgba8::new(g, g, g, alpha)
+                        }
+                        2 => {
+                            let rgb = [s(0), s(1), s(2)];
+                            let alpha = if transparent == Some(rgb) { 0 } else { 255 };
+                            Rgba8::new(to8(rgb[0]), to8(rgb[1]), to8(rgb[2]), alpha)
+                        }
+                        3 => {
+                            let index = s(0) as usize;
+                            match palette.get(index) {
+                                Some(&color) => color,
+                                None => return Err(PngError::ColorIndexOutOfRange(index)),
+                            }
+                        }
+                        4 => {
+                            let g = to8(s(0));
+                            Rgba8::new(g, g, g, to8(s(1)))
+                        }
+                        _ => Rgba8::new(to8(s(0)), to8(s(1)), to8(s(2)), to8(s(3))),
+                    };
+                    pixmap[(x0 + i * dx, y)] = color;
+                }
+                prev.copy_from_slice(row);
+            }
+        }
+
+        Ok(pixmap)
+    }
+}
+
+impl ImageLoader for PngImage {
+    fn read_from<R: BufRead>(inp: &mut R) -> Result<Pixmap> {
+        Self::read(inp).map_err(io::Error::from)
+    }
+}
+
+impl ImageDecoder for PngImage {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png"]
+    }
+
+    fn detect(&self, header: &[u8]) -> bool {
+        header.starts_with(&SIGNATURE)
+    }
+
+    fn decode(&self, mut inp: &mut dyn BufRead) -> Result<Pixmap> {
+        Self::read(&mut inp).map_err(io::Error::from)
+    }
+}
+
+/// PNG encoder for 8 bit rgb or rgba images. The image data is stored without
+/// compression, which keeps the encoder small but the files as large as TGA.
+#[derive(Copy, Clone, Debug)]
+pub struct PngWriter {
+    pub alpha: bool,
+}
+
+impl Default for PngWriter {
+    fn default() -> PngWriter {
+        PngWriter { alpha: false }
+    }
+}
+
+impl PngWriter {
+    pub fn new(alpha: bool) -> PngWriter {
+        PngWriter { alpha: alpha }
+    }
+
+    pub fn write<W: Write>(&self, pixmap: &Pixmap, out: &mut W) -> Result<()> {
+        let (w, h) = (pixmap.width(), pixmap.height());
+        if w == 0 || h == 0 || w > 0x7fffffff || h > 0x7fffffff {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid image size for png"));
+        }
+        let table = crc_table();
+        try!(out.write_all(&SIGNATURE));
+
+        let mut header = Vec::with_capacity(13);
+        for &v in &[w as u32, h as u32] {
+            header.extend_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]);
+        }
+        let color_type = if self.alpha { 6 } else { 2 };
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        try!(Self::write_chunk(out, &table, b"IHDR", &header));
+
+        // every scanline starts with filter type 0, none
+        let channels = if self.alpha { 4 } else { 3 };
+        let mut raw = Vec::with_capacity(h * (1 + w * channels));
+        for row in pixmap.rows() {
+            raw.push(0);
+            for c in row {
+                raw.extend_from_slice(&[c.r, c.g, c.b]);
+                if self.alpha {
+                    raw.push(c.a);
+                }
+            }
+        }
+        for data in zlib::compress_stored(&raw).chunks(1 << 20) {
+            try!(Self::write_chunk(out, &table, b"IDAT", data));
+        }
+        Self::write_chunk(out, &table, b"IEND", &[])
+    }
+
+    fn write_chunk<W: Write>(out: &mut W,
+                             table: &[u32; 256],
+                             kind: &[u8; 4],
+                             data: &[u8])
+                             -> Result<()> {
+        let len = data.len() as u32;
+        let crc = crc32(table, kind, data);
+        try!(out.write_all(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]));
+        try!(out.write_all(kind));
+        try!(out.write_all(data));
+        out.write_all(&[(crc >> 24) as u8, (crc >> 16) as u8, (crc >> 8) as u8, crc as u8])
+    }
+}
+
+impl ImageWriter for PngWriter {
+    fn save(&self, pixmap: &Pixmap, path: &str) -> Result<()> {
+        let mut file = BufWriter::new(try!(File::create(&Path::new(path))));
+        try!(self.write(pixmap, &mut file));
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, crc_table, unfilter, PngError, PngImage, PngWriter};
+    use color::Rgba8;
+    use pixmap::Pixmap;
+    use zlib;
+
+    fn decode(bytes: &[u8]) -> Pixmap {
+        PngImage::read(&mut &bytes[..]).unwrap()
+    }
+
+    fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let len = data.len();
+        let mut bytes = vec![0, 0, (len >> 8) as u8, len as u8];
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(data);
+        let crc = crc32(&crc_table(), kind, data);
+        bytes.extend_from_slice(&[(crc >> 24) as u8, (crc >> 16) as u8, (crc >> 8) as u8,
+                                  crc as u8]);
+        bytes
+    }
+
+    /// PNG file of the given header fields, extra chunks and uncompressed image data.
+    fn png(w: u8, h: u8, bit_depth: u8, color_type: u8, interlace: u8, extra: &[Vec<u8>],
+           raw: &[u8])
+           -> Vec<u8> {
+        let mut bytes = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        let header = [0, 0, 0, w, 0, 0, 0, h, bit_depth, color_type, 0, 0, interlace];
+        bytes.extend(chunk(b"IHDR", &header));
+        for data in extra {
+            bytes.extend_from_slice(data);
+        }
+        bytes.extend(chunk(b"IDAT", &zlib::compress_stored(raw)));
+        bytes.extend(chunk(b"IEND", &[]));
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut pixmap = Pixmap::new(7, 5, Rgba8::default());
+        for y in 0..5 {
+            for x in 0..7 {
+                pixmap[(x, y)] = Rgba8::new((x * 30) as u8, (y * 50) as u8, 99, (x * y * 8) as u8);
+            }
+        }
+        let mut out = Vec::new();
+        PngWriter::new(true).write(&pixmap, &mut out).unwrap();
+        assert!(decode(&out) == pixmap);
+
+        out.clear();
+        PngWriter::new(false).write(&pixmap, &mut out).unwrap();
+        assert!(decode(&out) == pixmap.map(|c| Rgba8::rgb(c.r, c.g, c.b)));
+    }
+
+    #[test]
+    fn test_filters() {
+        let prev = [10, 20, 30, 40];
+        let cases: [(u8, [u8; 4]); 5] = [(0, [1, 2, 3, 4]),
+                                         (1, [1, 2, 4, 6]),
+                                         (2, [11, 22, 33, 44]),
+                                         (3, [6, 12, 21, 30]),
+                                         (4, [11, 22, 33, 44])];
+        for &(filter, expected) in &cases {
+            let mut row = [1, 2, 3, 4];
+            unfilter(filter, 2, &prev, &mut row).unwrap();
+            assert_eq!(row, expected, "filter {}", filter);
+        }
+        match unfilter(5, 1, &prev, &mut [0; 4]) {
+            Err(PngError::InvalidFilter(5)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_color_types() {
+        // 2 bit gray, a sample per two bits and rows padded to bytes
+        let pixmap = decode(&png(3, 1, 2, 0, 0, &[], &[0, 0b00_01_11_00]));
+        assert_eq!(pixmap.row(0), &[Rgba8::from(0u8), Rgba8::from(85u8), Rgba8::from(255u8)]);
+
+        // 16 bit gray with a transparent value
+        let trns = chunk(b"tRNS", &[0x12, 0x34]);
+        let pixmap = decode(&png(2, 1, 16, 0, 0, &[trns], &[0, 0x12, 0x34, 0xff, 0xff]));
+        assert_eq!(pixmap.row(0), &[Rgba8::new(0x12, 0x12, 0x12, 0), Rgba8::from(255u8)]);
+
+        // palette with alpha for the first entry
+        let plte = chunk(b"PLTE", &[255, 0, 0, 0, 0, 255]);
+        let trns = chunk(b"tRNS", &[128]);
+        let pixmap = decode(&png(3, 1, 1, 3, 0, &[plte.clone(), trns], &[0, 0b0100_0000]));
+        assert_eq!(pixmap.row(0),
+                   &[Rgba8::new(255, 0, 0, 128),
+                     Rgba8::rgb(0, 0, 255),
+                     Rgba8::new(255, 0, 0, 128)]);
+        match PngImage::read(&mut &png(2, 1, 2, 3, 0, &[plte], &[0, 0b0010_0000])[..]) {
+            Err(PngError::ColorIndexOutOfRange(2)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        // gray and alpha
+        let pixmap = decode(&png(1, 1, 8, 4, 0, &[], &[0, 50, 60]));
+        assert_eq!(pixmap.row(0), &[Rgba8::new(50, 50, 50, 60)]);
+    }
+
+    #[test]
+    fn test_interlaced() {
+        // 3x3 gray image, passes 1, 4, 5, 6 and 7 are not empty
+        let pixels = [[1, 2, 3], [4, 5, 6], [7, 8, 9]];
+        let raw = [0, pixels[0][0], // pass 1: (0, 0)
+                   0, pixels[0][2], // pass 4: (2, 0)
+                   0, pixels[2][0], pixels[2][2], // pass 5: (0, 2) and (2, 2)
+                   0, pixels[0][1], 0, pixels[2][1], // pass 6: x = 1 in rows 0 and 2
+                   0, pixels[1][0], pixels[1][1], pixels[1][2]]; // pass 7: row 1
+        let pixmap = decode(&png(3, 3, 8, 0, 1, &[], &raw));
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(pixmap[(x, y)], Rgba8::from(pixels[y][x]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let check = |bytes: &[u8], expected: &str| {
+            match PngImage::read(&mut &bytes[..]) {
+                Err(err) => assert_eq!(format!("{:?}", err), expected),
+                Ok(_) => panic!("{:?} should not be decoded", bytes),
+            }
+        };
+
+        let valid = png(1, 1, 8, 2, 0, &[], &[0, 1, 2, 3]);
+        check(&valid[..valid.len() - 12], "Truncated");
+        check(&valid[1..], "InvalidSignature");
+        let mut bad = valid.clone();
+        bad[20] ^= 1;
+        check(&bad, "ChecksumMismatch(\"IHDR\")");
+
+        check(&png(1, 1, 4, 2, 0, &[], &[]), "UnsupportedColor { color_type: 2, bit_depth: 4 }");
+        check(&png(1, 1, 8, 2, 2, &[], &[]), "UnsupportedMethod");
+        check(&png(0, 1, 8, 2, 0, &[], &[]), "EmptyImage");
+        check(&png(1, 1, 8, 3, 0, &[], &[0, 0]), "MissingPalette");
+        check(&png(1, 1, 8, 2, 0, &[chunk(b"ABCD", &[])], &[]), "UnsupportedChunk(\"ABCD\")");
+        check(&png(1, 1, 8, 2, 0, &[chunk(b"tRNS", &[0])], &[]), "InvalidChunk(\"tRNS\")");
+        check(&png(2, 1, 8, 2, 0, &[], &[0, 1, 2, 3]), "MissingData");
+        check(&png(1, 1, 8, 2, 0, &[], &[0; 5000]), "Inflate(OutputTooLarge)");
+        check(&png(1, 1, 8, 2, 0, &[], &[7, 1, 2, 3]), "InvalidFilter(7)");
+        // ancillary chunks are skipped
+        assert_eq!(decode(&png(1, 1, 8, 2, 0, &[chunk(b"tEXt", b"a\0b")], &[0, 1, 2, 3])).row(0),
+                   &[Rgba8::rgb(1, 2, 3)]);
+    }
+}
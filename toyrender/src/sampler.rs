@@ -0,0 +1,299 @@
+use color::Rgba8;
+use pixmap::{EdgeMode, Pixmap};
+use texture::Texture;
+use vector3d::Vec3f;
+
+/// How texels around a sample position are combined.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// The texel the position falls into.
+    Nearest,
+    /// Weighted average of the four closest texel centers.
+    Bilinear,
+}
+
+/// How `Sampler::sample_grad` picks mip levels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MipFilter {
+    /// Always the full resolution level.
+    None,
+    /// The level closest to the texel footprint.
+    Nearest,
+    /// Blend of the two closest levels, trilinear filtering with `Filter::Bilinear`.
+    Linear,
+}
+
+/// Reads textures at normalized coordinates. `u` runs from the left to the right and
+/// `v` from the bottom to the top edge of the texture, `(0, 0)` is the bottom left
+/// corner as in Wavefront files. Coordinates outside of `[0, 1]` are addressed with
+/// `address_u` and `address_v`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub mip_filter: MipFilter,
+    /// Upper bound of the samples taken along the long axis of a stretched footprint,
+    /// one disables anisotropic filtering.
+    pub max_anisotropy: u32,
+    pub address_u: EdgeMode,
+    pub address_v: EdgeMode,
+}
+
+/// Moves a texel coordinate close to the texture without changing what `mode`
+/// addresses, so that it and its neighbour fit into an `i32`. The address modes
+/// repeat after `2 * n` texels, clamping only needs a texel of margin.
+fn fold(c: f32, mode: EdgeMode, n: i32) -> f32 {
+    match mode {
+        EdgeMode::Clamp => c.max(-1.0).min(n as f32 + 1.0),
+        EdgeMode::Wrap | EdgeMode::Mirror => c % (2 * n) as f32,
+    }
+}
+
+fn lerp(a: Rgba8, b: Rgba8, t: f32) -> Rgba8 {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t + 0.5) as u8;
+    Rgba8::new(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b), mix(a.a, b.a))
+}
+
+impl Sampler {
+    /// Trilinear filtering of a repeating texture.
+    pub fn new() -> Sampler {
+        Sampler {
+            filter: Filter::Bilinear,
+            mip_filter: MipFilter::Linear,
+            max_anisotropy: 1,
+            address_u: EdgeMode::Wrap,
+            address_v: EdgeMode::Wrap,
+        }
+    }
+
+    pub fn with_filter(filter: Filter) -> Sampler {
+        Sampler { filter: filter, ..Sampler::new() }
+    }
+
+    /// Color at `(uv.x, uv.y)`, transparent black if the texture is empty.
+    pub fn sample(&self, texture: &Pixmap, uv: Vec3f) -> Rgba8 {
+        let (w, h) = (texture.width() as i32, texture.height() as i32);
+        if w == 0 || h == 0 {
+            return Rgba8::default();
+        }
+
+        // texel `(x, y)` spans `[x, x + 1)`, rows start at the top
+        let x = fold(uv.x * w as f32, self.address_u, w);
+        let y = fold((1.0 - uv.y) * h as f32, self.address_v, h);
+        let column = |x: i32| if x >= 0 && x < w { x } else { self.address_u.apply(x, w) };
+        let row = |y: i32| if y >= 0 && y < h { y } else { self.address_v.apply(y, h) };
+
+        match self.filter {
+            Filter::Nearest => {
+                texture[(column(x.floor() as i32) as usize, row(y.floor() as i32) as usize)]
+            }
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                // 8 bit fixed point weights of the right column and the lower row
+                let tx = ((x - x0) * 256.0) as u32;
+                let ty = ((y - y0) * 256.0) as u32;
+                let (x0, y0) = (x0 as i32, y0 as i32);
+
+                let (left, right) = (column(x0) as usize, column(x0 + 1) as usize);
+                let upper = texture.row(row(y0) as usize);
+                let lower = texture.row(row(y0 + 1) as usize);
+                let mix = |a: Rgba8, b: Rgba8| {
+                    let lerp = |a: u8, b: u8| a as u32 * (256 - tx) + b as u32 * tx;
+                    [lerp(a.r, b.r), lerp(a.g, b.g), lerp(a.b, b.b), lerp(a.a, b.a)]
+                };
+                let top = mix(upper[left], upper[right]);
+                let bottom = mix(lower[left], lower[right]);
+                let channel = |i: usize| {
+                    ((top[i] * (256 - ty) + bottom[i] * ty + (1 << 15)) >> 16) as u8
+                };
+                Rgba8::new(channel(0), channel(1), channel(2), channel(3))
+            }
+        }
+    }
+
+    /// Color at `uv` for a pixel whose texture coordinates change by `ddx` towards the
+    /// next pixel to the right and by `ddy` towards the one below. The longer of the
+    /// two selects the mip level, with anisotropic filtering several samples are taken
+    /// along it from a correspondingly sharper level.
+    pub fn sample_grad(&self, texture: &Texture, uv: Vec3f, ddx: Vec3f, ddy: Vec3f) -> Rgba8 {
+        // footprint in texels of the full resolution level
+        let (w, h) = (texture.width() as f32, texture.height() as f32);
+        let texels = |d: Vec3f| ((d.x * w) * (d.x * w) + (d.y * h) * (d.y * h)).sqrt();
+        let (lx, ly) = (texels(ddx), texels(ddy));
+        let (major, major_len, minor_len) = if lx >= ly { (ddx, lx, ly) } else { (ddy, ly, lx) };
+
+        let mut samples = 1;
+        if self.max_anisotropy > 1 && major_len > 1.0 {
+            let ratio = if minor_len > 0.0 { major_len / minor_len } else { major_len };
+            samples = (ratio.ceil() as u32).max(1).min(self.max_anisotropy);
+        }
+        let lod = (major_len / samples as f32).log2();
+        if samples == 1 {
+            return self.sample_lod(texture, uv, lod);
+        }
+
+        let mut sum = [0u32; 4];
+        for i in 0..samples {
+            let offset = (i as f32 + 0.5) / samples as f32 - 0.5;
+            let c = self.sample_lod(texture, uv + major * offset, lod);
+            sum[0] += c.r as u32;
+            sum[1] += c.g as u32;
+            sum[2] += c.b as u32;
+            sum[3] += c.a as u32;
+        }
+        let channel = |v: u32| ((v + samples / 2) / samples) as u8;
+        Rgba8::new(channel(sum[0]), channel(sum[1]), channel(sum[2]), channel(sum[3]))
+    }
+
+    /// Color at `uv` from mip level `lod`, fractional levels are blended with
+    /// `MipFilter::Linear`.
+    pub fn sample_lod(&self, texture: &Texture, uv: Vec3f, lod: f32) -> Rgba8 {
+        let last = (texture.levels() - 1) as f32;
+        match self.mip_filter {
+            MipFilter::None => self.sample(texture.base(), uv),
+            MipFilter::Nearest => {
+                let level = lod.round().max(0.0).min(last);
+                self.sample(texture.level(level as usize), uv)
+            }
+            MipFilter::Linear => {
+                // also catches the `-inf` of a zero footprint
+                if !(lod > 0.0) {
+                    return self.sample(texture.base(), uv);
+                }
+                if lod >= last {
+                    return self.sample(texture.level(last as usize), uv);
+                }
+                let level = lod.floor();
+                let fine = self.sample(texture.level(level as usize), uv);
+                let coarse = self.sample(texture.level(level as usize + 1), uv);
+                lerp(fine, coarse, lod - level)
+            }
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, MipFilter, Sampler};
+    use color::Rgba8;
+    use pixmap::{EdgeMode, Pixmap};
+    use texture::{Downsample, Texture};
+    use vector3d::Vec3f;
+
+    fn checker() -> Pixmap {
+        // black and white columns, the bottom row is red
+        let (b, w, r) = (Rgba8::rgb(0, 0, 0), Rgba8::rgb(200, 200, 200), Rgba8::rgb(255, 0, 0));
+        Pixmap::from_vec(2, 2, vec![b, w, r, r]).unwrap()
+    }
+
+    #[test]
+    fn test_nearest() {
+        let texture = checker();
+        let sampler = Sampler::with_filter(Filter::Nearest);
+        let at = |u: f32, v: f32| sampler.sample(&texture, Vec3f::new(u, v, 0.0));
+
+        assert_eq!(at(0.25, 0.75), Rgba8::rgb(0, 0, 0));
+        assert_eq!(at(0.75, 0.75), Rgba8::rgb(200, 200, 200));
+        assert_eq!(at(0.25, 0.25), Rgba8::rgb(255, 0, 0));
+        // repeated
+        assert_eq!(at(1.75, -0.25), Rgba8::rgb(200, 200, 200));
+    }
+
+    #[test]
+    fn test_bilinear_and_addressing() {
+        let texture = checker();
+        let mut sampler = Sampler::new();
+        let at = |sampler: &Sampler, u: f32, v: f32| {
+            sampler.sample(&texture, Vec3f::new(u, v, 0.0))
+        };
+
+        // texel centers are exact, halfway between them is the average
+        assert_eq!(at(&sampler, 0.75, 0.75), Rgba8::rgb(200, 200, 200));
+        assert_eq!(at(&sampler, 0.5, 0.75), Rgba8::rgb(100, 100, 100));
+        assert_eq!(at(&sampler, 0.25, 0.5), Rgba8::rgb(128, 0, 0));
+
+        // at the left edge the repeating texture blends in the right column
+        assert_eq!(at(&sampler, 0.0, 0.75), Rgba8::rgb(100, 100, 100));
+        sampler.address_u = EdgeMode::Clamp;
+        assert_eq!(at(&sampler, 0.0, 0.75), Rgba8::rgb(0, 0, 0));
+        sampler.address_u = EdgeMode::Mirror;
+        assert_eq!(at(&sampler, -0.25, 0.75), Rgba8::rgb(0, 0, 0));
+        assert_eq!(at(&sampler, 1.25, 0.75), Rgba8::rgb(200, 200, 200));
+    }
+
+    #[test]
+    fn test_far_coordinates() {
+        let texture = checker();
+        for &mode in &[EdgeMode::Clamp, EdgeMode::Wrap, EdgeMode::Mirror] {
+            for &filter in &[Filter::Nearest, Filter::Bilinear] {
+                let sampler = Sampler {
+                    filter: filter,
+                    address_u: mode,
+                    address_v: mode,
+                    ..Sampler::new()
+                };
+                let at = |u: f32, v: f32| sampler.sample(&texture, Vec3f::new(u, v, 0.0));
+                for &c in &[f32::INFINITY, f32::NEG_INFINITY, f32::NAN, 3e9, -3e9, 1e38] {
+                    at(c, 0.75);
+                    at(0.25, c);
+                }
+                // whole periods further away address the same texels
+                if mode != EdgeMode::Clamp {
+                    assert_eq!(at(1e6 + 0.25, 0.75), at(0.25, 0.75));
+                    assert_eq!(at(-1e6 + 0.5, 0.75), at(0.5, 0.75));
+                }
+            }
+        }
+        let sampler = Sampler { address_u: EdgeMode::Clamp, ..Sampler::new() };
+        assert_eq!(sampler.sample(&texture, Vec3f::new(3e9, 0.75, 0.0)), Rgba8::rgb(200, 200, 200));
+    }
+
+    #[test]
+    fn test_mip_levels() {
+        // white and black columns average to gray one level down
+        let (b, w) = (Rgba8::rgb(0, 0, 0), Rgba8::rgb(255, 255, 255));
+        let columns = (0..16).map(|i| if i % 2 == 0 { w } else { b }).collect();
+        let image = Pixmap::from_vec(4, 4, columns);
+        let texture = Texture::with_mipmaps(image.unwrap(), Downsample::Box);
+        let sampler = Sampler::with_filter(Filter::Nearest);
+        let uv = Vec3f::new(0.1, 0.5, 0.0);
+        let zero = Vec3f::zero();
+
+        // one texel per pixel reads the full resolution level
+        let dx = Vec3f::new(0.25, 0.0, 0.0);
+        assert_eq!(sampler.sample_grad(&texture, uv, dx, zero), w);
+        // two texels per pixel read level 1, three blend levels 1 and 2
+        assert_eq!(sampler.sample_grad(&texture, uv, dx * 2.0, zero), Rgba8::rgb(128, 128, 128));
+        assert_eq!(sampler.sample_lod(&texture, uv, 1.5), Rgba8::rgb(128, 128, 128));
+        assert_eq!(sampler.sample_lod(&texture, uv, 0.5), Rgba8::rgb(192, 192, 192));
+
+        let sampler = Sampler { mip_filter: MipFilter::None, ..sampler };
+        assert_eq!(sampler.sample_grad(&texture, uv, dx * 2.0, zero), w);
+    }
+
+    #[test]
+    fn test_anisotropic() {
+        // white upper and black lower half, the footprint is stretched along the rows
+        let (b, w) = (Rgba8::rgb(0, 0, 0), Rgba8::rgb(255, 255, 255));
+        let image = Pixmap::from_vec(4, 4, (0..16).map(|i| if i < 8 { w } else { b }).collect());
+        let texture = Texture::with_mipmaps(image.unwrap(), Downsample::Box);
+        let mut sampler = Sampler::with_filter(Filter::Nearest);
+        sampler.mip_filter = MipFilter::Nearest;
+        let uv = Vec3f::new(0.5, 0.75, 0.0);
+        let (dx, dy) = (Vec3f::new(1.0, 0.0, 0.0), Vec3f::new(0.0, 0.25, 0.0));
+
+        // isotropic filtering blurs the columns as well by going down to the 1x1 level
+        assert_eq!(sampler.sample_grad(&texture, uv, dx, dy), Rgba8::rgb(128, 128, 128));
+        // several samples along the rows from a sharper level keep the halves apart
+        sampler.max_anisotropy = 2;
+        assert_eq!(sampler.sample_grad(&texture, uv, dx, dy), w);
+        sampler.max_anisotropy = 16;
+        assert_eq!(sampler.sample_grad(&texture, uv, dx, dy), w);
+    }
+}
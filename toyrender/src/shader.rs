@@ -0,0 +1,334 @@
+use std::ops::{Add, Mul, Sub};
+
+use color::Rgba8;
+use matrix::{Matrix4, Transforms, Vec4f};
+use model::Model;
+use shading::{Light, Surface};
+use vector3d::{Vec3f, Vertex};
+
+/// Per-vertex shader outputs, interpolated linearly across the triangle.
+pub trait Varying: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self> {}
+
+impl<T> Varying for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T> {}
+
+pub trait VertexShader {
+    type Input;
+    type Varying: Varying;
+
+    /// Clip space position of the vertex, usually after a projection from
+    /// `Matrix4`, along with the varyings handed to the fragment shader.
+    fn vertex(&self, input: &Self::Input) -> (Vec4f, Self::Varying);
+}
+
+/// Screen space derivatives of a varying, shared by the 2x2 quad of pixels a fragment
+/// lies in.
+#[derive(Copy, Clone, Debug)]
+pub struct Derivatives<V> {
+    /// Change towards the next pixel to the right.
+    pub ddx: V,
+    /// Change towards the next pixel below.
+    pub ddy: V,
+}
+
+pub trait FragmentShader<V> {
+    /// Color of the fragment, `None` discards it.
+    fn fragment(&self, varying: &V) -> Option<Rgba8>;
+
+    /// Whether the canvas calls `fragment_with_derivatives` instead of `fragment`,
+    /// the derivatives cost two more interpolations per quad.
+    fn uses_derivatives(&self) -> bool {
+        false
+    }
+
+    /// Same as `fragment`, e.g. to pick the mip level of a texture.
+    fn fragment_with_derivatives(&self,
+                                 varying: &V,
+                                 _derivatives: &Derivatives<V>)
+                                 -> Option<Rgba8> {
+        self.fragment(varying)
+    }
+}
+
+/// Corners of `Model` faces given as `(face, corner)`, projected by `Transforms`.
+/// The vertex attributes become the varyings, transformed to world space.
+pub struct ModelShader<'a> {
+    model: &'a Model,
+    transforms: Transforms,
+    model_view_projection: Matrix4,
+    normal_matrix: Matrix4,
+}
+
+impl<'a> ModelShader<'a> {
+    pub fn new(model: &'a Model, transforms: Transforms) -> ModelShader<'a> {
+        ModelShader {
+            model: model,
+            transforms: transforms,
+            model_view_projection: transforms.model_view_projection(),
+            normal_matrix: transforms.normal_matrix(),
+        }
+    }
+}
+
+impl<'a> VertexShader for ModelShader<'a> {
+    type Input = (usize, usize);
+    type Varying = Vertex;
+
+    fn vertex(&self, &(iface, nvert): &(usize, usize)) -> (Vec4f, Vertex) {
+        let model = self.model;
+        let position = model.verticies[model.faces[iface][nvert][0] as usize];
+
+        let (tangent, bitangent) = model.tangent_frame(iface, nvert);
+        let to_world = &self.transforms.model;
+        let varying = Vertex {
+            pos: to_world.transform_point(position),
+            uv: model.uv(iface, nvert),
+            norm: self.normal_matrix.transform_vector(model.normal(iface, nvert)).normalized(),
+            tangent: to_world.transform_vector(tangent),
+            bitangent: to_world.transform_vector(bitangent),
+        };
+        (self.model_view_projection * Vec4f::point(position), varying)
+    }
+}
+
+/// Per fragment lighting of a `Surface`, see `Surface::shade`.
+pub struct PhongShader<'a> {
+    pub surface: Surface<'a>,
+    pub light: Light,
+    /// Direction from the surface to the viewer.
+    pub eye_dir: Vec3f,
+}
+
+impl<'a> FragmentShader<Vertex> for PhongShader<'a> {
+    fn fragment(&self, p: &Vertex) -> Option<Rgba8> {
+        Some(self.surface.shade(p, &self.light, self.eye_dir))
+    }
+
+    fn uses_derivatives(&self) -> bool {
+        true
+    }
+
+    fn fragment_with_derivatives(&self, p: &Vertex, d: &Derivatives<Vertex>) -> Option<Rgba8> {
+        Some(self.surface.shade_grad(p, d.ddx.uv, d.ddy.uv, &self.light, self.eye_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FragmentShader, ModelShader, VertexShader};
+    use canvas::Canvas;
+    use color::Rgba8;
+    use matrix::{Matrix4, Transforms, Vec4f};
+    use model::NormalSpace;
+    use pixmap::Pixmap;
+    use shading::{Light, Surface};
+    use texture::Texture;
+    use trianglerasterizer::{CullMode, RasterizerState, Winding};
+    use vector3d::Vec3f;
+    use wavefront::parse_obj;
+
+    /// Maps pixel positions of a 32x32 canvas to clip space, the varying is the
+    /// x coordinate.
+    struct Passthrough;
+
+    impl VertexShader for Passthrough {
+        type Input = Vec3f;
+        type Varying = f32;
+
+        fn vertex(&self, pos: &Vec3f) -> (Vec4f, f32) {
+            let ndc = Vec3f::new(pos.x / 16.0 - 1.0, 1.0 - pos.y / 16.0, 0.0);
+            (Vec4f::point(ndc), pos.x)
+        }
+    }
+
+    /// Two tone shading of the varying, fragments left of `cutoff` are discarded.
+    struct Toon {
+        cutoff: f32,
+    }
+
+    impl FragmentShader<f32> for Toon {
+        fn fragment(&self, x: &f32) -> Option<Rgba8> {
+            if *x < self.cutoff {
+                None
+            } else if *x < 16.0 {
+                Some(Rgba8::rgb(100, 100, 100))
+            } else {
+                Some(Rgba8::rgb(200, 200, 200))
+            }
+        }
+    }
+
+    #[test]
+    fn test_varyings_and_discard() {
+        let mut canvas = Canvas::new(32, 32);
+        let triangle = [Vec3f::new(0.0, 0.0, 0.0),
+                        Vec3f::new(31.0, 0.0, 0.0),
+                        Vec3f::new(0.0, 31.0, 0.0)];
+        canvas.draw(&Passthrough, &Toon { cutoff: 4.0 }, Some(triangle));
+
+        let buffer = canvas.buffer();
+        assert_eq!(buffer.get(2, 2), Some(Rgba8::default()));
+        assert_eq!(buffer.get(8, 2), Some(Rgba8::rgb(100, 100, 100)));
+        assert_eq!(buffer.get(20, 2), Some(Rgba8::rgb(200, 200, 200)));
+        assert_eq!(buffer.get(20, 20), Some(Rgba8::default()));
+    }
+
+    /// Clip space positions with a varying each.
+    struct Clip;
+
+    impl VertexShader for Clip {
+        type Input = (Vec4f, f32);
+        type Varying = f32;
+
+        fn vertex(&self, input: &(Vec4f, f32)) -> (Vec4f, f32) {
+            *input
+        }
+    }
+
+    struct Gray;
+
+    impl FragmentShader<f32> for Gray {
+        fn fragment(&self, v: &f32) -> Option<Rgba8> {
+            Some(Rgba8::from(*v))
+        }
+    }
+
+    #[test]
+    fn test_perspective_correct() {
+        let mut canvas = Canvas::new(32, 32);
+        // the right corner is three times as far away as the left edge
+        let triangle = [(Vec4f::new(-1.0, -1.0, 0.0, 1.0), 0.0),
+                        (Vec4f::new(3.0, 0.0, 0.0, 3.0), 1.0),
+                        (Vec4f::new(-1.0, 1.0, 0.0, 1.0), 0.0)];
+        canvas.draw(&Clip, &Gray, Some(triangle));
+
+        // halfway on screen is only a quarter of the way in the triangle
+        let center = canvas.buffer().get(16, 16).unwrap().r;
+        assert!(center > 56 && center < 72, "{}", center);
+    }
+
+    #[test]
+    fn test_clipping() {
+        let mut canvas = Canvas::new(32, 32);
+        // the top corner is behind the camera, the near plane cuts off the upper half
+        let triangle = [(Vec4f::new(-0.5, -0.5, 0.0, 1.0), 1.0),
+                        (Vec4f::new(0.5, -0.5, 0.0, 1.0), 1.0),
+                        (Vec4f::new(0.0, 2.0, -3.0, -1.0), 1.0)];
+        canvas.draw(&Clip, &Gray, Some(triangle));
+        assert_eq!(canvas.buffer().get(16, 20), Some(Rgba8::rgb(255, 255, 255)));
+        assert_eq!(canvas.buffer().get(16, 12), Some(Rgba8::default()));
+
+        // far beyond the screen edges
+        let triangle = [(Vec4f::new(-50.0, -50.0, 0.5, 1.0), 1.0),
+                        (Vec4f::new(50.0, -50.0, 0.5, 1.0), 1.0),
+                        (Vec4f::new(0.0, 90.0, 0.5, 1.0), 1.0)];
+        canvas.draw(&Clip, &Gray, Some(triangle));
+        assert!(canvas.buffer().data().iter().all(|c| *c == Rgba8::rgb(255, 255, 255)));
+    }
+
+    #[test]
+    fn test_culling() {
+        let white = Some(Rgba8::rgb(255, 255, 255));
+        // counter-clockwise on screen, the top corner is behind the camera and would flip
+        // the winding if it were projected without clipping first
+        let front = [(Vec4f::new(-0.5, -0.5, 0.0, 1.0), 1.0),
+                     (Vec4f::new(0.5, -0.5, 0.0, 1.0), 1.0),
+                     (Vec4f::new(0.0, 2.0, -3.0, -1.0), 1.0)];
+        let back = [front[0], front[2], front[1]];
+
+        let mut canvas = Canvas::new(32, 32);
+        canvas.set_rasterizer_state(RasterizerState {
+            cull_mode: CullMode::Back,
+            front_face: Winding::CounterClockwise,
+        });
+        canvas.draw(&Clip, &Gray, Some(back));
+        assert_eq!(canvas.buffer().get(16, 20), Some(Rgba8::default()));
+        canvas.draw(&Clip, &Gray, Some(front));
+        assert_eq!(canvas.buffer().get(16, 20), white);
+
+        let mut canvas = Canvas::new(32, 32);
+        canvas.set_rasterizer_state(RasterizerState {
+            cull_mode: CullMode::Front,
+            front_face: Winding::Clockwise,
+        });
+        canvas.draw(&Clip, &Gray, Some(front));
+        assert_eq!(canvas.buffer().get(16, 20), white);
+    }
+
+    /// Clip space positions with a color, the varying's z is used as alpha.
+    struct Tinted;
+
+    impl FragmentShader<Vec3f> for Tinted {
+        fn fragment(&self, c: &Vec3f) -> Option<Rgba8> {
+            let channel = |v: f32| (v.max(0.0).min(1.0) * 255.0) as u8;
+            Some(Rgba8::new(channel(c.x), channel(c.y), 128, channel(c.z)))
+        }
+    }
+
+    impl VertexShader for Tinted {
+        type Input = (Vec4f, Vec3f);
+        type Varying = Vec3f;
+
+        fn vertex(&self, input: &(Vec4f, Vec3f)) -> (Vec4f, Vec3f) {
+            *input
+        }
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        // overlapping translucent triangles of all sizes, some crossing tile borders
+        // and the screen edges
+        let mut seed = 7u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        let mut triangles = Vec::new();
+        for _ in 0..300 {
+            let center = (random() * 2.4 - 1.2, random() * 2.4 - 1.2);
+            let size = random() * random() * 1.5;
+            let mut corner = || {
+                let position = Vec4f::new(center.0 + (random() - 0.5) * size,
+                                          center.1 + (random() - 0.5) * size,
+                                          random() * 2.0 - 1.0,
+                                          1.0);
+                (position, Vec3f::new(random(), random(), random()))
+            };
+            triangles.push([corner(), corner(), corner()]);
+        }
+
+        let mut serial = Canvas::new(200, 150);
+        serial.draw(&Tinted, &Tinted, triangles.iter().cloned());
+
+        for &threads in &[1, 3, 8] {
+            let mut parallel = Canvas::new(200, 150);
+            parallel.set_threads(threads);
+            parallel.draw_parallel(&Tinted, &Tinted, triangles.iter().cloned());
+            assert!(parallel.buffer() == serial.buffer());
+        }
+    }
+
+    #[test]
+    fn test_object_space_normal_map() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\n";
+        let model = parse_obj("triangle.obj", source.as_bytes()).unwrap();
+        let diffuse = Texture::new(Pixmap::new(1, 1, Rgba8::rgb(255, 255, 255)));
+        // (0.6, 0, 0.8) in object space
+        let normal_map = Texture::new(Pixmap::new(1, 1, Rgba8::rgb(204, 128, 230)));
+
+        // rotating the model together with the light and the viewer changes nothing
+        let shade = |rotation: Matrix4| {
+            let transforms = Transforms { model: rotation, ..Default::default() };
+            let (_, p) = ModelShader::new(&model, transforms).vertex(&(0, 0));
+            let surface = Surface {
+                normal_map: Some((&normal_map, NormalSpace::Object)),
+                normal_matrix: transforms.normal_matrix(),
+                ..Surface::new(&diffuse)
+            };
+            let light = Light::new(rotation.transform_vector(Vec3f::new(-1.0, 0.0, -0.2)));
+            surface.shade(&p, &light, rotation.transform_vector(Vec3f::new(0.0, 0.0, 1.0))).r
+        };
+        let still = shade(Matrix4::identity());
+        let rotated = shade(Matrix4::rotation_y(0.9) * Matrix4::rotation_x(0.4));
+        assert!((still as i32 - rotated as i32).abs() <= 1, "{} {}", still, rotated);
+    }
+}
@@ -0,0 +1,254 @@
+use color::Rgba8;
+use matrix::Matrix4;
+use model::{Material, NormalSpace};
+use sampler::Sampler;
+use texture::Texture;
+use vector3d::{Vec3f, Vertex};
+
+/// Directional light.
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+    /// Direction the light travels in.
+    pub direction: Vec3f,
+    /// Linear rgb in `[0, 1]`.
+    pub color: Vec3f,
+    pub intensity: f32,
+}
+
+impl Light {
+    /// White light of unit intensity.
+    pub fn new(direction: Vec3f) -> Light {
+        Light {
+            direction: direction.normalized(),
+            color: Vec3f::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn radiance(&self) -> Vec3f {
+        self.color * self.intensity
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ShadingModel {
+    /// Specular term from the reflected light direction.
+    Phong,
+    /// Specular term from the half vector, broader and cheaper highlights.
+    BlinnPhong,
+}
+
+/// Textures and reflectance coefficients of the triangles being drawn. All maps are
+/// read with `sampler` at the fragment's normalized texture coordinates.
+#[derive(Copy, Clone)]
+pub struct Surface<'a> {
+    pub diffuse: &'a Texture,
+    pub normal_map: Option<(&'a Texture, NormalSpace)>,
+    pub specular_map: Option<&'a Texture>,
+    pub shininess_map: Option<&'a Texture>,
+    pub sampler: Sampler,
+    /// Moves object space normals from the normal map to the space of the light,
+    /// usually `Transforms::normal_matrix`.
+    pub normal_matrix: Matrix4,
+
+    pub ambient: Vec3f,
+    pub diffuse_color: Vec3f,
+    pub specular: Vec3f,
+    pub shininess: f32,
+    pub model: ShadingModel,
+}
+
+impl<'a> Surface<'a> {
+    /// Matte surface which only shows the texture.
+    pub fn new(diffuse: &'a Texture) -> Surface<'a> {
+        Surface {
+            diffuse: diffuse,
+            normal_map: None,
+            specular_map: None,
+            shininess_map: None,
+            sampler: Sampler::new(),
+            normal_matrix: Matrix4::identity(),
+            ambient: Vec3f::zero(),
+            diffuse_color: Vec3f::new(1.0, 1.0, 1.0),
+            specular: Vec3f::zero(),
+            shininess: 0.0,
+            model: ShadingModel::BlinnPhong,
+        }
+    }
+
+    /// Takes coefficients and maps from the material, `diffuse` stands in for a
+    /// missing diffuse map.
+    pub fn with_material(diffuse: &'a Texture, material: &'a Material) -> Surface<'a> {
+        Surface {
+            diffuse: material.diffuse_map.as_ref().unwrap_or(diffuse),
+            normal_map: material.normal_map.as_ref().map(|map| (map, material.normal_space)),
+            specular_map: material.specular_map.as_ref(),
+            shininess_map: material.shininess_map.as_ref(),
+            ambient: material.ambient,
+            diffuse_color: material.diffuse,
+            specular: material.specular,
+            shininess: material.shininess,
+            ..Surface::new(diffuse)
+        }
+    }
+
+    /// Texel of `map` at the fragment, the derivatives of the texture coordinates
+    /// select the mip level.
+    fn sample(&self, map: &Texture, p: &Vertex, ddx: Vec3f, ddy: Vec3f) -> Rgba8 {
+        self.sampler.sample_grad(map, p.uv, ddx, ddy)
+    }
+
+    /// Shading normal of the fragment, tangent space normals are rotated by the
+    /// interpolated tangent frame and object space ones by `normal_matrix`.
+    pub fn normal(&self, p: &Vertex) -> Vec3f {
+        self.normal_grad(p, Vec3f::zero(), Vec3f::zero())
+    }
+
+    fn normal_grad(&self, p: &Vertex, ddx: Vec3f, ddy: Vec3f) -> Vec3f {
+        let (map, space) = match self.normal_map {
+            Some(normal_map) => normal_map,
+            None => return p.norm.normalized(),
+        };
+
+        let texel = self.sample(map, p, ddx, ddy);
+        let decode = |c: u8| c as f32 / 255.0 * 2.0 - 1.0;
+        let n = Vec3f::new(decode(texel.r), decode(texel.g), decode(texel.b));
+
+        let n = match space {
+            NormalSpace::Object => self.normal_matrix.transform_vector(n),
+            NormalSpace::Tangent => {
+                if p.tangent.norm() == 0.0 {
+                    return p.norm.normalized();
+                }
+                p.tangent * n.x + p.bitangent * n.y + p.norm * n.z
+            }
+        };
+        if n.norm() == 0.0 {
+            p.norm.normalized()
+        } else {
+            n.normalized()
+        }
+    }
+
+    /// Ambient, diffuse and specular reflection of `light` towards `eye_dir`, the
+    /// direction from the surface to the viewer. Textures are read at full resolution.
+    pub fn shade(&self, p: &Vertex, light: &Light, eye_dir: Vec3f) -> Rgba8 {
+        self.shade_grad(p, Vec3f::zero(), Vec3f::zero(), light, eye_dir)
+    }
+
+    /// Same as `shade`, textures are filtered for a pixel whose texture coordinates
+    /// change by `ddx` and `ddy` to its neighbours, see `Sampler::sample_grad`.
+    pub fn shade_grad(&self,
+                      p: &Vertex,
+                      ddx: Vec3f,
+                      ddy: Vec3f,
+                      light: &Light,
+                      eye_dir: Vec3f)
+                      -> Rgba8 {
+        let texel = self.sample(self.diffuse, p, ddx, ddy);
+        let n = self.normal_grad(p, ddx, ddy);
+        let l = light.direction * -1.0;
+
+        let n_dot_l = n * l;
+        let mut specular = 0.0;
+        if n_dot_l > 0.0 {
+            let shininess = match self.shininess_map {
+                Some(map) => self.shininess * f32::from(self.sample(map, p, ddx, ddy)),
+                None => self.shininess,
+            };
+            let cos = match self.model {
+                ShadingModel::Phong => (n * (2.0 * n_dot_l) - l) * eye_dir,
+                ShadingModel::BlinnPhong => n * (l + eye_dir).normalized(),
+            };
+            specular = cos.max(0.0).powf(shininess);
+            if let Some(map) = self.specular_map {
+                specular *= f32::from(self.sample(map, p, ddx, ddy));
+            }
+        }
+
+        let radiance = light.radiance();
+        let n_dot_l = n_dot_l.max(0.0);
+        let channel = |i: usize, c: u8| {
+            let albedo = c as f32 / 255.0;
+            let v = albedo * (self.ambient[i] + self.diffuse_color[i] * n_dot_l * radiance[i]) +
+                    self.specular[i] * specular * radiance[i];
+            (v.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+        };
+        Rgba8::new(channel(0, texel.r), channel(1, texel.g), channel(2, texel.b), texel.a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Light, ShadingModel, Surface};
+    use color::Rgba8;
+    use model::Material;
+    use pixmap::Pixmap;
+    use texture::Texture;
+    use vector3d::{Vec3f, Vertex};
+
+    fn fragment(norm: Vec3f) -> Vertex {
+        Vertex { norm: norm, ..Default::default() }
+    }
+
+    #[test]
+    fn test_diffuse() {
+        let texture = Texture::new(Pixmap::new(1, 1, Rgba8::rgb(200, 100, 50)));
+        let surface = Surface::new(&texture);
+        let light = Light::new(Vec3f::new(0.0, 0.0, -1.0));
+        let eye = Vec3f::new(0.0, 0.0, 1.0);
+
+        assert_eq!(surface.shade(&fragment(Vec3f::new(0.0, 0.0, 1.0)), &light, eye),
+                   Rgba8::rgb(200, 100, 50));
+        // facing away from the light
+        assert_eq!(surface.shade(&fragment(Vec3f::new(0.0, 0.0, -1.0)), &light, eye),
+                   Rgba8::rgb(0, 0, 0));
+
+        let dim = Light { intensity: 0.5, ..light };
+        assert_eq!(surface.shade(&fragment(Vec3f::new(0.0, 0.0, 1.0)), &dim, eye),
+                   Rgba8::rgb(100, 50, 25));
+    }
+
+    #[test]
+    fn test_default_material() {
+        let texture = Texture::new(Pixmap::new(1, 1, Rgba8::rgb(255, 0, 0)));
+        let material = Material::default();
+        let surface = Surface::with_material(&texture, &material);
+        let light = Light::new(Vec3f::new(0.0, 0.0, -1.0));
+        let eye = Vec3f::new(0.0, 0.0, 1.0);
+
+        for &model in &[ShadingModel::Phong, ShadingModel::BlinnPhong] {
+            let surface = Surface { model: model, ..surface };
+            assert_eq!(surface.shade(&fragment(Vec3f::new(0.0, 0.0, 1.0)), &light, eye),
+                       Rgba8::rgb(255, 0, 0));
+        }
+    }
+
+    #[test]
+    fn test_specular() {
+        let texture = Texture::new(Pixmap::new(1, 1, Rgba8::rgb(0, 0, 0)));
+        let spec_map = Texture::new(Pixmap::new(1, 1, Rgba8::from(128u8)));
+        let mut surface = Surface::new(&texture);
+        surface.specular = Vec3f::new(1.0, 1.0, 1.0);
+        surface.shininess = 16.0;
+
+        let light = Light::new(Vec3f::new(0.0, 0.0, -1.0));
+        let eye = Vec3f::new(0.0, 0.0, 1.0);
+        let head_on = fragment(Vec3f::new(0.0, 0.0, 1.0));
+        let tilted = fragment(Vec3f::new(0.0, 0.3, 1.0));
+
+        for &model in &[ShadingModel::Phong, ShadingModel::BlinnPhong] {
+            surface.model = model;
+            assert_eq!(surface.shade(&head_on, &light, eye), Rgba8::rgb(255, 255, 255));
+            assert!(surface.shade(&tilted, &light, eye).r < 255);
+        }
+        // the half vector lags behind the reflected ray, highlights are broader
+        surface.model = ShadingModel::Phong;
+        let phong = surface.shade(&tilted, &light, eye).r;
+        surface.model = ShadingModel::BlinnPhong;
+        assert!(surface.shade(&tilted, &light, eye).r > phong);
+
+        surface.specular_map = Some(&spec_map);
+        assert_eq!(surface.shade(&head_on, &light, eye), Rgba8::rgb(128, 128, 128));
+    }
+}
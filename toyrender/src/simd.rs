@@ -0,0 +1,236 @@
+//! Four lane `f32` vectors. SSE2 is used when the target supports it, every other
+//! target gets a plain array implementation with the same interface.
+
+use std::ops::{Add, Div, Mul, Sub};
+
+pub use self::imp::F32x4;
+
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"))]
+#[allow(unused_unsafe)]
+mod imp {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    #[derive(Copy, Clone, Debug)]
+    pub struct F32x4(__m128);
+
+    impl F32x4 {
+        #[inline]
+        pub fn new(a: f32, b: f32, c: f32, d: f32) -> F32x4 {
+            F32x4(unsafe { _mm_setr_ps(a, b, c, d) })
+        }
+
+        #[inline]
+        pub fn splat(v: f32) -> F32x4 {
+            F32x4(unsafe { _mm_set1_ps(v) })
+        }
+
+        #[inline]
+        pub fn to_array(self) -> [f32; 4] {
+            let mut out = [0.0; 4];
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), self.0) };
+            out
+        }
+
+        #[inline]
+        pub fn min(self, other: F32x4) -> F32x4 {
+            F32x4(unsafe { _mm_min_ps(self.0, other.0) })
+        }
+
+        #[inline]
+        pub fn max(self, other: F32x4) -> F32x4 {
+            F32x4(unsafe { _mm_max_ps(self.0, other.0) })
+        }
+
+        #[inline]
+        pub fn sqrt(self) -> F32x4 {
+            F32x4(unsafe { _mm_sqrt_ps(self.0) })
+        }
+
+        /// Sum of all lanes.
+        #[inline]
+        pub fn sum(self) -> f32 {
+            unsafe {
+                let shuffled = _mm_shuffle_ps(self.0, self.0, 0b10_11_00_01);
+                let sums = _mm_add_ps(self.0, shuffled);
+                let high = _mm_movehl_ps(shuffled, sums);
+                _mm_cvtss_f32(_mm_add_ss(sums, high))
+            }
+        }
+
+        #[inline]
+        pub(super) fn vadd(self, other: F32x4) -> F32x4 {
+            F32x4(unsafe { _mm_add_ps(self.0, other.0) })
+        }
+
+        #[inline]
+        pub(super) fn vsub(self, other: F32x4) -> F32x4 {
+            F32x4(unsafe { _mm_sub_ps(self.0, other.0) })
+        }
+
+        #[inline]
+        pub(super) fn vmul(self, other: F32x4) -> F32x4 {
+            F32x4(unsafe { _mm_mul_ps(self.0, other.0) })
+        }
+
+        #[inline]
+        pub(super) fn vdiv(self, other: F32x4) -> F32x4 {
+            F32x4(unsafe { _mm_div_ps(self.0, other.0) })
+        }
+    }
+}
+
+#[cfg(not(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2")))]
+mod imp {
+    #[derive(Copy, Clone, Debug)]
+    pub struct F32x4([f32; 4]);
+
+    impl F32x4 {
+        #[inline]
+        fn zip<F: Fn(f32, f32) -> f32>(self, other: F32x4, f: F) -> F32x4 {
+            let (a, b) = (self.0, other.0);
+            F32x4([f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])])
+        }
+
+        #[inline]
+        pub fn new(a: f32, b: f32, c: f32, d: f32) -> F32x4 {
+            F32x4([a, b, c, d])
+        }
+
+        #[inline]
+        pub fn splat(v: f32) -> F32x4 {
+            F32x4([v; 4])
+        }
+
+        #[inline]
+        pub fn to_array(self) -> [f32; 4] {
+            self.0
+        }
+
+        #[inline]
+        pub fn min(self, other: F32x4) -> F32x4 {
+            self.zip(other, f32::min)
+        }
+
+        #[inline]
+        pub fn max(self, other: F32x4) -> F32x4 {
+            self.zip(other, f32::max)
+        }
+
+        #[inline]
+        pub fn sqrt(self) -> F32x4 {
+            let a = self.0;
+            F32x4([a[0].sqrt(), a[1].sqrt(), a[2].sqrt(), a[3].sqrt()])
+        }
+
+        /// Sum of all lanes.
+        #[inline]
+        pub fn sum(self) -> f32 {
+            (self.0[0] + self.0[1]) + (self.0[2] + self.0[3])
+        }
+
+        #[inline]
+        pub(super) fn vadd(self, other: F32x4) -> F32x4 {
+            self.zip(other, |a, b| a + b)
+        }
+
+        #[inline]
+        pub(super) fn vsub(self, other: F32x4) -> F32x4 {
+            self.zip(other, |a, b| a - b)
+        }
+
+        #[inline]
+        pub(super) fn vmul(self, other: F32x4) -> F32x4 {
+            self.zip(other, |a, b| a * b)
+        }
+
+        #[inline]
+        pub(super) fn vdiv(self, other: F32x4) -> F32x4 {
+            self.zip(other, |a, b| a / b)
+        }
+    }
+}
+
+impl F32x4 {
+    /// `[0, 1, 2, 3]`, the lane offsets of a quad of pixels.
+    #[inline]
+    pub fn lanes() -> F32x4 {
+        F32x4::new(0.0, 1.0, 2.0, 3.0)
+    }
+
+    /// Dot product of all four lanes.
+    #[inline]
+    pub fn dot(self, other: F32x4) -> f32 {
+        (self * other).sum()
+    }
+}
+
+impl Add for F32x4 {
+    type Output = F32x4;
+
+    #[inline]
+    fn add(self, other: F32x4) -> F32x4 {
+        self.vadd(other)
+    }
+}
+
+impl Sub for F32x4 {
+    type Output = F32x4;
+
+    #[inline]
+    fn sub(self, other: F32x4) -> F32x4 {
+        self.vsub(other)
+    }
+}
+
+impl Mul for F32x4 {
+    type Output = F32x4;
+
+    #[inline]
+    fn mul(self, other: F32x4) -> F32x4 {
+        self.vmul(other)
+    }
+}
+
+impl Mul<f32> for F32x4 {
+    type Output = F32x4;
+
+    #[inline]
+    fn mul(self, other: f32) -> F32x4 {
+        self.vmul(F32x4::splat(other))
+    }
+}
+
+impl Div for F32x4 {
+    type Output = F32x4;
+
+    #[inline]
+    fn div(self, other: F32x4) -> F32x4 {
+        self.vdiv(other)
+    }
+}
+
+impl PartialEq for F32x4 {
+    fn eq(&self, other: &F32x4) -> bool {
+        self.to_array() == other.to_array()
+    }
+}
+
+#[test]
+fn test_arithmetic() {
+    let a = F32x4::new(1.0, 2.0, 3.0, 4.0);
+    let b = F32x4::splat(2.0);
+
+    assert_eq!((a + b).to_array(), [3.0, 4.0, 5.0, 6.0]);
+    assert_eq!((a - b).to_array(), [-1.0, 0.0, 1.0, 2.0]);
+    assert_eq!((a * b).to_array(), [2.0, 4.0, 6.0, 8.0]);
+    assert_eq!((a / b).to_array(), [0.5, 1.0, 1.5, 2.0]);
+    assert_eq!((a * 3.0).to_array(), [3.0, 6.0, 9.0, 12.0]);
+    assert_eq!(a.min(b), F32x4::new(1.0, 2.0, 2.0, 2.0));
+    assert_eq!(a.max(b), F32x4::new(2.0, 2.0, 3.0, 4.0));
+    assert_eq!((a * a).sqrt(), a);
+    assert_eq!(a.sum(), 10.0);
+    assert_eq!(a.dot(F32x4::lanes()), 20.0);
+}
@@ -0,0 +1,222 @@
+use std::f32::consts::PI;
+
+use color::Rgba8;
+use pixmap::Pixmap;
+
+/// Filter used to shrink one mip level into the next.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Downsample {
+    /// Average of the texels a smaller texel covers, cheap and slightly blurry.
+    Box,
+    /// Lanczos with three lobes, keeps more detail but may ring at hard edges.
+    Lanczos,
+}
+
+/// Image together with its mip chain, each level half the size of the previous one
+/// down to a single texel. Level 0 is the original image.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    levels: Vec<Pixmap>,
+}
+
+impl Texture {
+    /// Texture without mipmaps.
+    pub fn new(image: Pixmap) -> Texture {
+        Texture { levels: vec![image] }
+    }
+
+    pub fn with_mipmaps(image: Pixmap, filter: Downsample) -> Texture {
+        let mut texture = Texture::new(image);
+        texture.generate_mipmaps(filter);
+        texture
+    }
+
+    /// Replaces the mip chain by one computed from level 0.
+    pub fn generate_mipmaps(&mut self, filter: Downsample) {
+        self.levels.truncate(1);
+        loop {
+            let next = {
+                let last = &self.levels[self.levels.len() - 1];
+                if last.width() <= 1 && last.height() <= 1 || last.data().is_empty() {
+                    break;
+                }
+                downsample(last, filter)
+            };
+            self.levels.push(next);
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width()
+    }
+    pub fn height(&self) -> usize {
+        self.levels[0].height()
+    }
+
+    /// Number of levels including the original image.
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Level `i` of the chain, the smallest one if there are fewer levels.
+    pub fn level(&self, i: usize) -> &Pixmap {
+        &self.levels[i.min(self.levels.len() - 1)]
+    }
+
+    pub fn base(&self) -> &Pixmap {
+        &self.levels[0]
+    }
+}
+
+impl Default for Texture {
+    fn default() -> Texture {
+        Texture::new(Pixmap::default())
+    }
+}
+
+impl From<Pixmap> for Texture {
+    fn from(image: Pixmap) -> Texture {
+        Texture::new(image)
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Contributions of the `n` source texels to every one of the `m` destination texels
+/// along one axis, normalized to sum up to one.
+fn weights(n: usize, m: usize, filter: Downsample) -> Vec<Vec<(usize, f32)>> {
+    let scale = n as f32 / m as f32;
+    (0..m)
+        .map(|i| {
+            let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+            let mut taps: Vec<(usize, f32)> = match filter {
+                Downsample::Box => {
+                    (start.floor() as usize..(end.ceil() as usize).min(n))
+                        .map(|j| (j, end.min(j as f32 + 1.0) - start.max(j as f32)))
+                        .collect()
+                }
+                Downsample::Lanczos => {
+                    // centers of destination texels in source texels, the kernel is
+                    // stretched by `scale` to cut off frequencies the destination can't hold
+                    let center = (start + end) / 2.0;
+                    let radius = 3.0 * scale;
+                    let first = (center - radius).floor().max(0.0) as usize;
+                    let last = ((center + radius).ceil() as usize).min(n);
+                    (first..last)
+                        .map(|j| {
+                            let x = (j as f32 + 0.5 - center) / scale;
+                            let w = if x.abs() < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 };
+                            (j, w)
+                        })
+                        .collect()
+                }
+            };
+            let sum: f32 = taps.iter().map(|t| t.1).sum();
+            for tap in &mut taps {
+                tap.1 /= sum;
+            }
+            taps
+        })
+        .collect()
+}
+
+/// Next smaller mip level, each dimension is halved but kept at least one.
+fn downsample(image: &Pixmap, filter: Downsample) -> Pixmap {
+    let (w, h) = (image.width(), image.height());
+    let (mw, mh) = ((w / 2).max(1), (h / 2).max(1));
+    let channels = |c: Rgba8| [c.r as f32, c.g as f32, c.b as f32, c.a as f32];
+
+    // separable, rows first
+    let columns = weights(w, mw, filter);
+    let mut narrow = vec![[0.0f32; 4]; mw * h];
+    for (y, row) in image.rows().enumerate() {
+        for (x, taps) in columns.iter().enumerate() {
+            let sum = &mut narrow[y * mw + x];
+            for &(j, weight) in taps {
+                let c = channels(row[j]);
+                for k in 0..4 {
+                    sum[k] += c[k] * weight;
+                }
+            }
+        }
+    }
+
+    let rows = weights(h, mh, filter);
+    let mut out = Pixmap::new(mw, mh, Rgba8::default());
+    for (y, taps) in rows.iter().enumerate() {
+        for x in 0..mw {
+            let mut sum = [0.0f32; 4];
+            for &(j, weight) in taps {
+                let c = narrow[j * mw + x];
+                for k in 0..4 {
+                    sum[k] += c[k] * weight;
+                }
+            }
+            let channel = |v: f32| (v + 0.5).max(0.0).min(255.0) as u8;
+            out[(x, y)] =
+                Rgba8::new(channel(sum[0]), channel(sum[1]), channel(sum[2]), channel(sum[3]));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Downsample, Texture};
+    use color::Rgba8;
+    use pixmap::Pixmap;
+
+    #[test]
+    fn test_mip_chain() {
+        let image = Pixmap::new(8, 3, Rgba8::rgb(10, 20, 30));
+        let texture = Texture::with_mipmaps(image, Downsample::Box);
+
+        let sizes: Vec<_> = (0..texture.levels()).map(|i| {
+            (texture.level(i).width(), texture.level(i).height())
+        }).collect();
+        assert_eq!(sizes, vec![(8, 3), (4, 1), (2, 1), (1, 1)]);
+        // a flat image stays flat with either filter
+        assert!(texture.level(2).data().iter().all(|&c| c == Rgba8::rgb(10, 20, 30)));
+
+        let image = Pixmap::new(5, 5, Rgba8::rgb(10, 20, 30));
+        let texture = Texture::with_mipmaps(image, Downsample::Lanczos);
+        assert_eq!(texture.levels(), 3);
+        assert!(texture.level(1).data().iter().all(|&c| c == Rgba8::rgb(10, 20, 30)));
+        assert_eq!(texture.level(7).width(), 1);
+    }
+
+    #[test]
+    fn test_downsample_averages() {
+        // a checkerboard of black and white texels turns gray, Lanczos is slightly off
+        // at the borders where its kernel is cut
+        let (b, w) = (Rgba8::rgb(0, 0, 0), Rgba8::rgb(255, 255, 255));
+        let mut image = Pixmap::new(16, 16, b);
+        for y in 0..16 {
+            for x in 0..16 {
+                if (x + y) % 2 == 0 {
+                    image[(x, y)] = w;
+                }
+            }
+        }
+
+        for &filter in &[Downsample::Box, Downsample::Lanczos] {
+            let texture = Texture::with_mipmaps(image.clone(), filter);
+            for c in texture.level(1).data() {
+                assert!((c.r as i32 - 128).abs() <= 4, "{:?} {:?}", filter, c);
+            }
+            let last = texture.level(texture.levels() - 1);
+            assert_eq!((last.width(), last.height()), (1, 1));
+        }
+
+        // odd sizes keep the average
+        let image = Pixmap::from_vec(3, 1, vec![b, w, w]).unwrap();
+        let texture = Texture::with_mipmaps(image, Downsample::Box);
+        assert_eq!(texture.level(1).data(), &[Rgba8::rgb(170, 170, 170)]);
+    }
+}
@@ -1,128 +1,761 @@
-use std::mem;
+use std::error;
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::io::{Read, BufReader, Error, ErrorKind};
+use std::io::{BufRead, Read, Write, BufReader, BufWriter, Error, ErrorKind};
 use std::path::Path;
+use std::result;
 
+use color::Rgba8;
+use image::ImageDecoder;
 use pixmap::Pixmap;
 
 pub type Result<T> = io::Result<T>;
+pub type TgaResult<T> = result::Result<T, TgaError>;
 
 pub trait ImageLoader
 {
-    fn load(path: &str) -> Result<Pixmap>;
+    /// Decodes an image from any source, a file, an archive entry or a buffer.
+    fn read_from<R: BufRead>(inp: &mut R) -> Result<Pixmap>;
+
+    /// Decodes an image embedded with `include_bytes!` or held in memory.
+    fn load_bytes(bytes: &[u8]) -> Result<Pixmap> {
+        Self::read_from(&mut &bytes[..])
+    }
+
+    fn load(path: &str) -> Result<Pixmap> {
+        let file = try!(File::open(&Path::new(path)));
+        Self::read_from(&mut BufReader::new(file))
+    }
+}
+
+pub trait ImageWriter
+{
+    fn save(&self, pixmap: &Pixmap, path: &str) -> Result<()>;
+}
+
+/// Describes why a TGA file could not be decoded.
+#[derive(Debug)]
+pub enum TgaError {
+    Io(io::Error),
+    /// The data ended before the whole image was read.
+    Truncated,
+    /// Image type is not one of 1, 2, 3 or their RLE variants 9, 10, 11.
+    UnsupportedImageType(u8),
+    /// Pixel depth is not valid for the image type.
+    UnsupportedPixelDepth {
+        datatypecode: u8,
+        bitsperpixel: u8,
+    },
+    /// Color map type is neither 0 nor 1.
+    InvalidColorMapType(u8),
+    UnsupportedColorMapDepth(u8),
+    /// Color mapped image without a color map.
+    MissingColorMap,
+    /// A pixel refers to a color map entry which does not exist.
+    ColorIndexOutOfRange(usize),
+    /// Width or height is zero.
+    EmptyImage,
+    /// A run length packet continues past the last pixel of the image.
+    RleOverrun,
+}
+
+impl fmt::Display for TgaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TgaError::Io(ref err) => write!(f, "tga: {}", err),
+            TgaError::Truncated => write!(f, "tga: unexpected end of image data"),
+            TgaError::UnsupportedImageType(code) => {
+                write!(f, "tga: unsupported image type {}", code)
+            }
+            TgaError::UnsupportedPixelDepth { datatypecode, bitsperpixel } => {
+                write!(f,
+                       "tga: {} bits per pixel is not supported for image type {}",
+                       bitsperpixel,
+                       datatypecode)
+            }
+            TgaError::InvalidColorMapType(kind) => write!(f, "tga: invalid color map type {}", kind),
+            TgaError::UnsupportedColorMapDepth(depth) => {
+                write!(f, "tga: unsupported color map depth {}", depth)
+            }
+            TgaError::MissingColorMap => write!(f, "tga: color mapped image without color map"),
+            TgaError::ColorIndexOutOfRange(index) => {
+                write!(f, "tga: color index {} is out of the color map", index)
+            }
+            TgaError::EmptyImage => write!(f, "tga: image has zero width or height"),
+            TgaError::RleOverrun => write!(f, "tga: run length packet exceeds the image size"),
+        }
+    }
+}
+
+impl error::Error for TgaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TgaError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TgaError {
+    fn from(err: io::Error) -> TgaError {
+        match err.kind() {
+            ErrorKind::UnexpectedEof => TgaError::Truncated,
+            _ => TgaError::Io(err),
+        }
+    }
+}
+
+impl From<TgaError> for io::Error {
+    fn from(err: TgaError) -> io::Error {
+        match err {
+            TgaError::Io(err) => err,
+            TgaError::Truncated => Error::new(ErrorKind::UnexpectedEof, err),
+            err => Error::new(ErrorKind::InvalidData, err),
+        }
+    }
 }
 
-const HEADERSIZE: usize = 18; // 18 = sizeof(TgaHeader)
-#[repr(C, packed)]
+const HEADERSIZE: usize = 18;
+
+// Bytes 8..12 hold the x and y origin of the image which is not needed for decoding.
 struct TgaHeader {
-    idlength: i8,
-    colormaptype: i8,
-    datatypecode: i8,
-    colormaporigin: i16,
-    colormaplength: i16,
-    colormapdepth: i8,
-    x_origin: i16,
-    y_origin: i16,
-    width: i16,
-    height: i16,
-    bitsperpixel: i8,
-    imagedescriptor: i8,
+    idlength: u8,
+    colormaptype: u8,
+    datatypecode: u8,
+    colormaporigin: u16,
+    colormaplength: u16,
+    colormapdepth: u8,
+    width: u16,
+    height: u16,
+    bitsperpixel: u8,
+    imagedescriptor: u8,
+}
+
+impl TgaHeader {
+    fn parse(bytes: &[u8; HEADERSIZE]) -> TgaHeader {
+        let word = |i: usize| bytes[i] as u16 | (bytes[i + 1] as u16) << 8;
+
+        TgaHeader {
+            idlength: bytes[0],
+            colormaptype: bytes[1],
+            datatypecode: bytes[2],
+            colormaporigin: word(3),
+            colormaplength: word(5),
+            colormapdepth: bytes[7],
+            width: word(12),
+            height: word(14),
+            bitsperpixel: bytes[16],
+            imagedescriptor: bytes[17],
+        }
+    }
+
+    fn is_color_mapped(&self) -> bool {
+        self.datatypecode == 1 || self.datatypecode == 9
+    }
+
+    fn is_rle(&self) -> bool {
+        self.datatypecode >= 9
+    }
+
+    fn validate(&self) -> TgaResult<()> {
+        let depth_ok = match self.datatypecode {
+            1 | 9 => self.bitsperpixel == 8 || self.bitsperpixel == 16,
+            2 | 10 => {
+                match self.bitsperpixel {
+                    15 | 16 | 24 | 32 => true,
+                    _ => false,
+                }
+            }
+            3 | 11 => self.bitsperpixel == 8,
+            code => return Err(TgaError::UnsupportedImageType(code)),
+        };
+        if !depth_ok {
+            return Err(TgaError::UnsupportedPixelDepth {
+                datatypecode: self.datatypecode,
+                bitsperpixel: self.bitsperpixel,
+            });
+        }
+
+        match self.colormaptype {
+            0 if self.is_color_mapped() => return Err(TgaError::MissingColorMap),
+            0 => {}
+            1 => {
+                match self.colormapdepth {
+                    15 | 16 | 24 | 32 => {}
+                    depth => return Err(TgaError::UnsupportedColorMapDepth(depth)),
+                }
+            }
+            kind => return Err(TgaError::InvalidColorMapType(kind)),
+        }
+
+        if self.width == 0 || self.height == 0 {
+            return Err(TgaError::EmptyImage);
+        }
+        Ok(())
+    }
+
+    /// Low bits of the image descriptor hold the number of alpha bits per pixel.
+    fn has_alpha(&self) -> bool {
+        self.imagedescriptor & 0x0f != 0 || self.bitsperpixel == 32
+    }
+
+    /// Bit 5 of the image descriptor, scanlines are stored from top to bottom.
+    fn top_to_bottom(&self) -> bool {
+        self.imagedescriptor & 0x20 != 0
+    }
+
+    /// Bit 4 of the image descriptor, pixels are stored from right to left.
+    fn right_to_left(&self) -> bool {
+        self.imagedescriptor & 0x10 != 0
+    }
 }
 
+/// TGA decoder, the resulting pixmap is always oriented with row zero at the top.
 pub struct TgaImage;
 
 impl TgaImage {
-    fn read_header(file: &mut BufReader<File>) -> Result<TgaHeader> {
+    /// Same as `ImageLoader::load`, but reports exactly what was wrong with the file.
+    pub fn open(path: &str) -> TgaResult<Pixmap> {
+        let path = Path::new(path);
+        let file = try!(File::open(&path));
+        Self::read(&mut BufReader::new(file))
+    }
+
+    pub fn read<R: BufRead>(file: &mut R) -> TgaResult<Pixmap> {
+        let header = try!(Self::read_header(file));
+        try!(header.validate());
+
+        let mut id = Vec::with_capacity(header.idlength as usize);
+        try!(Self::read_raw(header.idlength as usize, file, &mut id));
+
+        let colormap = if header.colormaptype == 1 {
+            try!(Self::read_colormap(&header, file))
+        } else {
+            Vec::new()
+        };
+
+        let w = header.width as usize;
+        let h = header.height as usize;
+        let bytespp = ((header.bitsperpixel as usize) + 7) >> 3;
+
+        // The buffer grows while reading, so a bogus header can not force a huge allocation.
+        let mut buffer: Vec<u8> = Vec::new();
+        if header.is_rle() {
+            try!(Self::read_rle(w * h, bytespp, file, &mut buffer));
+        } else {
+            try!(Self::read_raw(w * h * bytespp, file, &mut buffer));
+        }
+
+        let mut pixmap = Pixmap::new(w, h, Rgba8::default());
+        for (i, bytes) in buffer.chunks(bytespp).enumerate() {
+            let color = if header.is_color_mapped() {
+                let index = Self::read_index(bytes);
+                let entry = index.wrapping_sub(header.colormaporigin as usize);
+                match colormap.get(entry) {
+                    Some(&color) => color,
+                    None => return Err(TgaError::ColorIndexOutOfRange(index)),
+                }
+            } else {
+                Self::read_color(bytes, header.has_alpha())
+            };
+
+            let (mut x, mut y) = (i % w, i / w);
+            if header.right_to_left() {
+                x = w - 1 - x;
+            }
+            if !header.top_to_bottom() {
+                y = h - 1 - y;
+            }
+            pixmap.set(x as i32, y as i32, color);
+        }
+
+        Ok(pixmap)
+    }
+
+    fn read_header<R: Read>(file: &mut R) -> TgaResult<TgaHeader> {
         let mut header_bytes: [u8; HEADERSIZE] = [0; HEADERSIZE];
-        try!(file.read(&mut header_bytes));
+        try!(file.read_exact(&mut header_bytes));
+
+        Ok(TgaHeader::parse(&header_bytes))
+    }
+
+    fn read_colormap<R: Read>(header: &TgaHeader, inp: &mut R) -> TgaResult<Vec<Rgba8>> {
+        let bytespp = ((header.colormapdepth as usize) + 7) >> 3;
+        let count = header.colormaplength as usize;
 
-        let header = unsafe { mem::transmute::<[u8; HEADERSIZE], TgaHeader>(header_bytes) };
-        Ok(header)
+        let mut buffer = Vec::with_capacity(count * bytespp);
+        try!(Self::read_raw(count * bytespp, inp, &mut buffer));
+
+        let alpha = header.colormapdepth == 16 || header.colormapdepth == 32;
+        Ok(buffer.chunks(bytespp).map(|bytes| Self::read_color(bytes, alpha)).collect())
     }
 
-    fn read_raw(inp: &mut BufReader<File>, out: &mut Vec<u8>) -> Result<()> {
-        try!(inp.read_to_end(out));
+    fn read_raw<R: Read>(count: usize, inp: &mut R, out: &mut Vec<u8>) -> TgaResult<()> {
+        let start = out.len();
+        try!(inp.by_ref().take(count as u64).read_to_end(out));
 
+        if out.len() - start < count {
+            return Err(TgaError::Truncated);
+        }
         Ok(())
     }
 
-    fn read_rle(pixelcount: usize,
-                bytespp: usize,
-                inp: &mut BufReader<File>,
-                out: &mut Vec<u8>)
-                -> Result<()> {
-        let mut buffer = Vec::new();
-        try!(inp.read_to_end(&mut buffer));
+    fn read_rle<R: Read>(pixelcount: usize,
+                         bytespp: usize,
+                         inp: &mut R,
+                         out: &mut Vec<u8>)
+                         -> TgaResult<()> {
+        let mut pixel = [0u8; 4];
 
-        let mut pos = 0;
         let mut pix = 0;
         while pix < pixelcount {
-            let mut chunkheader = buffer[pos] as usize;
-            pos += 1;
+            let mut chunkheader = [0u8; 1];
+            try!(inp.read_exact(&mut chunkheader));
+
+            let chunkheader = chunkheader[0] as usize;
+            let count = (chunkheader & 0x7f) + 1;
+            if pix + count > pixelcount {
+                return Err(TgaError::RleOverrun);
+            }
+
             if chunkheader < 128 {
-                chunkheader += 1;
-                let endpos = pos + chunkheader * bytespp;
-                while pos < endpos {
-                    out.push(buffer[pos]);
-                    pos += 1;
-                }
+                try!(Self::read_raw(count * bytespp, inp, out));
             } else {
-                chunkheader -= 127;
-                for _i in 0..chunkheader {
-                    for j in 0..bytespp {
-                        out.push(buffer[pos + j]);
-                    }
+                try!(inp.read_exact(&mut pixel[..bytespp]));
+                for _ in 0..count {
+                    out.extend_from_slice(&pixel[..bytespp]);
                 }
-                pos += bytespp;
             }
-            pix += chunkheader;
+            pix += count;
         }
 
         Ok(())
     }
+
+    fn read_index(bytes: &[u8]) -> usize {
+        match bytes.len() {
+            1 => bytes[0] as usize,
+            _ => bytes[0] as usize | (bytes[1] as usize) << 8,
+        }
+    }
+
+    /// Pixels without alpha are opaque.
+    fn read_color(bytes: &[u8], alpha: bool) -> Rgba8 {
+        match bytes.len() {
+            1 => Rgba8::rgb(bytes[0], bytes[0], bytes[0]),
+            2 => {
+                // 15 and 16 bit pixels are stored as ARRRRRGG GGGBBBBB
+                let v = bytes[0] as u16 | (bytes[1] as u16) << 8;
+                let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+                let a = if alpha && v & 0x8000 == 0 {
+                    0
+                } else {
+                    255
+                };
+                Rgba8::new(expand((v >> 10) & 0x1f),
+                           expand((v >> 5) & 0x1f),
+                           expand(v & 0x1f),
+                           a)
+            }
+            3 => Rgba8::rgb(bytes[2], bytes[1], bytes[0]),
+            _ => {
+                let a = if alpha {
+                    bytes[3]
+                } else {
+                    255
+                };
+                Rgba8::new(bytes[2], bytes[1], bytes[0], a)
+            }
+        }
+    }
 }
 
 impl ImageLoader for TgaImage {
-    fn load(path: &str) -> Result<Pixmap> {
-        let path = Path::new(path);
-        let file = try!(File::open(&path));
-        let mut file = BufReader::new(file);
+    fn read_from<R: BufRead>(inp: &mut R) -> Result<Pixmap> {
+        Self::read(inp).map_err(io::Error::from)
+    }
+}
 
-        let header = try!(Self::read_header(&mut file));
-        let w = header.width as usize;
-        let h = header.height as usize;
-        let bpp = (header.bitsperpixel >> 3) as usize;
+impl ImageDecoder for TgaImage {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tga"]
+    }
 
-        let mut buffer: Vec<u8> = Vec::with_capacity(w * h * bpp);
-        try!(match header.datatypecode {
-            2...3 => Self::read_raw(&mut file, &mut buffer),
-            10...11 => Self::read_rle(w * h, bpp, &mut file, &mut buffer),
-            _ => Err(Error::new(ErrorKind::Other, "oh no!")),
-        });
+    /// TGA files have no magic number.
+    fn detect(&self, _header: &[u8]) -> bool {
+        false
+    }
 
-        let mut pixmap = Pixmap::new(w, h, 0);
+    fn decode(&self, mut inp: &mut dyn BufRead) -> Result<Pixmap> {
+        Self::read(&mut inp).map_err(io::Error::from)
+    }
+}
+
+/// Corner of the image where the first stored scanline is placed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Origin {
+    BottomLeft,
+    TopLeft,
+}
+
+/// TGA encoder, pixmap row zero is always written as the top of the image.
+#[derive(Copy, Clone, Debug)]
+pub struct TgaWriter {
+    /// 8 (grayscale), 24 or 32 bits per pixel
+    pub bitsperpixel: u8,
+    pub rle: bool,
+    pub origin: Origin,
+}
+
+impl Default for TgaWriter {
+    fn default() -> TgaWriter {
+        TgaWriter {
+            bitsperpixel: 24,
+            rle: true,
+            origin: Origin::BottomLeft,
+        }
+    }
+}
 
-        for y in 0..h {
+impl TgaWriter {
+    pub fn new(bitsperpixel: u8, rle: bool, origin: Origin) -> TgaWriter {
+        TgaWriter {
+            bitsperpixel: bitsperpixel,
+            rle: rle,
+            origin: origin,
+        }
+    }
+
+    pub fn write<W: Write>(&self, pixmap: &Pixmap, out: &mut W) -> Result<()> {
+        let bytespp = (self.bitsperpixel >> 3) as usize;
+        let datatypecode = match (bytespp, self.rle) {
+            (1, false) => 3,
+            (1, true) => 11,
+            (3, false) | (4, false) => 2,
+            (3, true) | (4, true) => 10,
+            _ => {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("unsupported bits per pixel: {}", self.bitsperpixel)))
+            }
+        };
+
+        let w = pixmap.width();
+        let h = pixmap.height();
+        if w > u16::max_value() as usize || h > u16::max_value() as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, "image is too large for tga"));
+        }
+
+        let mut imagedescriptor = if bytespp == 4 {
+            8 // alpha channel depth
+        } else {
+            0
+        };
+        if self.origin == Origin::TopLeft {
+            imagedescriptor |= 0x20;
+        }
+
+        let mut header = [0u8; HEADERSIZE];
+        header[2] = datatypecode;
+        header[12] = w as u8;
+        header[13] = (w >> 8) as u8;
+        header[14] = h as u8;
+        header[15] = (h >> 8) as u8;
+        header[16] = self.bitsperpixel;
+        header[17] = imagedescriptor;
+        try!(out.write_all(&header));
+
+        let mut line = Vec::with_capacity(w * bytespp);
+        for i in 0..h {
+            let y = match self.origin {
+                Origin::TopLeft => i,
+                Origin::BottomLeft => h - 1 - i,
+            };
+
+            line.clear();
             for x in 0..w {
-                match bpp {
-                    1 => {
-                        let intensity = buffer[y * w + x] as u32;
-                        pixmap[x][y] = (intensity + (intensity << (8 * 1)) +
-                                        (intensity <<
-                                         (8 * 2))) as i32;
-                    }
-                    v @ 3...4 => {
-                        let bytes = &buffer[(y * w + x) * v..(y * w + x + 1) * v];
-                        pixmap[x][y] = (bytes[0] as u32 + ((bytes[1] as u32) << (8 * 1)) +
-                                        ((bytes[2] as u32) <<
-                                         (8 * 2))) as i32;
-                    }
-                    _ => {}
+                Self::push_pixel(pixmap.get(x as i32, y as i32).unwrap(), bytespp, &mut line);
+            }
+
+            if self.rle {
+                try!(Self::write_rle(&line, bytespp, out));
+            } else {
+                try!(out.write_all(&line));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push_pixel(c: Rgba8, bytespp: usize, out: &mut Vec<u8>) {
+        match bytespp {
+            1 => out.push(c.luma()),
+            3 => out.extend_from_slice(&[c.b, c.g, c.r]),
+            _ => out.extend_from_slice(&[c.b, c.g, c.r, c.a]),
+        }
+    }
+
+    /// Encodes a single scanline, packets never cross the line boundary.
+    fn write_rle<W: Write>(line: &[u8], bytespp: usize, out: &mut W) -> Result<()> {
+        const MAX_CHUNK: usize = 128;
+
+        let pixels: Vec<&[u8]> = line.chunks(bytespp).collect();
+        let count = pixels.len();
+
+        let mut pos = 0;
+        while pos < count {
+            let mut run = 1;
+            while pos + run < count && run < MAX_CHUNK && pixels[pos + run] == pixels[pos] {
+                run += 1;
+            }
+
+            if run > 1 {
+                try!(out.write_all(&[(128 + run - 1) as u8]));
+                try!(out.write_all(pixels[pos]));
+                pos += run;
+            } else {
+                // raw packet lasts until the next pair of equal pixels
+                let start = pos;
+                pos += 1;
+                while pos < count && pos - start < MAX_CHUNK &&
+                      !(pos + 1 < count && pixels[pos] == pixels[pos + 1]) {
+                    pos += 1;
                 }
+
+                try!(out.write_all(&[(pos - start - 1) as u8]));
+                try!(out.write_all(&line[start * bytespp..pos * bytespp]));
             }
         }
 
-        Ok(pixmap)
+        Ok(())
+    }
+}
+
+impl ImageWriter for TgaWriter {
+    fn save(&self, pixmap: &Pixmap, path: &str) -> Result<()> {
+        let file = try!(File::create(&Path::new(path)));
+        let mut file = BufWriter::new(file);
+
+        try!(self.write(pixmap, &mut file));
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+
+    use super::{ImageLoader, ImageWriter, Origin, TgaError, TgaImage, TgaWriter};
+    use color::Rgba8;
+    use pixmap::Pixmap;
+
+    fn write_file(name: &str, bytes: &[u8]) -> String {
+        let path = env::temp_dir().join(name);
+        File::create(&path).unwrap().write_all(bytes).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn header(datatypecode: u8, bitsperpixel: u8, w: u8, h: u8) -> Vec<u8> {
+        vec![0, 0, datatypecode, 0, 0, 0, 0, 0, 0, 0, 0, 0, w, 0, h, 0, bitsperpixel, 0x20]
+    }
+
+    fn pixel(pixmap: &Pixmap, x: i32, y: i32) -> Rgba8 {
+        pixmap.get(x, y).unwrap()
+    }
+
+    fn gradient(w: usize, h: usize, bitsperpixel: u8) -> Pixmap {
+        let mut pixmap = Pixmap::new(w, h, Rgba8::default());
+        for x in 0..w {
+            for y in 0..h {
+                // a few flat areas to exercise run length packets
+                let (r, g, b) = if y % 3 == 0 {
+                    (0x12, 0x34, 0x56)
+                } else {
+                    (x * 7, y * 13, x + y)
+                };
+                let color = match bitsperpixel {
+                    8 => Rgba8::rgb(b as u8, b as u8, b as u8),
+                    24 => Rgba8::rgb(r as u8, g as u8, b as u8),
+                    _ => Rgba8::new(r as u8, g as u8, b as u8, (x * 8) as u8),
+                };
+                pixmap.set(x as i32, y as i32, color);
+            }
+        }
+        pixmap
+    }
+
+    fn round_trip(writer: TgaWriter, name: &str) {
+        let pixmap = gradient(40, 24, writer.bitsperpixel);
+
+        let path = env::temp_dir().join(name);
+        let path = path.to_str().unwrap();
+        writer.save(&pixmap, path).unwrap();
+        let loaded = TgaImage::load(path).unwrap();
+        assert!(TgaImage::load_bytes(&fs::read(path).unwrap()).unwrap() == loaded);
+
+        assert_eq!(loaded.width(), 40);
+        assert_eq!(loaded.height(), 24);
+        assert!(loaded == pixmap);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &bpp in &[8, 24, 32] {
+            for &rle in &[false, true] {
+                for &origin in &[Origin::TopLeft, Origin::BottomLeft] {
+                    let name = format!("toyrender_round_trip_{}_{}_{:?}.tga", bpp, rle, origin);
+                    round_trip(TgaWriter::new(bpp, rle, origin), &name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let pixmap = Pixmap::new(3, 3, Rgba8::default());
+        let mut out = Vec::new();
+
+        TgaWriter::new(32, true, Origin::TopLeft).write(&pixmap, &mut out).unwrap();
+        assert_eq!(out[2], 10);
+        assert_eq!(out[16], 32);
+        assert_eq!(out[17], 0x28);
+
+        out.clear();
+        TgaWriter::new(8, false, Origin::BottomLeft).write(&pixmap, &mut out).unwrap();
+        assert_eq!(out[2], 3);
+        assert_eq!(out[17], 0);
+        assert_eq!(out.len(), 18 + 9);
+    }
+
+    #[test]
+    fn test_bottom_left_order() {
+        let mut pixmap = Pixmap::new(2, 2, Rgba8::default());
+        pixmap.set(0, 1, Rgba8::rgb(255, 255, 255));
+
+        let mut out = Vec::new();
+        TgaWriter::new(8, false, Origin::BottomLeft).write(&pixmap, &mut out).unwrap();
+
+        // the last pixmap row is stored first
+        assert!(out[18] != 0);
+        assert_eq!(&out[19..], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_rle_packets() {
+        let mut out = Vec::new();
+        TgaWriter::write_rle(&[1, 1, 1, 2, 3, 4, 4], 1, &mut out).unwrap();
+
+        assert_eq!(out, vec![130, 1, 1, 2, 3, 129, 4]);
+    }
+
+    #[test]
+    fn test_skip_image_id() {
+        let mut bytes = header(3, 8, 3, 1);
+        bytes[0] = 3;
+        bytes.extend_from_slice(b"id!");
+        bytes.extend_from_slice(&[0x10, 0x20, 0x30]);
+
+        let pixmap = TgaImage::open(&write_file("toyrender_id.tga", &bytes)).unwrap();
+        assert_eq!(pixel(&pixmap, 0, 0), Rgba8::rgb(0x10, 0x10, 0x10));
+        assert_eq!(pixel(&pixmap, 2, 0), Rgba8::rgb(0x30, 0x30, 0x30));
+    }
+
+    #[test]
+    fn test_color_mapped() {
+        let mut bytes = header(9, 8, 2, 2);
+        // two 24 bit entries starting at index 5
+        bytes[1] = 1;
+        bytes[3] = 5;
+        bytes[5] = 2;
+        bytes[7] = 24;
+        bytes.extend_from_slice(&[0x00, 0x00, 0xff, 0xff, 0x00, 0x00]);
+        bytes.extend_from_slice(&[0x82, 6, 0x00, 5]);
+
+        let pixmap = TgaImage::open(&write_file("toyrender_cmap.tga", &bytes)).unwrap();
+        assert_eq!(pixel(&pixmap, 0, 0), Rgba8::rgb(0, 0, 0xff));
+        assert_eq!(pixel(&pixmap, 0, 1), Rgba8::rgb(0, 0, 0xff));
+        assert_eq!(pixel(&pixmap, 1, 1), Rgba8::rgb(0xff, 0, 0));
+
+        let last = bytes.len() - 1;
+        bytes[last] = 7;
+        match TgaImage::open(&write_file("toyrender_cmap_bad.tga", &bytes)) {
+            Err(TgaError::ColorIndexOutOfRange(7)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_16_bit() {
+        let mut bytes = header(2, 16, 2, 2);
+        bytes.extend_from_slice(&[0x00, 0x7c, 0x1f, 0x00, 0xe0, 0x03, 0x00, 0x00]);
+
+        let pixmap = TgaImage::open(&write_file("toyrender_16.tga", &bytes)).unwrap();
+        assert_eq!(pixel(&pixmap, 0, 0), Rgba8::rgb(0xff, 0, 0));
+        assert_eq!(pixel(&pixmap, 1, 0), Rgba8::rgb(0, 0, 0xff));
+        assert_eq!(pixel(&pixmap, 0, 1), Rgba8::rgb(0, 0xff, 0));
+
+        // with one alpha bit in the descriptor the top bit becomes the alpha channel
+        bytes[17] |= 0x01;
+        bytes[19] |= 0x80;
+        let pixmap = TgaImage::open(&write_file("toyrender_16a.tga", &bytes)).unwrap();
+        assert_eq!(pixel(&pixmap, 0, 0), Rgba8::rgb(0xff, 0, 0));
+        assert_eq!(pixel(&pixmap, 1, 0), Rgba8::new(0, 0, 0xff, 0));
+    }
+
+    #[test]
+    fn test_32_bit_alpha() {
+        let mut bytes = header(2, 32, 1, 1);
+        bytes.extend_from_slice(&[0x10, 0x20, 0x30, 0x40]);
+
+        let pixmap = TgaImage::open(&write_file("toyrender_32.tga", &bytes)).unwrap();
+        assert_eq!(pixel(&pixmap, 0, 0), Rgba8::new(0x30, 0x20, 0x10, 0x40));
+    }
+
+    #[test]
+    fn test_origin_bits() {
+        let mut bytes = header(3, 8, 3, 2);
+        bytes[17] = 0x10; // bottom right
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+        let pixmap = TgaImage::open(&write_file("toyrender_origin.tga", &bytes)).unwrap();
+        assert_eq!(pixel(&pixmap, 0, 0).b, 6);
+        assert_eq!(pixel(&pixmap, 2, 0).b, 4);
+        assert_eq!(pixel(&pixmap, 0, 1).b, 3);
+        assert_eq!(pixel(&pixmap, 2, 1).b, 1);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let check = |name: &str, bytes: &[u8], expected: &str| {
+            match TgaImage::open(&write_file(name, bytes)) {
+                Err(err) => assert_eq!(format!("{:?}", err), expected),
+                Ok(_) => panic!("{} should not be decoded", name),
+            }
+        };
+
+        check("toyrender_short_header.tga", &[0, 0, 2], "Truncated");
+
+        let mut bytes = header(2, 24, 2, 2);
+        bytes.extend_from_slice(&[0; 11]);
+        check("toyrender_short_raw.tga", &bytes, "Truncated");
+
+        let mut bytes = header(10, 24, 2, 2);
+        bytes.extend_from_slice(&[0x84, 0, 0, 0]);
+        check("toyrender_overrun.tga", &bytes, "RleOverrun");
+
+        let mut bytes = header(10, 24, 2, 2);
+        bytes.extend_from_slice(&[0x03, 0, 0, 0]);
+        check("toyrender_short_rle.tga", &bytes, "Truncated");
+
+        check("toyrender_type.tga", &header(32, 24, 2, 2), "UnsupportedImageType(32)");
+        check("toyrender_depth.tga",
+              &header(3, 24, 2, 2),
+              "UnsupportedPixelDepth { datatypecode: 3, bitsperpixel: 24 }");
+        check("toyrender_no_cmap.tga", &header(1, 8, 2, 2), "MissingColorMap");
+        check("toyrender_empty.tga", &header(2, 24, 0, 2), "EmptyImage");
     }
 }
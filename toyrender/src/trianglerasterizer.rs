@@ -0,0 +1,468 @@
+use vector3d::Vec3f;
+
+/// Vertex positions are snapped to `1 / 2^SUBPIXEL_BITS` of a pixel.
+pub const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL: i64 = 1 << SUBPIXEL_BITS;
+
+/// Largest distance of a vertex from the origin in pixels for which the fixed point
+/// edge functions can not overflow. Corners further out are moved onto this bound,
+/// which changes the triangle, so callers should clip to it first.
+pub const GUARD_BAND: f32 = (1 << 20) as f32;
+
+/// Walks the pixels covered by a screen space triangle and yields them together with
+/// their barycentric weights. Coverage is tested at pixel centers with exact fixed point
+/// edge functions and the top-left fill rule, so triangles sharing an edge cover each
+/// pixel on it exactly once. Both windings are accepted, degenerate triangles cover
+/// nothing.
+pub struct TriangleRasterizer {
+    x: i32,
+    y: i32,
+    min_x: i32,
+    max_x: i32,
+    max_y: i32,
+
+    /// Edge functions at the current pixel and at the start of the current row, the
+    /// function of edge `i` is zero on the edge opposite of vertex `i`.
+    edges: [i64; 3],
+    row_edges: [i64; 3],
+    step_x: [i64; 3],
+    step_y: [i64; 3],
+    /// `-1` excludes pixels exactly on edges which are not top or left edges.
+    bias: [i64; 3],
+
+    inv_area: f32,
+    /// Vertices 1 and 2 were swapped to make the winding clockwise on screen.
+    swapped: bool,
+}
+
+fn to_fixed(v: Vec3f) -> (i64, i64) {
+    let s = SUBPIXEL as f32;
+    let clamp = |c: f32| (c.max(-GUARD_BAND).min(GUARD_BAND) * s).round() as i64;
+    (clamp(v.x), clamp(v.y))
+}
+
+/// Twice the signed area of `a`, `b`, `c`, positive if clockwise with `y` pointing down.
+fn edge(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Order of a triangle's corners as seen on screen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Winding {
+    /// Winding of a screen space triangle with `y` pointing down, `None` if it covers no
+    /// area once snapped to the subpixel grid.
+    pub fn of(v: [Vec3f; 3]) -> Option<Winding> {
+        let area = edge(to_fixed(v[0]), to_fixed(v[1]), to_fixed(v[2]));
+        if area > 0 {
+            Some(Winding::Clockwise)
+        } else if area < 0 {
+            Some(Winding::CounterClockwise)
+        } else {
+            None
+        }
+    }
+}
+
+/// Which triangles are discarded before rasterization.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+}
+
+/// Culling of the triangles drawn by the canvas pipeline.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RasterizerState {
+    pub cull_mode: CullMode,
+    /// Winding of triangles which face the viewer.
+    pub front_face: Winding,
+}
+
+impl RasterizerState {
+    /// No culling, counter-clockwise triangles are front facing.
+    pub fn new() -> RasterizerState {
+        RasterizerState {
+            cull_mode: CullMode::None,
+            front_face: Winding::CounterClockwise,
+        }
+    }
+
+    /// Whether a triangle with the given winding is discarded.
+    pub fn culls(&self, winding: Winding) -> bool {
+        match self.cull_mode {
+            CullMode::None => false,
+            CullMode::Front => winding == self.front_face,
+            CullMode::Back => winding != self.front_face,
+        }
+    }
+}
+
+impl Default for RasterizerState {
+    fn default() -> RasterizerState {
+        RasterizerState::new()
+    }
+}
+
+impl TriangleRasterizer {
+    /// Covered pixels of the triangle inside the `width` x `height` target.
+    pub fn new(v: [Vec3f; 3], width: usize, height: usize) -> TriangleRasterizer {
+        TriangleRasterizer::with_scissor(v, 0, 0, width as i32, height as i32)
+    }
+
+    /// Covered pixels with `left <= x < right` and `top <= y < bottom`.
+    pub fn with_scissor(v: [Vec3f; 3],
+                        left: i32,
+                        top: i32,
+                        right: i32,
+                        bottom: i32)
+                        -> TriangleRasterizer {
+        let mut p = [to_fixed(v[0]), to_fixed(v[1]), to_fixed(v[2])];
+        let mut area = edge(p[0], p[1], p[2]);
+        let swapped = area < 0;
+        if swapped {
+            p.swap(1, 2);
+            area = -area;
+        }
+
+        let floor = |c: i64| (c >> SUBPIXEL_BITS) as i32;
+        let min = |i: usize| p.iter().map(|q| if i == 0 { q.0 } else { q.1 }).min().unwrap();
+        let max = |i: usize| p.iter().map(|q| if i == 0 { q.0 } else { q.1 }).max().unwrap();
+        let min_x = floor(min(0)).max(left);
+        let min_y = floor(min(1)).max(top);
+        let mut max_x = floor(max(0)).min(right - 1);
+        let max_y = floor(max(1)).min(bottom - 1);
+        if area == 0 {
+            // nothing to cover, `next` stops right away
+            max_x = min_x - 1;
+        }
+
+        let center = (min_x as i64 * SUBPIXEL + SUBPIXEL / 2,
+                      min_y as i64 * SUBPIXEL + SUBPIXEL / 2);
+        let mut edges = [0; 3];
+        let mut step_x = [0; 3];
+        let mut step_y = [0; 3];
+        let mut bias = [0; 3];
+        for i in 0..3 {
+            let a = p[(i + 1) % 3];
+            let b = p[(i + 2) % 3];
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+
+            edges[i] = edge(a, b, center);
+            step_x[i] = -dy * SUBPIXEL;
+            step_y[i] = dx * SUBPIXEL;
+            let top_left = (dy == 0 && dx > 0) || dy < 0;
+            bias[i] = if top_left { 0 } else { -1 };
+        }
+
+        TriangleRasterizer {
+            x: min_x,
+            y: min_y,
+            min_x: min_x,
+            max_x: max_x,
+            max_y: if max_x < min_x { min_y - 1 } else { max_y },
+            edges: edges,
+            row_edges: edges,
+            step_x: step_x,
+            step_y: step_y,
+            bias: bias,
+            inv_area: 1.0 / area as f32,
+            swapped: swapped,
+        }
+    }
+
+    /// Covered pixels row by row, see `Span`.
+    pub fn spans(self) -> Spans {
+        Spans { raster: self }
+    }
+
+    /// Change of the barycentric weights for a step of `edge_step`, in the order of the
+    /// vertices passed to `new`.
+    fn weight_step(&self, edge_step: [i64; 3]) -> [f32; 3] {
+        let step = [edge_step[0] as f32 * self.inv_area,
+                    edge_step[1] as f32 * self.inv_area,
+                    edge_step[2] as f32 * self.inv_area];
+        if self.swapped {
+            [step[0], step[2], step[1]]
+        } else {
+            step
+        }
+    }
+
+    #[inline]
+    fn covered(&self) -> bool {
+        (0..3).all(|i| self.edges[i] + self.bias[i] >= 0)
+    }
+
+    #[inline]
+    fn weights(&self) -> [f32; 3] {
+        let w = [self.edges[0] as f32 * self.inv_area,
+                 self.edges[1] as f32 * self.inv_area,
+                 self.edges[2] as f32 * self.inv_area];
+        if self.swapped {
+            [w[0], w[2], w[1]]
+        } else {
+            w
+        }
+    }
+}
+
+impl Iterator for TriangleRasterizer {
+    /// Pixel position and the barycentric weights of the vertices at its center.
+    type Item = (i32, i32, [f32; 3]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.y <= self.max_y {
+            if self.x > self.max_x {
+                self.x = self.min_x;
+                self.y += 1;
+                for i in 0..3 {
+                    self.row_edges[i] += self.step_y[i];
+                }
+                self.edges = self.row_edges;
+                continue;
+            }
+
+            let item = if self.covered() {
+                Some((self.x, self.y, self.weights()))
+            } else {
+                None
+            };
+            self.x += 1;
+            for i in 0..3 {
+                self.edges[i] += self.step_x[i];
+            }
+            if item.is_some() {
+                return item;
+            }
+        }
+        None
+    }
+}
+
+/// Horizontal run of covered pixels, `left <= x < right`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub y: i32,
+    pub left: i32,
+    pub right: i32,
+    /// Barycentric weights at the center of the first pixel.
+    pub weights: [f32; 3],
+    /// Change of the weights from one pixel to the next.
+    pub step: [f32; 3],
+    /// Change of the weights from one row to the next.
+    pub step_y: [f32; 3],
+}
+
+/// Spans of a `TriangleRasterizer`, the first and last covered pixel of every row is
+/// solved for directly instead of testing every pixel of the bounding box.
+pub struct Spans {
+    raster: TriangleRasterizer,
+}
+
+fn floor_div(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+impl Iterator for Spans {
+    type Item = Span;
+
+    fn next(&mut self) -> Option<Span> {
+        let r = &mut self.raster;
+        while r.y <= r.max_y {
+            // pixel `k` of the row is covered if `row_edges + bias + k * step_x >= 0`
+            // holds for every edge
+            let mut first = 0;
+            let mut last = (r.max_x - r.min_x) as i64;
+            for i in 0..3 {
+                let e = r.row_edges[i] + r.bias[i];
+                let step = r.step_x[i];
+                if step > 0 {
+                    first = first.max(-floor_div(e, step));
+                } else if step < 0 {
+                    last = last.min(floor_div(e, -step));
+                } else if e < 0 {
+                    last = -1;
+                }
+            }
+
+            let y = r.y;
+            r.y += 1;
+            for i in 0..3 {
+                r.row_edges[i] += r.step_y[i];
+            }
+            if first > last {
+                continue;
+            }
+
+            for i in 0..3 {
+                r.edges[i] = r.row_edges[i] - r.step_y[i] + r.step_x[i] * first;
+            }
+            return Some(Span {
+                y: y,
+                left: r.min_x + first as i32,
+                right: r.min_x + last as i32 + 1,
+                weights: r.weights(),
+                step: r.weight_step(r.step_x),
+                step_y: r.weight_step(r.step_y),
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CullMode, RasterizerState, TriangleRasterizer, Winding, GUARD_BAND};
+    use vector3d::Vec3f;
+
+    fn coverage(triangles: &[[Vec3f; 3]], size: usize) -> Vec<u32> {
+        let mut count = vec![0; size * size];
+        for triangle in triangles {
+            for (x, y, _) in TriangleRasterizer::new(*triangle, size, size) {
+                count[y as usize * size + x as usize] += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_watertight() {
+        // a jittered grid split into triangles of both windings, the outline is the
+        // border of the target so every pixel must be covered exactly once
+        let n = 8;
+        let size = 64;
+        let cell = size as f32 / n as f32;
+        let point = |i: usize, j: usize| {
+            let border = i == 0 || j == 0 || i == n || j == n;
+            let jitter = if border { 0.0 } else { ((i * 7 + j * 13) % 11) as f32 * 0.37 - 1.8 };
+            Vec3f::new(i as f32 * cell + jitter, j as f32 * cell - jitter * 0.6, 0.0)
+        };
+
+        let mut triangles = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                let (a, b, c, d) = (point(i, j), point(i + 1, j), point(i + 1, j + 1), point(i, j + 1));
+                if (i + j) % 2 == 0 {
+                    triangles.push([a, b, c]);
+                    triangles.push([a, d, c]);
+                } else {
+                    triangles.push([a, b, d]);
+                    triangles.push([b, c, d]);
+                }
+            }
+        }
+
+        assert!(coverage(&triangles, size).iter().all(|&c| c == 1));
+
+        // scissored to quarters the triangles cover every pixel once as well
+        let mut count = vec![0; size * size];
+        let half = size as i32 / 2;
+        for &(left, top) in &[(0, 0), (half, 0), (0, half), (half, half)] {
+            for triangle in &triangles {
+                let raster = TriangleRasterizer::with_scissor(*triangle, left, top, left + half, top + half);
+                for (x, y, _) in raster {
+                    assert!(x >= left && x < left + half && y >= top && y < top + half);
+                    count[y as usize * size + x as usize] += 1;
+                }
+            }
+        }
+        assert!(count.iter().all(|&c| c == 1));
+
+        // spans cover the same pixels with the same weights
+        for triangle in &triangles {
+            let pixels: Vec<_> = TriangleRasterizer::new(*triangle, size, size).collect();
+            let mut from_spans = Vec::new();
+            for span in TriangleRasterizer::new(*triangle, size, size).spans() {
+                for x in span.left..span.right {
+                    let k = (x - span.left) as f32;
+                    let w = [span.weights[0] + span.step[0] * k,
+                             span.weights[1] + span.step[1] * k,
+                             span.weights[2] + span.step[2] * k];
+                    from_spans.push((x, span.y, w));
+                }
+            }
+
+            assert_eq!(pixels.len(), from_spans.len());
+            for (a, b) in pixels.iter().zip(from_spans.iter()) {
+                assert_eq!((a.0, a.1), (b.0, b.1));
+                assert!((0..3).all(|i| (a.2[i] - b.2[i]).abs() < 1e-4));
+            }
+        }
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let line = [Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(5.0, 5.0, 0.0), Vec3f::new(10.0, 10.0, 0.0)];
+        assert_eq!(TriangleRasterizer::new(line, 16, 16).count(), 0);
+
+        let flat = [Vec3f::new(1.0, 4.0, 0.0), Vec3f::new(9.0, 4.0, 0.0), Vec3f::new(5.0, 4.0, 0.0)];
+        assert_eq!(TriangleRasterizer::new(flat, 16, 16).count(), 0);
+
+        let offscreen = [Vec3f::new(-9.0, 0.0, 0.0), Vec3f::new(-1.0, 0.0, 0.0), Vec3f::new(-5.0, 8.0, 0.0)];
+        assert_eq!(TriangleRasterizer::new(offscreen, 16, 16).count(), 0);
+    }
+
+    #[test]
+    fn test_far_vertices() {
+        // corners beyond the guard band are clamped instead of overflowing
+        let huge = [Vec3f::new(-2e7, -2e7, 0.0), Vec3f::new(2e7, -2e7, 0.0), Vec3f::new(2e7, 2e7, 0.0)];
+        let g = GUARD_BAND;
+        let clamped = [Vec3f::new(-g, -g, 0.0), Vec3f::new(g, -g, 0.0), Vec3f::new(g, g, 0.0)];
+        assert_eq!(Winding::of(huge), Some(Winding::Clockwise));
+        let covered = TriangleRasterizer::new(huge, 16, 16).count();
+        assert!(covered > 120);
+        assert_eq!(covered, TriangleRasterizer::new(clamped, 16, 16).count());
+        let far = [Vec3f::new(3e38, 0.0, 0.0), Vec3f::new(-3e38, 1.0, 0.0), Vec3f::new(0.0, -3e38, 0.0)];
+        TriangleRasterizer::new(far, 16, 16).count();
+    }
+
+    #[test]
+    fn test_winding_and_culling() {
+        let (a, b, c) = (Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(8.0, 0.0, 0.0), Vec3f::new(0.0, 8.0, 0.0));
+        // y points down, a -> b -> c turns clockwise on screen
+        assert_eq!(Winding::of([a, b, c]), Some(Winding::Clockwise));
+        assert_eq!(Winding::of([a, c, b]), Some(Winding::CounterClockwise));
+        assert_eq!(Winding::of([a, b, b * 2.0]), None);
+
+        let mut state = RasterizerState::new();
+        assert!(!state.culls(Winding::Clockwise) && !state.culls(Winding::CounterClockwise));
+        state.cull_mode = CullMode::Back;
+        assert!(state.culls(Winding::Clockwise) && !state.culls(Winding::CounterClockwise));
+        state.cull_mode = CullMode::Front;
+        assert!(!state.culls(Winding::Clockwise) && state.culls(Winding::CounterClockwise));
+        state.front_face = Winding::Clockwise;
+        assert!(state.culls(Winding::Clockwise) && !state.culls(Winding::CounterClockwise));
+    }
+
+    #[test]
+    fn test_weights() {
+        let triangle = [Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 16.0, 0.0), Vec3f::new(16.0, 0.0, 0.0)];
+        for (x, y, w) in TriangleRasterizer::new(triangle, 16, 16) {
+            assert!((w[0] + w[1] + w[2] - 1.0).abs() < 1e-5);
+            // interpolating the positions gives back the pixel center
+            let cx = w[2] * 16.0;
+            let cy = w[1] * 16.0;
+            assert!((cx - (x as f32 + 0.5)).abs() < 1e-4);
+            assert!((cy - (y as f32 + 0.5)).abs() < 1e-4);
+        }
+        // a row down the weights change by `step_y`
+        for span in TriangleRasterizer::new(triangle, 16, 16).spans() {
+            assert!((span.step_y[1] - 1.0 / 16.0).abs() < 1e-6);
+            assert!((span.step_y[0] + span.step_y[2] + 1.0 / 16.0).abs() < 1e-6);
+        }
+        // the diagonal runs through pixel centers and is a right edge, they are left to
+        // the neighbouring triangle
+        assert_eq!(TriangleRasterizer::new(triangle, 16, 16).count(), 15 * 16 / 2);
+    }
+}
@@ -5,7 +5,7 @@ use std::ops::BitXor;
 use std::ops::{Sub, Index, IndexMut, Mul, Add};
 use num::traits::NumCast;
 
-use num::pow;
+use simd::F32x4;
 
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
 pub struct Vector3D<T> {
@@ -37,13 +37,28 @@ impl<T> Vector3D<T> where T: Copy + Clone
     }
 }
 
+/// `dot` and `norm` run on `F32x4` lanes with the fourth lane zero, the operators
+/// stay generic over `T`.
 impl Vector3D<f32> {
+    #[inline]
+    pub fn simd(self) -> F32x4 {
+        F32x4::new(self.x, self.y, self.z, 0.0)
+    }
+
+    #[inline]
+    pub fn from_simd(v: F32x4) -> Vec3f {
+        let a = v.to_array();
+        Vec3f::new(a[0], a[1], a[2])
+    }
+
+    #[inline]
     pub fn dot(&self, other: Vector3D<f32>) -> f32 {
-        self.x * other.x + self.y * other.y + self.z * other.z
+        self.simd().dot(other.simd())
     }
 
+    #[inline]
     pub fn norm(self) -> f32 {
-        return (num::pow(self.x, 2) + num::pow(self.z, 2) + num::pow(self.y, 2)).sqrt();
+        self.dot(self).sqrt()
     }
 
     pub fn normalized(self) -> Vector3D<f32> {
@@ -153,42 +168,64 @@ pub struct Vertex {
     pub pos: Vec3f,
     pub uv: Vec3f,
     pub norm: Vec3f,
+    pub tangent: Vec3f,
+    pub bitangent: Vec3f,
+}
+
+/// The fifteen components of a `Vertex` packed into four `F32x4`, the last lane is
+/// zero. The span loops interpolate all varyings at once, so the sums and scaling
+/// below take four vector operations instead of fifteen scalar ones.
+impl Vertex {
+    #[inline]
+    fn lanes(&self) -> [F32x4; 4] {
+        [F32x4::new(self.pos.x, self.pos.y, self.pos.z, self.uv.x),
+         F32x4::new(self.uv.y, self.uv.z, self.norm.x, self.norm.y),
+         F32x4::new(self.norm.z, self.tangent.x, self.tangent.y, self.tangent.z),
+         self.bitangent.simd()]
+    }
+
+    #[inline]
+    fn from_lanes(lanes: [F32x4; 4]) -> Vertex {
+        let a = lanes[0].to_array();
+        let b = lanes[1].to_array();
+        let c = lanes[2].to_array();
+        Vertex {
+            pos: Vec3f::new(a[0], a[1], a[2]),
+            uv: Vec3f::new(a[3], b[0], b[1]),
+            norm: Vec3f::new(b[2], b[3], c[0]),
+            tangent: Vec3f::new(c[1], c[2], c[3]),
+            bitangent: Vec3f::from_simd(lanes[3]),
+        }
+    }
 }
 
 impl Sub for Vertex {
     type Output = Vertex;
 
+    #[inline]
     fn sub(self, other: Vertex) -> Self::Output {
-        Vertex {
-            pos: self.pos - other.pos,
-            uv: self.uv - other.uv,
-            norm: self.norm - other.norm,
-        }
+        let (a, b) = (self.lanes(), other.lanes());
+        Vertex::from_lanes([a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]])
     }
 }
 
 impl Add for Vertex {
     type Output = Vertex;
 
+    #[inline]
     fn add(self, other: Vertex) -> Self::Output {
-        Vertex {
-            pos: self.pos + other.pos,
-            uv: self.uv + other.uv,
-            norm: self.norm + other.norm,
-        }
+        let (a, b) = (self.lanes(), other.lanes());
+        Vertex::from_lanes([a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]])
     }
 }
 
-
 impl Mul<f32> for Vertex {
     type Output = Vertex;
 
+    #[inline]
     fn mul(self, other: f32) -> Self::Output {
-        Vertex {
-            pos: self.pos * other,
-            uv: self.uv * other,
-            norm: self.norm * other,
-        }
+        let a = self.lanes();
+        Vertex::from_lanes([a[0] * other, a[1] * other, a[2] * other, a[3] * other])
     }
 }
 
@@ -203,6 +240,51 @@ fn test_normalized() {
     assert!(v2.normalized().norm() - 1.0 < EPS);
 }
 
+#[test]
+fn test_arithmetic() {
+    let a = Vec3f::new(1.0, 2.0, 3.0);
+    let b = Vec3f::new(-2.0, 0.5, 4.0);
+
+    assert_eq!(a + b, Vec3f::new(-1.0, 2.5, 7.0));
+    assert_eq!(a - b, Vec3f::new(3.0, 1.5, -1.0));
+    assert_eq!(a * 2.0, Vec3f::new(2.0, 4.0, 6.0));
+    assert_eq!(a * b, 11.0);
+    assert_eq!(a.dot(b), 11.0);
+    assert_eq!(Vec3f::new(3.0, 0.0, 4.0).norm(), 5.0);
+    assert_eq!(a ^ b, Vec3f::new(6.5, -10.0, 4.5));
+    assert_eq!(a.simd(), F32x4::new(1.0, 2.0, 3.0, 0.0));
+    assert_eq!(Vec3f::from_simd(F32x4::new(1.0, 2.0, 3.0, 4.0)), a);
+
+    let i = Vec3i::new(1, 2, 3);
+    assert_eq!(i + i * 2 - Vec3i::new(0, 1, 0), Vec3i::new(3, 5, 9));
+    assert_eq!(i * i, 14);
+
+    let d = Vector3D::new(1.0f64, 2.0, 3.0);
+    assert_eq!(d + d * 2.0 - d, Vector3D::new(2.0, 4.0, 6.0));
+    assert_eq!(d * d, 14.0);
+}
+
+#[test]
+fn test_vertex_arithmetic() {
+    let v = |s: f32| {
+        Vertex {
+            pos: Vec3f::new(1.0, 2.0, 3.0) * s,
+            uv: Vec3f::new(4.0, 5.0, 6.0) * s,
+            norm: Vec3f::new(7.0, 8.0, 9.0) * s,
+            tangent: Vec3f::new(10.0, 11.0, 12.0) * s,
+            bitangent: Vec3f::new(13.0, 14.0, 15.0) * s,
+        }
+    };
+    let same = |a: Vertex, b: Vertex| {
+        a.pos == b.pos && a.uv == b.uv && a.norm == b.norm && a.tangent == b.tangent &&
+        a.bitangent == b.bitangent
+    };
+
+    assert!(same(v(1.0) + v(2.0), v(3.0)));
+    assert!(same(v(1.0) - v(3.0), v(-2.0)));
+    assert!(same(v(1.0) * 0.5, v(0.5)));
+}
+
 #[test]
 fn test_numcast() {
     let v1 = Vec3f::new(2.0, 2.0, 0.0);
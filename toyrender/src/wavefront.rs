@@ -0,0 +1,599 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Error, ErrorKind};
+use std::result;
+use std::str::FromStr;
+
+use model::{Material, Model, SubMesh};
+use vector3d::{Vec3f, Vec3i};
+
+pub type ObjResult<T> = result::Result<T, ObjError>;
+
+#[derive(Debug)]
+pub enum ObjErrorKind {
+    Io(io::Error),
+    InvalidNumber(String),
+    /// The statement has too few arguments, e.g. `vn 1 0`.
+    MissingArguments(String),
+    /// Face vertex is not one of `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+    InvalidIndex(String),
+    /// Index refers to an element which has not been defined yet.
+    IndexOutOfRange(i64),
+    /// Faces need at least three vertices.
+    TooFewVertices(usize),
+    InvalidSmoothingGroup(String),
+}
+
+/// Error which points to the offending line, line zero means the file itself.
+#[derive(Debug)]
+pub struct ObjError {
+    pub file: String,
+    pub line: usize,
+    pub kind: ObjErrorKind,
+}
+
+impl ObjError {
+    pub fn new(file: &str, line: usize, kind: ObjErrorKind) -> ObjError {
+        ObjError {
+            file: file.to_string(),
+            line: line,
+            kind: kind,
+        }
+    }
+}
+
+impl fmt::Display for ObjErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjErrorKind::Io(ref err) => write!(f, "{}", err),
+            ObjErrorKind::InvalidNumber(ref s) => write!(f, "invalid number `{}`", s),
+            ObjErrorKind::MissingArguments(ref s) => write!(f, "not enough arguments for `{}`", s),
+            ObjErrorKind::InvalidIndex(ref s) => write!(f, "invalid face vertex `{}`", s),
+            ObjErrorKind::IndexOutOfRange(i) => write!(f, "index {} is out of range", i),
+            ObjErrorKind::TooFewVertices(n) => write!(f, "face with {} vertices", n),
+            ObjErrorKind::InvalidSmoothingGroup(ref s) => {
+                write!(f, "invalid smoothing group `{}`", s)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.kind)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.kind)
+        }
+    }
+}
+
+impl error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ObjErrorKind::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ObjError> for io::Error {
+    fn from(err: ObjError) -> io::Error {
+        match err.kind {
+            ObjErrorKind::Io(_) => Error::new(ErrorKind::Other, err),
+            _ => Error::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+type ParseResult<T> = result::Result<T, ObjErrorKind>;
+
+fn parse_number<T: FromStr>(word: &str) -> ParseResult<T> {
+    word.parse().map_err(|_| ObjErrorKind::InvalidNumber(word.to_string()))
+}
+
+/// Parses at least `min` numbers, the rest of the line must be numbers too.
+fn parse_numbers(statement: &str, words: &[&str], min: usize) -> ParseResult<Vec<f32>> {
+    if words.len() < min {
+        return Err(ObjErrorKind::MissingArguments(statement.to_string()));
+    }
+    words.iter().map(|word| parse_number(word)).collect()
+}
+
+/// Turns a one based, possibly negative (relative) index into a zero based one.
+fn resolve_index(word: &str, count: usize) -> ParseResult<i32> {
+    let index: i64 = try!(word.parse().map_err(|_| ObjErrorKind::InvalidIndex(word.to_string())));
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjErrorKind::IndexOutOfRange(index));
+    }
+    Ok(resolved as i32)
+}
+
+/// Current `o`, `g`, `usemtl` and `s` state, faces sharing it form a sub-mesh.
+#[derive(Default)]
+struct State {
+    object: Option<String>,
+    group: Option<String>,
+    material: Option<String>,
+    smoothing: u32,
+}
+
+struct ObjParser {
+    model: Model,
+    state: State,
+}
+
+impl ObjParser {
+    /// Parses a statement joined from its continuation lines, blank ones are skipped.
+    fn line(&mut self, statement: &str) -> ParseResult<()> {
+        let words: Vec<&str> = statement.split_whitespace().collect();
+        match words.split_first() {
+            Some((keyword, args)) => self.statement(keyword, args),
+            None => Ok(()),
+        }
+    }
+
+    fn statement(&mut self, keyword: &str, args: &[&str]) -> ParseResult<()> {
+        match keyword {
+            "v" => {
+                // x y z [w], some tools append vertex colors
+                let v = try!(parse_numbers(keyword, args, 3));
+                self.model.verticies.push(Vec3f::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let vt = try!(parse_numbers(keyword, args, 1));
+                let v = if vt.len() > 1 {
+                    vt[1]
+                } else {
+                    0.0
+                };
+                self.model.uv.push([vt[0], v]);
+            }
+            "vn" => {
+                let n = try!(parse_numbers(keyword, args, 3));
+                self.model.normals.push(Vec3f::new(n[0], n[1], n[2]));
+            }
+            "f" => try!(self.face(args)),
+            "o" => self.state.object = Some(args.join(" ")),
+            "g" => {
+                self.state.group = if args.is_empty() {
+                    None
+                } else {
+                    Some(args.join(" "))
+                }
+            }
+            "usemtl" => self.state.material = args.first().map(|name| name.to_string()),
+            "mtllib" => {
+                for name in args {
+                    self.model.material_libs.push(name.to_string());
+                }
+            }
+            "s" => {
+                self.state.smoothing = match args.first() {
+                    Some(&"off") => 0,
+                    Some(word) => {
+                        try!(word.parse()
+                                 .map_err(|_| ObjErrorKind::InvalidSmoothingGroup(word.to_string())))
+                    }
+                    None => return Err(ObjErrorKind::MissingArguments(keyword.to_string())),
+                }
+            }
+            // points, lines and free-form geometry are not rendered
+            "p" | "l" | "vp" | "cstype" | "deg" | "bmat" | "step" | "curv" | "curv2" | "surf" |
+            "parm" | "trim" | "hole" | "scrv" | "sp" | "end" => {
+                debug!("obj: skipping `{}` statement", keyword);
+            }
+            _ => warn!("obj: unknown statement `{}`", keyword),
+        }
+        Ok(())
+    }
+
+    fn face(&mut self, args: &[&str]) -> ParseResult<()> {
+        if args.len() < 3 {
+            return Err(ObjErrorKind::TooFewVertices(args.len()));
+        }
+
+        let mut polygon = Vec::with_capacity(args.len());
+        for word in args {
+            let mut vertex = Vec3i::new(-1, -1, -1);
+
+            let parts: Vec<&str> = word.split('/').collect();
+            if parts.len() > 3 || parts[0].is_empty() {
+                return Err(ObjErrorKind::InvalidIndex(word.to_string()));
+            }
+
+            let counts = [self.model.verticies.len(), self.model.uv.len(), self.model.normals.len()];
+            for (i, part) in parts.iter().enumerate() {
+                if part.is_empty() {
+                    continue;
+                }
+                vertex[i] = try!(resolve_index(part, counts[i]));
+            }
+            polygon.push(vertex);
+        }
+
+        // Fan triangulation, exact for the convex polygons exporters produce.
+        let start = self.model.faces.len();
+        for i in 1..polygon.len() - 1 {
+            self.model.faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+        }
+        self.extend_mesh(start);
+
+        Ok(())
+    }
+
+    fn extend_mesh(&mut self, start: usize) {
+        let end = self.model.faces.len();
+        if let Some(mesh) = self.model.meshes.last_mut() {
+            let state = &self.state;
+            if mesh.faces.end == start && mesh.object == state.object &&
+               mesh.group == state.group && mesh.material == state.material &&
+               mesh.smoothing == state.smoothing {
+                mesh.faces.end = end;
+                return;
+            }
+        }
+
+        self.model.meshes.push(SubMesh {
+            object: self.state.object.clone(),
+            group: self.state.group.clone(),
+            material: self.state.material.clone(),
+            smoothing: self.state.smoothing,
+            faces: start..end,
+        });
+    }
+}
+
+/// Reads a Wavefront OBJ file, polygons are triangulated and faces are split into
+/// sub-meshes by object, group, material and smoothing group. Missing texture or
+/// normal indices are stored as `-1`.
+pub fn parse_obj<R: BufRead>(file: &str, input: R) -> ObjResult<Model> {
+    let mut parser = ObjParser {
+        model: Model::default(),
+        state: State::default(),
+    };
+
+    // a trailing backslash continues the statement on the next line
+    let mut statement = String::new();
+    let mut statement_line = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = try!(line.map_err(|err| ObjError::new(file, i + 1, ObjErrorKind::Io(err))));
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => &line[..],
+        };
+
+        if statement.is_empty() {
+            statement_line = i + 1;
+        }
+        let line = line.trim_end();
+        if line.ends_with('\\') {
+            statement.push_str(&line[..line.len() - 1]);
+            statement.push(' ');
+            continue;
+        }
+        statement.push_str(line);
+
+        try!(parser.line(&statement).map_err(|kind| ObjError::new(file, statement_line, kind)));
+        statement.clear();
+    }
+    // a continuation on the last line ends the statement at the end of the file
+    try!(parser.line(&statement).map_err(|kind| ObjError::new(file, statement_line, kind)));
+
+    parser.model.compute_tangents();
+    Ok(parser.model)
+}
+
+/// Texture file names of a material, relative to the material library.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaterialMaps {
+    pub diffuse: Option<String>,
+    pub normal: Option<String>,
+    pub specular: Option<String>,
+    pub shininess: Option<String>,
+}
+
+/// Number of values which follow a texture map option.
+fn map_option_arity(option: &str) -> Option<usize> {
+    match option {
+        "-blendu" | "-blendv" | "-bm" | "-boost" | "-clamp" | "-imfchan" | "-texres" |
+        "-type" => Some(1),
+        "-mm" => Some(2),
+        // up to three numbers, see `map_file_name`
+        "-o" | "-s" | "-t" | "-cc" => Some(3),
+        _ => None,
+    }
+}
+
+/// Skips the options of a `map_*` statement and returns the file name.
+fn map_file_name(statement: &str, args: &[&str]) -> ParseResult<String> {
+    let mut i = 0;
+    while i < args.len() {
+        match map_option_arity(args[i]) {
+            Some(3) => {
+                i += 1;
+                let mut n = 0;
+                while n < 3 && i < args.len() - 1 && args[i].parse::<f32>().is_ok() {
+                    i += 1;
+                    n += 1;
+                }
+            }
+            Some(arity) => i += 1 + arity,
+            None => break,
+        }
+    }
+
+    if i >= args.len() {
+        return Err(ObjErrorKind::MissingArguments(statement.to_string()));
+    }
+    Ok(args[i..].join(" "))
+}
+
+fn parse_color(statement: &str, args: &[&str]) -> ParseResult<Vec3f> {
+    let c = try!(parse_numbers(statement, args, 1));
+    if c.len() < 3 {
+        return Ok(Vec3f::new(c[0], c[0], c[0]));
+    }
+    Ok(Vec3f::new(c[0], c[1], c[2]))
+}
+
+fn mtl_statement(materials: &mut Vec<(Material, MaterialMaps)>,
+                 keyword: &str,
+                 args: &[&str])
+                 -> ParseResult<()> {
+    if keyword == "newmtl" {
+        let mut material = Material::default();
+        material.name = args.join(" ");
+        materials.push((material, MaterialMaps::default()));
+        return Ok(());
+    }
+
+    let &mut (ref mut material, ref mut maps) = match materials.last_mut() {
+        Some(last) => last,
+        None => {
+            warn!("mtl: `{}` before the first `newmtl`", keyword);
+            return Ok(());
+        }
+    };
+
+    match keyword {
+        "Ka" | "Kd" | "Ks" if args.first() == Some(&"spectral") || args.first() == Some(&"xyz") => {
+            warn!("mtl: only rgb colors are supported");
+        }
+        "Ka" => material.ambient = try!(parse_color(keyword, args)),
+        "Kd" => material.diffuse = try!(parse_color(keyword, args)),
+        "Ks" => material.specular = try!(parse_color(keyword, args)),
+        "Ns" => material.shininess = try!(parse_numbers(keyword, args, 1))[0],
+        "d" => {
+            // `d -halo 0.5` is treated as plain dissolve
+            let args = if args.first() == Some(&"-halo") {
+                &args[1..]
+            } else {
+                args
+            };
+            material.dissolve = try!(parse_numbers(keyword, args, 1))[0];
+        }
+        "Tr" => material.dissolve = 1.0 - try!(parse_numbers(keyword, args, 1))[0],
+        "map_Kd" => maps.diffuse = Some(try!(map_file_name(keyword, args))),
+        // exporters write normal maps as bump maps, height maps are not supported
+        "map_Bump" | "map_bump" | "bump" | "norm" => {
+            maps.normal = Some(try!(map_file_name(keyword, args)))
+        }
+        "map_Ks" => maps.specular = Some(try!(map_file_name(keyword, args))),
+        "map_Ns" => maps.shininess = Some(try!(map_file_name(keyword, args))),
+        _ => debug!("mtl: skipping `{}` statement", keyword),
+    }
+    Ok(())
+}
+
+/// Reads a Wavefront material library, texture maps are returned as file names.
+pub fn parse_mtl<R: BufRead>(file: &str, input: R) -> ObjResult<Vec<(Material, MaterialMaps)>> {
+    let mut materials = Vec::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let line = try!(line.map_err(|err| ObjError::new(file, i + 1, ObjErrorKind::Io(err))));
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => &line[..],
+        };
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let Some((keyword, args)) = words.split_first() {
+            try!(mtl_statement(&mut materials, keyword, args)
+                     .map_err(|kind| ObjError::new(file, i + 1, kind)));
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mtl, parse_obj, MaterialMaps, ObjErrorKind};
+    use model::{Model, NormalSpace};
+    use vector3d::{Vec3f, Vec3i};
+
+    fn parse(source: &str) -> Model {
+        parse_obj("test.obj", source.as_bytes()).unwrap()
+    }
+
+    fn error(source: &str) -> (usize, ObjErrorKind) {
+        let err = parse_obj("test.obj", source.as_bytes()).err().expect("parse error");
+        assert_eq!(err.file, "test.obj");
+        (err.line, err.kind)
+    }
+
+    const SQUARE: &'static str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0 1.0
+v 0 1 0
+vt 0 0
+vt 1 0 0
+vt 1
+vn 0 0 1
+";
+
+    #[test]
+    fn test_index_forms() {
+        let model = parse(&format!("{}f 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf 1/1/1 2/2/1 \
+                                    3/3/1\n",
+                                   SQUARE));
+
+        assert_eq!(model.faces.len(), 4);
+        assert_eq!(model.faces[0][2], Vec3i::new(2, -1, -1));
+        assert_eq!(model.faces[1][2], Vec3i::new(2, 2, -1));
+        assert_eq!(model.faces[2][2], Vec3i::new(2, -1, 0));
+        assert_eq!(model.faces[3][2], Vec3i::new(2, 2, 0));
+        assert_eq!(model.uv[2], [1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_relative_indices() {
+        let model = parse(&format!("{}f -4/-3/-1 -3/-2/-1 -2/-1/-1\n", SQUARE));
+
+        assert_eq!(model.faces[0][0], Vec3i::new(0, 0, 0));
+        assert_eq!(model.faces[0][2], Vec3i::new(2, 2, 0));
+    }
+
+    #[test]
+    fn test_triangulation() {
+        let model = parse(&format!("{}f 1 2 3 4\n", SQUARE));
+
+        assert_eq!(model.faces.len(), 2);
+        assert_eq!(model.faces[1][0][0], 0);
+        assert_eq!(model.faces[1][1][0], 2);
+        assert_eq!(model.faces[1][2][0], 3);
+    }
+
+    #[test]
+    fn test_sub_meshes() {
+        let model = parse(&format!("{}mtllib a.mtl b.mtl\no box\ng front\nusemtl red\nf 1 2 \
+                                    3\nf 1 3 4\ng back\ns 1\nf 3 2 1\nusemtl blue\nf 4 3 1\n",
+                                   SQUARE));
+
+        assert_eq!(model.material_libs, vec!["a.mtl".to_string(), "b.mtl".to_string()]);
+        assert_eq!(model.meshes.len(), 3);
+        assert_eq!(model.meshes[0].faces, 0..2);
+        assert_eq!(model.meshes[0].object, Some("box".to_string()));
+        assert_eq!(model.meshes[0].group, Some("front".to_string()));
+        assert_eq!(model.meshes[0].material, Some("red".to_string()));
+        assert_eq!(model.meshes[1].faces, 2..3);
+        assert_eq!(model.meshes[1].smoothing, 1);
+        assert_eq!(model.meshes[2].material, Some("blue".to_string()));
+    }
+
+    #[test]
+    fn test_comments_and_continuation() {
+        let model = parse("# header\nv 0 0 0 # origin\nv 1 0 \\\n  0\nv 0 1 0\n\nf 1 2 \\\n3\n");
+
+        assert_eq!(model.verticies.len(), 3);
+        assert_eq!(model.faces.len(), 1);
+
+        let model = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3 \\");
+        assert_eq!(model.faces.len(), 1);
+        match error("v 0 0 0\nv 1 0 0\nf 1 \\\n2 \\\n") {
+            (3, _) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        match error("v 0 0 0\nv 1 x 0\n") {
+            (2, ObjErrorKind::InvalidNumber(ref s)) if s == "x" => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match error("v 0 0 0\nvn 0 1\n") {
+            (2, ObjErrorKind::MissingArguments(ref s)) if s == "vn" => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match error(&format!("{}f 1 2 7\n", SQUARE)) {
+            (10, ObjErrorKind::IndexOutOfRange(7)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match error(&format!("{}f 1 2 -5\n", SQUARE)) {
+            (10, ObjErrorKind::IndexOutOfRange(-5)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match error(&format!("{}f 1/1/1/1 2 3\n", SQUARE)) {
+            (10, ObjErrorKind::InvalidIndex(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match error(&format!("{}f 1 2\n", SQUARE)) {
+            (10, ObjErrorKind::TooFewVertices(2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match error("s maybe\n") {
+            (1, ObjErrorKind::InvalidSmoothingGroup(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_message() {
+        let err = parse_obj("head.obj", "v 0 0 0\nf 1 1\n".as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "head.obj:2: face with 2 vertices");
+    }
+
+    #[test]
+    fn test_mtl() {
+        let source = "
+newmtl skin
+Ka 0.1 0.2 0.3
+Kd 0.5
+Ks 1 1 1
+Ns 16
+Tr 0.25
+map_Kd -s 2 2 -clamp on skin diffuse.tga
+map_Bump -bm 0.5 skin_nm.tga
+map_Ks skin_spec.tga
+map_Ns skin_gloss.tga
+
+newmtl empty
+";
+        let materials = parse_mtl("test.mtl", source.as_bytes()).unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let (ref skin, ref maps) = materials[0];
+        assert_eq!(skin.name, "skin");
+        assert_eq!(skin.ambient, Vec3f::new(0.1, 0.2, 0.3));
+        assert_eq!(skin.diffuse, Vec3f::new(0.5, 0.5, 0.5));
+        assert_eq!(skin.shininess, 16.0);
+        assert_eq!(skin.dissolve, 0.75);
+        assert_eq!(maps.diffuse, Some("skin diffuse.tga".to_string()));
+        assert_eq!(maps.normal, Some("skin_nm.tga".to_string()));
+        assert_eq!(skin.normal_space, NormalSpace::Tangent);
+        assert_eq!(maps.specular, Some("skin_spec.tga".to_string()));
+        assert_eq!(maps.shininess, Some("skin_gloss.tga".to_string()));
+
+        assert_eq!(materials[1].0.dissolve, 1.0);
+        assert_eq!(materials[1].1, MaterialMaps::default());
+    }
+
+    #[test]
+    fn test_mtl_errors() {
+        let err = parse_mtl("test.mtl", "newmtl a\nNs high\n".as_bytes()).err().unwrap();
+        assert_eq!(err.line, 2);
+
+        let err = parse_mtl("test.mtl", "newmtl a\nmap_Kd -bm 1\n".as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "test.mtl:2: not enough arguments for `map_Kd`");
+    }
+
+    #[test]
+    fn test_normal_map_keywords() {
+        for keyword in &["map_Bump", "map_bump", "bump", "norm"] {
+            let source = format!("newmtl a\n{} a_nm.tga\n", keyword);
+            let materials = parse_mtl("test.mtl", source.as_bytes()).unwrap();
+            assert_eq!(materials[0].1.normal, Some("a_nm.tga".to_string()));
+            assert_eq!(materials[0].0.normal_space, NormalSpace::Tangent);
+        }
+    }
+}
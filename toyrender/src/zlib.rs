@@ -0,0 +1,494 @@
+use std::error;
+use std::fmt;
+use std::result;
+
+pub type InflateResult<T> = result::Result<T, InflateError>;
+
+/// Describes why a zlib stream could not be decompressed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InflateError {
+    /// The data ended before the last block.
+    Truncated,
+    /// The header names another method than deflate or its check bits are wrong.
+    InvalidHeader,
+    /// The stream needs a preset dictionary.
+    PresetDictionary,
+    /// Block type 3 is reserved.
+    InvalidBlockType,
+    /// The length of a stored block does not match its complement.
+    StoredLengthMismatch,
+    /// Code lengths which do not form a prefix code.
+    InvalidCodeLengths,
+    /// A code which is not assigned to a literal, length or distance.
+    InvalidSymbol,
+    /// A distance which reaches back before the start of the output.
+    DistanceTooFar,
+    /// The Adler-32 checksum does not match the decompressed data.
+    ChecksumMismatch,
+    /// The data decompresses to more bytes than the caller allowed.
+    OutputTooLarge,
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match *self {
+            InflateError::Truncated => "unexpected end of compressed data",
+            InflateError::InvalidHeader => "invalid zlib header",
+            InflateError::PresetDictionary => "preset dictionaries are not supported",
+            InflateError::InvalidBlockType => "invalid block type",
+            InflateError::StoredLengthMismatch => "stored block length does not match",
+            InflateError::InvalidCodeLengths => "invalid huffman code lengths",
+            InflateError::InvalidSymbol => "invalid huffman code",
+            InflateError::DistanceTooFar => "distance reaches before the start of the data",
+            InflateError::ChecksumMismatch => "adler-32 checksum mismatch",
+            InflateError::OutputTooLarge => "decompressed data exceeds the expected size",
+        };
+        write!(f, "inflate: {}", message)
+    }
+}
+
+impl error::Error for InflateError {}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43,
+                                51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4,
+                                4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385,
+                              513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385,
+                              24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9,
+                              10, 10, 11, 11, 12, 12, 13, 13];
+/// Order in which the lengths of the code length code are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14,
+                                        1, 15];
+
+const MAX_BITS: usize = 15;
+/// Codes up to this length are decoded with a single table lookup.
+const FAST_BITS: u32 = 9;
+
+/// Reads the little endian bit stream of deflate.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Bits<'a> {
+        Bits {
+            data: data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        while self.count <= 56 && self.pos < self.data.len() {
+            self.buffer |= (self.data[self.pos] as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+    }
+
+    /// Next `n` bits without consuming them, padded with zeros past the end.
+    fn peek(&mut self, n: u32) -> u32 {
+        if self.count < n {
+            self.refill();
+        }
+        (self.buffer & ((1 << n) - 1)) as u32
+    }
+
+    fn consume(&mut self, n: u32) -> InflateResult<()> {
+        if n > self.count {
+            return Err(InflateError::Truncated);
+        }
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> InflateResult<u32> {
+        let value = self.peek(n);
+        try!(self.consume(n));
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let rest = self.count % 8;
+        self.buffer >>= rest;
+        self.count -= rest;
+    }
+
+    /// Bytes read so far, not counting whole bytes still in the bit buffer.
+    fn bytes_consumed(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+}
+
+/// Canonical Huffman code, short codes are looked up in `fast` and longer ones are
+/// decoded bit by bit from the code counts.
+struct Huffman {
+    /// Number of codes of every length.
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code.
+    symbols: Vec<u16>,
+    /// Indexed by the next `FAST_BITS` bits: `symbol << 4 | length`, zero if the code
+    /// is longer.
+    fast: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> InflateResult<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // more codes of a length than there are left is not a prefix code, fewer is
+        // allowed for e.g. a single distance code
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..MAX_BITS + 1 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        let mut fast = vec![0u16; 1 << FAST_BITS];
+        let mut code = 0u32;
+        let mut index = 0;
+        for len in 1..FAST_BITS + 1 {
+            for _ in 0..counts[len as usize] {
+                // codes are stored starting with their most significant bit
+                let reversed = code.reverse_bits() >> (32 - len);
+                let entry = symbols[index] << 4 | len as u16;
+                let mut i = reversed;
+                while i < 1 << FAST_BITS {
+                    fast[i as usize] = entry;
+                    i += 1 << len;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+
+        Ok(Huffman {
+            counts: counts,
+            symbols: symbols,
+            fast: fast,
+        })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> InflateResult<u16> {
+        let entry = self.fast[bits.peek(FAST_BITS) as usize];
+        if entry != 0 {
+            try!(bits.consume((entry & 0xf) as u32));
+            return Ok(entry >> 4);
+        }
+
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..MAX_BITS + 1 {
+            code |= try!(bits.bits(1)) as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::InvalidSymbol)
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+}
+
+fn dynamic_codes(bits: &mut Bits) -> InflateResult<(Huffman, Huffman)> {
+    let literals = try!(bits.bits(5)) as usize + 257;
+    let distances = try!(bits.bits(5)) as usize + 1;
+    let code_lengths = try!(bits.bits(4)) as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = try!(bits.bits(3)) as u8;
+    }
+    let code_length_code = try!(Huffman::new(&lengths));
+
+    let mut lengths = vec![0u8; literals + distances];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = try!(code_length_code.decode(bits));
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 if i > 0 => (lengths[i - 1], 3 + try!(bits.bits(2)) as usize),
+            17 => (0, 3 + try!(bits.bits(3)) as usize),
+            18 => (0, 11 + try!(bits.bits(7)) as usize),
+            _ => return Err(InflateError::InvalidCodeLengths),
+        };
+        if i + repeat > lengths.len() {
+            return Err(InflateError::InvalidCodeLengths);
+        }
+        for length in &mut lengths[i..i + repeat] {
+            *length = value;
+        }
+        i += repeat;
+    }
+
+    // without an end of block code the block never ends
+    if lengths[256] == 0 {
+        return Err(InflateError::InvalidCodeLengths);
+    }
+    Ok((try!(Huffman::new(&lengths[..literals])), try!(Huffman::new(&lengths[literals..]))))
+}
+
+fn inflate_block(bits: &mut Bits,
+                 literals: &Huffman,
+                 distances: &Huffman,
+                 out: &mut Vec<u8>,
+                 limit: usize)
+                 -> InflateResult<()> {
+    loop {
+        let symbol = try!(literals.decode(bits)) as usize;
+        if symbol < 256 {
+            if out.len() >= limit {
+                return Err(InflateError::OutputTooLarge);
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(InflateError::InvalidSymbol);
+        }
+        let length = LENGTH_BASE[i] as usize + try!(bits.bits(LENGTH_EXTRA[i] as u32)) as usize;
+
+        let i = try!(distances.decode(bits)) as usize;
+        if i >= DIST_BASE.len() {
+            return Err(InflateError::InvalidSymbol);
+        }
+        let distance = DIST_BASE[i] as usize + try!(bits.bits(DIST_EXTRA[i] as u32)) as usize;
+        if distance > out.len() {
+            return Err(InflateError::DistanceTooFar);
+        }
+        if length > limit - out.len() {
+            return Err(InflateError::OutputTooLarge);
+        }
+
+        // the copy may overlap the bytes it produces
+        let start = out.len() - distance;
+        for k in 0..length {
+            let byte = out[start + k];
+            out.push(byte);
+        }
+    }
+}
+
+/// Decompresses raw deflate data into `out` and returns the number of bytes of
+/// `data` which were used, anything after the last block is left alone. Decoding
+/// stops with an error once `out` would grow past `limit` bytes.
+pub fn inflate(data: &[u8], out: &mut Vec<u8>, limit: usize) -> InflateResult<usize> {
+    let mut bits = Bits::new(data);
+    loop {
+        let last = try!(bits.bits(1)) == 1;
+        match try!(bits.bits(2)) {
+            0 => {
+                bits.align_to_byte();
+                let len = try!(bits.bits(16));
+                let complement = try!(bits.bits(16));
+                if len != !complement & 0xffff {
+                    return Err(InflateError::StoredLengthMismatch);
+                }
+                if len as usize > limit - out.len() {
+                    return Err(InflateError::OutputTooLarge);
+                }
+                for _ in 0..len {
+                    out.push(try!(bits.bits(8)) as u8);
+                }
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                try!(inflate_block(&mut bits, &literals, &distances, out, limit));
+            }
+            2 => {
+                let (literals, distances) = try!(dynamic_codes(&mut bits));
+                try!(inflate_block(&mut bits, &literals, &distances, out, limit));
+            }
+            _ => return Err(InflateError::InvalidBlockType),
+        }
+        if last {
+            bits.align_to_byte();
+            return Ok(bits.bytes_consumed());
+        }
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // the sums can't overflow within a chunk this size
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// Decompresses a zlib stream of at most `limit` bytes, the header and checksum around
+/// the deflate data are verified.
+pub fn decompress(data: &[u8], limit: usize) -> InflateResult<Vec<u8>> {
+    if data.len() < 2 {
+        return Err(InflateError::Truncated);
+    }
+    let (method, flags) = (data[0], data[1]);
+    if method & 0x0f != 8 || method >> 4 > 7 || (method as u16 * 256 + flags as u16) % 31 != 0 {
+        return Err(InflateError::InvalidHeader);
+    }
+    if flags & 0x20 != 0 {
+        return Err(InflateError::PresetDictionary);
+    }
+
+    let mut out = Vec::new();
+    let end = 2 + try!(inflate(&data[2..], &mut out, limit));
+    let checksum = match data.get(end..end + 4) {
+        Some(bytes) => bytes.iter().fold(0, |v, &b| v << 8 | b as u32),
+        None => return Err(InflateError::Truncated),
+    };
+    if checksum != adler32(&out) {
+        return Err(InflateError::ChecksumMismatch);
+    }
+    Ok(out)
+}
+
+/// zlib stream of `data` in stored blocks, i.e. valid but not compressed.
+pub fn compress_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    out.extend_from_slice(&[0x78, 0x01]);
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let len = block.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        out.extend_from_slice(block);
+    }
+
+    let checksum = adler32(data);
+    out.extend_from_slice(&[(checksum >> 24) as u8,
+                            (checksum >> 16) as u8,
+                            (checksum >> 8) as u8,
+                            checksum as u8]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, compress_stored, decompress, inflate, InflateError};
+
+    #[test]
+    fn test_stored() {
+        let data: Vec<u8> = (0..200000).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(decompress(&compress_stored(&data), data.len()).unwrap(), data);
+        assert_eq!(decompress(&compress_stored(&[]), 0).unwrap(), Vec::<u8>::new());
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_fixed_and_dynamic() {
+        // zlib.compress(b"hello hello hello hello"), a fixed block with a back reference
+        let fixed = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01,
+                     0x68, 0x03, 0x08, 0xb1];
+        assert_eq!(decompress(&fixed, 23).unwrap(), b"hello hello hello hello".to_vec());
+
+        // 300 pseudo random letters out of "abcd" compressed by zlib, short codes for the
+        // four letters make it a dynamic block
+        let dynamic = [0x78, 0xda, 0x25, 0x90, 0x8b, 0x11, 0xc0, 0x30, 0x08, 0x42, 0x67, 0x45,
+                       0xd8, 0x7f, 0x86, 0xf0, 0xc8, 0xa5, 0xbd, 0x58, 0xe5, 0x67, 0xed, 0x4b,
+                       0xe2, 0xa8, 0xb7, 0x74, 0xe9, 0xb1, 0xbd, 0xd6, 0xb9, 0x0d, 0x6a, 0xdf,
+                       0x89, 0x59, 0x8b, 0xd8, 0xad, 0x03, 0xe4, 0x46, 0x2c, 0xf3, 0x60, 0x58,
+                       0x7d, 0x5a, 0x65, 0xf3, 0x9c, 0x7a, 0x4e, 0xd5, 0xe8, 0x9c, 0xe6, 0xde,
+                       0x7d, 0x54, 0xb6, 0xc8, 0xc2, 0x41, 0xa3, 0x6d, 0x7c, 0x4a, 0xc2, 0x7d,
+                       0xf8, 0xa0, 0x7c, 0x08, 0xe0, 0x01, 0x0d, 0x88, 0x49, 0x3a, 0x71, 0x2f,
+                       0x4c, 0xd5, 0x8d, 0x0f, 0x89, 0xa9, 0x59, 0xe0, 0x87, 0x01, 0x50, 0x69,
+                       0x61, 0xd5, 0x16, 0xd0, 0xf5, 0x2a, 0x5e, 0x9e, 0x90, 0x10, 0x59, 0xb2,
+                       0xe4, 0x33, 0x64, 0x2d, 0x36, 0x32, 0x49, 0x41, 0x12, 0x82, 0x7f, 0x92,
+                       0xad, 0xf5, 0x93, 0x3e, 0xf5, 0x1f, 0x73, 0x8b];
+        let mut x = 1u32;
+        let expected: Vec<u8> = (0..300)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fffffff;
+                b'a' + (x >> 16) as u8 % 4
+            })
+            .collect();
+        assert_eq!(decompress(&dynamic, 300).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_limit() {
+        // stored, literal and back reference output all count against the limit
+        let stored = compress_stored(&[0; 100]);
+        assert_eq!(decompress(&stored, 99), Err(InflateError::OutputTooLarge));
+        let fixed = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01,
+                     0x68, 0x03, 0x08, 0xb1];
+        assert_eq!(decompress(&fixed, 3), Err(InflateError::OutputTooLarge));
+        assert_eq!(decompress(&fixed, 22), Err(InflateError::OutputTooLarge));
+        assert_eq!(decompress(&fixed, 1000).unwrap().len(), 23);
+    }
+
+    #[test]
+    fn test_errors() {
+        let stored = compress_stored(b"abc");
+        let mut bad = stored.clone();
+        bad[0] = 0x79;
+        assert_eq!(decompress(&bad, 3), Err(InflateError::InvalidHeader));
+        let mut bad = stored.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert_eq!(decompress(&bad, 3), Err(InflateError::ChecksumMismatch));
+        assert_eq!(decompress(&stored[..stored.len() - 5], 3), Err(InflateError::Truncated));
+
+        let mut out = Vec::new();
+        assert_eq!(inflate(&[0x07], &mut out, 16), Err(InflateError::InvalidBlockType));
+        assert_eq!(inflate(&[0x01, 0x03, 0x00, 0xfd, 0xff], &mut out, 16),
+                   Err(InflateError::StoredLengthMismatch));
+        // a fixed block with a back reference of distance 1 into empty output
+        assert_eq!(inflate(&[0x03, 0x02], &mut out, 16), Err(InflateError::DistanceTooFar));
+    }
+}